@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use anatase::{VM, program::{Program, Constant}};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{program, numeric_loop};
+
+
+const RUNS : usize = 10;
+
+
+///
+/// Compares `VM::run`, which runs the pre-decoded instructions,
+/// with stepping through the bytecode one `VM::execute` at a time
+/// like the debugger and the profilers do, and a `call` that copies
+/// its arguments with a `callw` that doesn't
+///
+/// run with `cargo bench -p anatase`, `--features jit` also
+/// compares the pre-decoded instructions with native code
+///
+fn main() {
+    let program = Arc::new(numeric_loop(10_000_000.0));
+
+    let (threaded, stepped) = measure_both(
+        || {
+            let mut vm = VM::<false>::new(program.clone()).unwrap();
+            vm.run().unwrap();
+        },
+        || {
+            let mut vm = VM::<false>::new(program.clone()).unwrap();
+            vm.run_with_fuel(u64::MAX).unwrap();
+        },
+    );
+
+    println!("numeric loop, best of {RUNS}");
+    println!("  pre-decoded {:>10.3} ms", threaded.as_secs_f64() * 1000.0);
+    println!("  stepped     {:>10.3} ms", stepped.as_secs_f64() * 1000.0);
+    println!("  speedup     {:>10.2}x", stepped.as_secs_f64() / threaded.as_secs_f64());
+
+
+    let copied = Arc::new(call_loop(1_000_000, false));
+    let windowed = Arc::new(call_loop(1_000_000, true));
+
+    let copied = measure(|| {
+        let mut vm = VM::<false>::new(copied.clone()).unwrap();
+        vm.run().unwrap();
+    });
+
+    let windowed = measure(|| {
+        let mut vm = VM::<false>::new(windowed.clone()).unwrap();
+        vm.run().unwrap();
+    });
+
+    println!("calls, best of {RUNS}");
+    println!("  call        {:>10.3} ms", copied.as_secs_f64() * 1000.0);
+    println!("  callw       {:>10.3} ms", windowed.as_secs_f64() * 1000.0);
+    println!("  speedup     {:>10.2}x", copied.as_secs_f64() / windowed.as_secs_f64());
+
+
+    #[cfg(feature = "jit")]
+    {
+        let compiled = measure(|| {
+            let mut vm = VM::<false>::new(program.clone()).unwrap();
+            vm.set_jit_threshold(0);
+            vm.run().unwrap();
+        });
+
+        println!("jit, best of {RUNS}");
+        println!("  pre-decoded {:>10.3} ms", threaded.as_secs_f64() * 1000.0);
+        println!("  compiled    {:>10.3} ms", compiled.as_secs_f64() * 1000.0);
+        println!("  speedup     {:>10.2}x", threaded.as_secs_f64() / compiled.as_secs_f64());
+    }
+}
+
+
+fn measure(f: impl Fn()) -> Duration {
+    (0..RUNS).map(|_| time(&f)).min().unwrap()
+}
+
+
+///
+/// Like `measure` for two functions but alternates between
+/// them so both see the same load on the machine
+///
+fn measure_both(a: impl Fn(), b: impl Fn()) -> (Duration, Duration) {
+    (0..RUNS).fold((Duration::MAX, Duration::MAX), |(x, y), _| {
+        (x.min(time(&a)), y.min(time(&b)))
+    })
+}
+
+
+fn time(f: &impl Fn()) -> Duration {
+    let timer = Instant::now();
+    f();
+    timer.elapsed()
+}
+
+
+///
+/// Calls a function that adds its two arguments `n` times,
+/// with a `callw` if `window` is set and a `call` otherwise
+///
+fn call_loop(n: i64, window: bool) -> Program {
+    let call : &[u8] = match window {
+        true => &[52, 2, 0, 0, 0, 0, 2],       // callw @2 add @3 @4
+        false => &[50, 2, 0, 0, 0, 0, 2, 3, 4], // call @2 add @3 @4
+    };
+
+    let end = 42 + call.len() as u32;
+    let add = end + 3;
+
+    let mut bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,    // call @0 main, ret
+
+        // main
+        7, 4,                       // push 4
+        3, 1, 0, 0,                 // set @1 n
+        3, 0, 1, 0,                 // set @0 0
+
+        // $loop (18)
+        3, 3, 2, 0,                 // set @3 1
+        3, 4, 2, 0,                 // set @4 1
+    ];
+
+    bytecode.extend_from_slice(call);
+    bytecode[28..32].copy_from_slice(&add.to_le_bytes());
+
+    bytecode.extend_from_slice(&[162, 1, 1, 2, 0]); // subik @1 @1 1
+    bytecode.extend_from_slice(&[14, 0, 1, 18, 0, 0, 0]); // jlti @0 @1 $loop $end
+    bytecode.extend_from_slice(&end.to_le_bytes());
+
+    bytecode.extend_from_slice(&[
+        // $end
+        8, 5,                       // pop 5
+        0,                          // ret
+
+        // add
+        100, 0, 1, 2,               // addi @0 @1 @2
+        8, 1,                       // pop 1
+        0,                          // ret
+    ]);
+
+    let constants = vec![Constant::Int(n), Constant::Int(0), Constant::Int(1)];
+    program(bytecode, constants, &[(8, 0, "main"), (add, 2, "add")])
+}
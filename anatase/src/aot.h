@@ -0,0 +1,245 @@
+/*
+ * The runtime of the programs `anatase::aot` translates to C
+ *
+ * A value is a tag and its bits like `Data` in the vm, and the
+ * registers of every frame live in `an_stack` the same way they
+ * do in `Stack` so a function's registers are `r[0]..` from the
+ * bottom of its frame up.
+ */
+
+#ifndef ANATASE_H
+#define ANATASE_H
+
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+
+#define AN_UNINIT 0
+#define AN_I64    1
+#define AN_U64    2
+#define AN_F64    3
+#define AN_BOOL   4
+#define AN_STR    5
+#define AN_ARR    6
+
+
+/* the default limits of the vm */
+#ifndef AN_STACK_SLOTS
+#define AN_STACK_SLOTS (1000000 / sizeof(Data))
+#endif
+
+#ifndef AN_CALL_DEPTH
+#define AN_CALL_DEPTH 10000
+#endif
+
+
+typedef struct {
+    uint64_t tag;
+    union {
+        int64_t i64;
+        uint64_t u64;
+        double f64;
+        bool b;
+    } as;
+} Data;
+
+
+static Data an_stack[AN_STACK_SLOTS];
+/* one past the last register in use, like `Stack::top` */
+static Data *an_top = an_stack;
+/* the amount of calls that haven't returned */
+static size_t an_depth;
+
+/* what a function returns once it's done, anything
+ * else is the offset of the function it tail calls */
+#define AN_RETURN UINT32_MAX
+
+
+static inline Data an_i64(int64_t v)  { Data d = { AN_I64, { 0 } }; d.as.i64 = v; return d; }
+static inline Data an_u64(uint64_t v) { Data d = { AN_U64, { 0 } }; d.as.u64 = v; return d; }
+static inline Data an_f64(double v)   { Data d = { AN_F64, { 0 } }; d.as.f64 = v; return d; }
+static inline Data an_bool(bool v)    { Data d = { AN_BOOL, { 0 } }; d.as.b = v; return d; }
+
+static inline double an_f64_bits(uint64_t bits) { double v; memcpy(&v, &bits, sizeof v); return v; }
+
+
+static inline bool an_can_push(size_t amount) {
+    return (size_t)(an_top - an_stack) + amount < AN_STACK_SLOTS;
+}
+
+
+static const char *an_tag_name(uint64_t tag) {
+    switch (tag) {
+    case AN_UNINIT: return "uninit";
+    case AN_I64:    return "int";
+    case AN_U64:    return "uint";
+    case AN_F64:    return "float";
+    case AN_BOOL:   return "bool";
+    case AN_STR:    return "str";
+    case AN_ARR:    return "array";
+    default:        return "unknown";
+    }
+}
+
+
+/* stops the program the way `VmError` is reported */
+static inline void an_error(const char *message, uint32_t offset, uint32_t function) {
+    fprintf(stderr, "error: %s at offset %u in function %u\n", message, offset, function);
+    exit(1);
+}
+
+
+static inline void an_type_mismatch(uint64_t expected, uint64_t found, uint32_t offset, uint32_t function) {
+    fprintf(stderr, "error: type mismatch, expected %s found %s at offset %u in function %u\n",
+        an_tag_name(expected), an_tag_name(found), offset, function);
+    exit(1);
+}
+
+
+#define AN_EXPECT(value, expected, offset, function) \
+    if ((value).tag != (expected)) an_type_mismatch((expected), (value).tag, (offset), (function))
+
+
+/*
+ * integer arithmetic wraps like the plain opcodes of the vm, a shift
+ * by an amount outside 0..64 shifts every bit out and the one division
+ * that overflows, `INT64_MIN / -1`, wraps as well
+ */
+static inline int64_t an_addi(int64_t x, int64_t y) { return (int64_t)((uint64_t)x + (uint64_t)y); }
+static inline int64_t an_subi(int64_t x, int64_t y) { return (int64_t)((uint64_t)x - (uint64_t)y); }
+static inline int64_t an_muli(int64_t x, int64_t y) { return (int64_t)((uint64_t)x * (uint64_t)y); }
+static inline int64_t an_divi(int64_t x, int64_t y) { return y == -1 ? an_subi(0, x) : x / y; }
+static inline int64_t an_remi(int64_t x, int64_t y) { return y == -1 ? 0 : x % y; }
+static inline int64_t an_lsi(int64_t x, int64_t y)  { return (uint64_t)y > 63 ? 0 : (int64_t)((uint64_t)x << y); }
+static inline int64_t an_rsi(int64_t x, int64_t y)  { return (uint64_t)y > 63 ? (x < 0 ? -1 : 0) : x >> y; }
+static inline uint64_t an_lsu(uint64_t x, uint64_t y) { return y > 63 ? 0 : x << y; }
+static inline uint64_t an_rsu(uint64_t x, uint64_t y) { return y > 63 ? 0 : x >> y; }
+
+
+/* whether the checked opcodes trap */
+static inline bool an_addi_overflows(int64_t x, int64_t y) {
+    return y > 0 ? x > INT64_MAX - y : x < INT64_MIN - y;
+}
+
+static inline bool an_subi_overflows(int64_t x, int64_t y) {
+    return y < 0 ? x > INT64_MAX + y : x < INT64_MIN + y;
+}
+
+static inline bool an_muli_overflows(int64_t x, int64_t y) {
+    if (x == 0 || y == 0) return false;
+    if (x == -1) return y == INT64_MIN;
+    if (y == -1) return x == INT64_MIN;
+    return an_muli(x, y) / y != x;
+}
+
+static inline bool an_lsi_overflows(int64_t x, int64_t y) {
+    return (uint64_t)y > 63 || an_rsi(an_lsi(x, y), y) != x;
+}
+
+static inline bool an_lsu_overflows(uint64_t x, uint64_t y) {
+    return y > 63 || an_rsu(an_lsu(x, y), y) != x;
+}
+
+
+/* the saturating opcodes clamp to the limit the result overflowed past */
+static inline int64_t an_addi_s(int64_t x, int64_t y) {
+    return an_addi_overflows(x, y) ? (y > 0 ? INT64_MAX : INT64_MIN) : x + y;
+}
+
+static inline int64_t an_subi_s(int64_t x, int64_t y) {
+    return an_subi_overflows(x, y) ? (y < 0 ? INT64_MAX : INT64_MIN) : x - y;
+}
+
+static inline int64_t an_muli_s(int64_t x, int64_t y) {
+    return an_muli_overflows(x, y) ? ((x < 0) == (y < 0) ? INT64_MAX : INT64_MIN) : x * y;
+}
+
+static inline int64_t an_lsi_s(int64_t x, int64_t y) {
+    if (x == 0) return 0;
+    return an_lsi_overflows(x, y) ? (x < 0 ? INT64_MIN : INT64_MAX) : an_lsi(x, y);
+}
+
+static inline uint64_t an_lsu_s(uint64_t x, uint64_t y) {
+    if (x == 0) return 0;
+    return an_lsu_overflows(x, y) ? UINT64_MAX : an_lsu(x, y);
+}
+
+
+/* `as i64`, which saturates and turns NaN into 0 */
+static inline int64_t an_f64_to_i64(double v) {
+    if (isnan(v)) return 0;
+    if (v <= -9223372036854775808.0) return INT64_MIN;
+    if (v >= 9223372036854775808.0) return INT64_MAX;
+    return (int64_t)v;
+}
+
+
+/* a float the way `{:?}` formats it, the shortest digits that read back the same */
+static void an_write_f64(FILE *out, double v) {
+    if (isnan(v)) { fputs("NaN", out); return; }
+    if (isinf(v)) { fputs(v < 0 ? "-inf" : "inf", out); return; }
+
+    char buf[32];
+    for (int precision = 0; precision <= 16; precision++) {
+        snprintf(buf, sizeof buf, "%.*e", precision, v);
+        if (strtod(buf, NULL) == v) break;
+    }
+
+    /* split "-d.ddde+x" into its sign, digits and exponent */
+    const char *p = buf;
+    if (*p == '-') { fputc('-', out); p++; }
+
+    char digits[24];
+    int len = 0;
+    for (; *p != 'e'; p++) {
+        if (*p != '.') digits[len++] = *p;
+    }
+    digits[len] = 0;
+
+    int exponent = atoi(p + 1);
+    double magnitude = fabs(v);
+
+    if (magnitude != 0 && (magnitude < 1e-4 || magnitude >= 1e16)) {
+        fputc(digits[0], out);
+        if (len > 1) fprintf(out, ".%s", digits + 1);
+        fprintf(out, "e%d", exponent);
+        return;
+    }
+
+    if (exponent < 0) {
+        fputs("0.", out);
+        for (int i = 0; i < -exponent - 1; i++) fputc('0', out);
+        fputs(digits, out);
+        return;
+    }
+
+    for (int i = 0; i <= exponent; i++) fputc(i < len ? digits[i] : '0', out);
+    fputc('.', out);
+    fputs(exponent + 1 < len ? digits + exponent + 1 : "0", out);
+}
+
+
+/* a value the way `VM::format` does */
+static void an_write(FILE *out, Data v) {
+    switch (v.tag) {
+    case AN_I64:  fprintf(out, "int %lld", (long long)v.as.i64); break;
+    case AN_U64:  fprintf(out, "uint %llu", (unsigned long long)v.as.u64); break;
+    case AN_F64:  fputs("float ", out); an_write_f64(out, v.as.f64); break;
+    case AN_BOOL: fprintf(out, "bool %s", v.as.b ? "true" : "false"); break;
+    default:      fputs(an_tag_name(v.tag), out); break;
+    }
+}
+
+
+static inline void an_print(Data v) {
+    fputs("print: ", stdout);
+    an_write(stdout, v);
+    fputc('\n', stdout);
+}
+
+
+#endif
@@ -0,0 +1,520 @@
+use std::fmt::{Display, Write};
+
+use crate::{bytecode, decoder::{self, Instruction, Value}, program::{Program, Constant}, verifier::VerifyError};
+
+
+/// The runtime every translated program includes as `anatase.h`
+pub const RUNTIME : &str = include_str!("aot.h");
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranslateError {
+    Verify(VerifyError),
+    /// The instruction needs the heap or the natives of a `VM`
+    Unsupported { offset: usize, mnemonic: &'static str },
+}
+
+
+///
+/// Translates `program` to a C program that runs without a `VM`
+///
+/// Every function becomes a C function with a `switch` over its
+/// blocks that works on the registers of its frame in `an_stack`,
+/// laid out like `Stack`, so calls, windows and tail calls behave
+/// exactly like they do in the `VM`. Tags are checked like `VM<true>`
+/// does and errors are reported like `VmError` is, after which the
+/// program exits. `main` runs the program from offset 0 and prints
+/// the result like the `anatase` binary does.
+///
+/// Only numbers and bools can be translated, strings, arrays and
+/// natives need a `VM` and are reported as `Unsupported`
+///
+/// The C file includes the runtime in `RUNTIME` as `anatase.h`
+///
+pub fn translate(program: &Program) -> Result<String, TranslateError> {
+    program.verification().map_err(|e| TranslateError::Verify(e.clone()))?;
+
+    let bytecode = program.bytecode();
+    let functions = program.functions();
+    let instructions = decoder::decode_all(bytecode).expect("verified bytecode decodes");
+
+    let mut starts : Vec<_> = functions.iter().map(|x| (x.offset, x.argc)).collect();
+    starts.sort();
+
+    // the bootstrap, it never returns to anything so it has no arguments to pop
+    if starts.first().map(|x| x.0) != Some(0) {
+        starts.insert(0, (0, 0));
+    }
+
+
+    let mut out = String::new();
+    let _ = writeln!(out, "#include \"anatase.h\"");
+    let _ = writeln!(out);
+    let _ = writeln!(out);
+
+    for &(start, _) in &starts {
+        let _ = writeln!(out, "static uint32_t an_f{start}(Data *r);");
+    }
+
+    let _ = writeln!(out, "static void an_run(uint32_t f, Data *r);");
+
+
+    for &(start, argc) in &starts {
+        let range = functions.range(start, bytecode.len());
+        let body : Vec<_> = instructions.iter()
+            .filter(|x| range.contains(&x.offset))
+            .collect();
+
+        let _ = writeln!(out);
+        let _ = writeln!(out);
+        let _ = writeln!(out, "// {}", functions.name_of(start));
+        let _ = writeln!(out, "static uint32_t an_f{start}(Data *r) {{");
+
+        function(&mut out, program, start, argc, &body)?;
+
+        let _ = writeln!(out, "}}");
+    }
+
+
+    // a function returns the function it tail calls so the frame
+    // is reused without growing the C stack, like `tailcall` does
+    let _ = writeln!(out);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "// runs `f` and every function it tail calls in the frame at `r`");
+    let _ = writeln!(out, "static void an_run(uint32_t f, Data *r) {{");
+    let _ = writeln!(out, "    for (;;) switch (f) {{");
+    let _ = writeln!(out, "    case AN_RETURN: return;");
+
+    for &(start, _) in &starts {
+        let _ = writeln!(out, "    case {start}: f = an_f{start}(r); break;");
+    }
+
+    let _ = writeln!(out, "    }}");
+    let _ = writeln!(out, "}}");
+
+
+    let _ = writeln!(out);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "int main(void) {{");
+    let _ = writeln!(out, "    an_run(0, an_stack);");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    fputs(\"result is \", stdout);");
+    let _ = writeln!(out, "    an_write(stdout, an_stack[0]);");
+    let _ = writeln!(out, "    fputc('\\n', stdout);");
+    let _ = writeln!(out, "    return 0;");
+    let _ = writeln!(out, "}}");
+
+    Ok(out)
+}
+
+
+///
+/// Writes the body of the function at `start`, every jump goes
+/// through the `switch` and the rest falls through to the next
+/// instruction like it does in the bytecode
+///
+fn function(out: &mut String, program: &Program, start: usize, argc: u8, body: &[&Instruction]) -> Result<(), TranslateError> {
+    let mut blocks = vec![start];
+    for instruction in body {
+        for operand in &instruction.operands {
+            if let Value::Block(target) = *operand {
+                blocks.push(target as usize);
+            }
+        }
+    }
+
+
+    let _ = writeln!(out, "    uint32_t block = {start};");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    for (;;) switch (block) {{");
+
+    let mut falls_through = false;
+
+    for instruction in body {
+        if blocks.contains(&instruction.offset) {
+            // a block the previous instruction runs into
+            if falls_through {
+                let _ = writeln!(out, "        /* fallthrough */");
+            }
+
+            let _ = writeln!(out, "    case {}:", instruction.offset);
+        }
+
+        let _ = writeln!(out, "        // {:>5} {instruction}", instruction.offset);
+        let code = translate_instruction(program, start, argc, instruction)?;
+        for line in code.lines() {
+            let _ = match line.is_empty() {
+                true => writeln!(out),
+                false => writeln!(out, "        {line}"),
+            };
+        }
+
+        falls_through = !matches!(
+            instruction.opcode,
+            | bytecode::RETURN | bytecode::TAILCALL | bytecode::JMP | bytecode::JIF | bytecode::JNIF
+            | bytecode::JLTI | bytecode::JLTF | bytecode::JLEI | bytecode::JLEF
+            | bytecode::JEQI | bytecode::JEQF | bytecode::JNEI | bytecode::JNEF
+        );
+    }
+
+    let _ = writeln!(out, "    }}");
+    Ok(())
+}
+
+
+/// The C code of `instruction` in the function at `function`
+fn translate_instruction(program: &Program, function: usize, argc: u8, instruction: &Instruction) -> Result<String, TranslateError> {
+    use bytecode::*;
+
+    let offset = instruction.offset;
+    let operands = &instruction.operands;
+
+    // where errors are reported
+    let at = format!("{offset}, {function}");
+
+    let reg = |index: usize| match operands[index] {
+        Value::Reg(v) | Value::U8(v) => v,
+        _ => unreachable!(),
+    };
+
+    let target = |index: usize| match operands[index] {
+        Value::Block(v) | Value::Function(v) => v,
+        _ => unreachable!(),
+    };
+
+    let args = || match operands.last() {
+        Some(Value::RegList(v)) => v.clone(),
+        _ => unreachable!(),
+    };
+
+    let unsupported = || TranslateError::Unsupported { offset, mnemonic: instruction.mnemonic };
+
+
+    let code = match instruction.opcode {
+        RETURN if argc == 0 => "return AN_RETURN;".to_string(),
+        RETURN => format!("an_top -= {argc};\nreturn AN_RETURN;"),
+
+        COPY => format!("r[{}] = r[{}];", reg(0), reg(1)),
+        SWAP => format!("{{ Data t = r[{0}]; r[{0}] = r[{1}]; r[{1}] = t; }}", reg(0), reg(1)),
+
+        SET => {
+            let Value::Constant(index) = operands[1] else { unreachable!() };
+            let value = constant(&program.constants()[index as usize]).ok_or_else(unsupported)?;
+            format!("r[{}] = {value};", reg(0))
+        },
+
+        PUSH => format!("if (!an_can_push({0})) an_error(\"stack overflow\", {at});\nan_top += {0};", reg(0)),
+        POP => format!("an_top -= {};", reg(0)),
+
+        PRINT => format!("an_print(r[{}]);", reg(0)),
+
+
+        JMP => format!("block = {};\ncontinue;", target(0)),
+
+        JIF | JNIF => {
+            let (yes, no) = match instruction.opcode {
+                JIF => (target(1), target(2)),
+                _ => (target(2), target(1)),
+            };
+
+            let cond = reg(0);
+            format!("AN_EXPECT(r[{cond}], AN_BOOL, {at});\nblock = r[{cond}].as.b ? {yes} : {no};\ncontinue;")
+        },
+
+        IJIF | IJNIF => {
+            let not = if instruction.opcode == IJNIF { "!" } else { "" };
+
+            let cond = reg(0);
+            format!("AN_EXPECT(r[{cond}], AN_BOOL, {at});\nif ({not}r[{cond}].as.b) {{ block = {}; continue; }}", target(1))
+        },
+
+        JLTI | JLTF | JLEI | JLEF | JEQI | JEQF | JNEI | JNEF => {
+            let (kind, op) = match instruction.opcode {
+                JLTI => (Kind::Int, "<"),
+                JLTF => (Kind::Float, "<"),
+                JLEI => (Kind::Int, "<="),
+                JLEF => (Kind::Float, "<="),
+                JEQI => (Kind::Int, "=="),
+                JEQF => (Kind::Float, "=="),
+                JNEI => (Kind::Int, "!="),
+                _    => (Kind::Float, "!="),
+            };
+
+            let (tag, field, _) = kind.c();
+            let (lhs, rhs) = (reg(0), reg(1));
+            format!(
+                "AN_EXPECT(r[{lhs}], {tag}, {at});\nAN_EXPECT(r[{rhs}], {tag}, {at});\n\
+                block = r[{lhs}].as.{field} {op} r[{rhs}].as.{field} ? {} : {};\ncontinue;",
+                target(2), target(3),
+            )
+        },
+
+
+        // the callee's `ret` pops its arguments and
+        // leaves its result in the first register
+        CALL => {
+            let args = args();
+            let mut code = format!(
+                "if (an_depth >= AN_CALL_DEPTH || !an_can_push({0})) an_error(\"stack overflow\", {at});\n\
+                {{\n    Data *callee = an_top;\n    an_top += {0};\n",
+                args.len() + 1,
+            );
+
+            for (index, arg) in args.iter().enumerate() {
+                let _ = writeln!(code, "    callee[{}] = r[{arg}];", index + 1);
+            }
+
+            let _ = write!(code, "    an_depth++;\n    an_run({}, callee);\n    an_depth--;\n    r[{}] = callee[0];\n}}", target(1), reg(0));
+            code
+        },
+
+
+        // the window already is the end of the frame
+        CALLW => {
+            let (dst, argc) = (reg(0), reg(2));
+            format!(
+                "if (an_depth >= AN_CALL_DEPTH) an_error(\"stack overflow\", {at});\n\
+                an_depth++;\nan_run({}, r + {dst});\nan_depth--;\nan_top = r + {dst} + {argc} + 1;",
+                target(1),
+            )
+        },
+
+
+        // the arguments are copied above the frame before moving them
+        // down, a call to itself is a loop and a call to another function
+        // returns it to the `an_run` that called this one, which runs it
+        // in the same frame so neither grows the C stack or `an_depth`
+        TAILCALL => {
+            let args = args();
+            let mut code = format!("if (!an_can_push({})) an_error(\"stack overflow\", {at});\n", args.len() + 1);
+
+            for (index, arg) in args.iter().enumerate() {
+                let _ = writeln!(code, "an_top[{index}] = r[{arg}];");
+            }
+
+            let goto = target(0);
+            let _ = writeln!(code, "memmove(r + 1, an_top, {} * sizeof(Data));\nan_top = r + {};", args.len(), args.len() + 1);
+
+            match goto as usize == function {
+                true => { let _ = write!(code, "block = {goto};\ncontinue;"); },
+                false => { let _ = write!(code, "return {goto};"); },
+            }
+
+            code
+        },
+
+
+        | CASTIU | CASTIF | CASTUI | CASTUF | CASTFI | CASTFU
+        | NOTI | NOTU | NOTB | NEGI | NEGF => {
+            let (kind, result) = unary(instruction.opcode);
+
+            let (tag, field, ty) = kind.c();
+            let (dst, val) = (reg(0), reg(1));
+            format!("AN_EXPECT(r[{val}], {tag}, {at});\n{{\n    {ty} x = r[{val}].as.{field};\n    r[{dst}] = {result};\n}}")
+        },
+
+
+        opcode => {
+            let Some((kind, result, fails)) = binary(opcode)
+            else { return Err(unsupported()) };
+
+            let (tag, field, ty) = kind.c();
+            let (dst, lhs) = (reg(0), reg(1));
+
+            // the constant of an immediate is checked at runtime like it is in the vm
+            let rhs = match operands[2] {
+                Value::Reg(v) => format!("r[{v}]"),
+                Value::Constant(v) => constant(&program.constants()[v as usize]).ok_or_else(unsupported)?,
+                _ => unreachable!(),
+            };
+
+            let mut code = format!(
+                "{{\n    Data lhs = r[{lhs}], rhs = {rhs};\n    AN_EXPECT(lhs, {tag}, {at});\n    AN_EXPECT(rhs, {tag}, {at});\n\n    \
+                {ty} x = lhs.as.{field}, y = rhs.as.{field};\n",
+            );
+
+            if let Some((condition, message)) = fails {
+                let _ = writeln!(code, "    if ({condition}) an_error(\"{message}\", {at});");
+            }
+
+            let _ = write!(code, "    r[{dst}] = {result};\n}}");
+            code
+        },
+    };
+
+    Ok(code)
+}
+
+
+/// A constant as a C expression, `None` for strings
+fn constant(constant: &Constant) -> Option<String> {
+    Some(match constant {
+        Constant::Int(i64::MIN) => "an_i64(INT64_MIN)".to_string(),
+        Constant::Int(v) => format!("an_i64(INT64_C({v}))"),
+        // the bits so it's the exact same float
+        Constant::Float(v) => format!("an_f64(an_f64_bits(UINT64_C({:#x}))) /* {v:?} */", v.to_bits()),
+        Constant::Bool(v) => format!("an_bool({v})"),
+        Constant::Str(_) => return None,
+    })
+}
+
+
+/// The type of the operands of an arithmetic or comparison instruction
+#[derive(Clone, Copy)]
+enum Kind {
+    Int,
+    Uint,
+    Float,
+    Bool,
+}
+
+
+impl Kind {
+    /// The tag, the field of the value and the C type
+    fn c(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Kind::Int => ("AN_I64", "i64", "int64_t"),
+            Kind::Uint => ("AN_U64", "u64", "uint64_t"),
+            Kind::Float => ("AN_F64", "f64", "double"),
+            Kind::Bool => ("AN_BOOL", "b", "bool"),
+        }
+    }
+}
+
+
+///
+/// The type of the operand of an opcode with a single one and the C
+/// expression of its result from the operand `x`
+///
+fn unary(opcode: u8) -> (Kind, &'static str) {
+    use bytecode::*;
+    use Kind::*;
+
+    match opcode {
+        CASTIU => (Int, "an_u64((uint64_t)x)"),
+        CASTIF => (Int, "an_f64((double)x)"),
+        CASTUI => (Uint, "an_i64((int64_t)x)"),
+        CASTUF => (Uint, "an_f64((double)x)"),
+        CASTFI => (Float, "an_i64(an_f64_to_i64(x))"),
+        // the vm casts a float to an int for this one as well
+        CASTFU => (Float, "an_i64(an_f64_to_i64(x))"),
+
+        NOTI => (Int, "an_i64(~x)"),
+        NOTU => (Uint, "an_u64(~x)"),
+        NOTB => (Bool, "an_bool(!x)"),
+        NEGI => (Int, "an_i64(an_subi(0, x))"),
+        NEGF => (Float, "an_f64(-x)"),
+
+        _ => unreachable!("not a cast, not or neg opcode"),
+    }
+}
+
+
+// the C condition an opcode fails on and the message of its error
+type Fails = Option<(&'static str, &'static str)>;
+
+
+///
+/// The type of the operands of an arithmetic or comparison opcode, the
+/// C expression of its result from the operands `x` and `y` and what
+/// it fails on
+///
+fn binary(opcode: u8) -> Option<(Kind, &'static str, Fails)> {
+    use bytecode::*;
+    use Kind::*;
+
+    const ZERO : Fails = Some(("y == 0", "division by zero"));
+    let overflow = |condition| Some((condition, "integer overflow"));
+
+    Some(match opcode {
+        ADDI | ADDIK => (Int, "an_i64(an_addi(x, y))", None),
+        ADDU => (Uint, "an_u64(x + y)", None),
+        ADDF | ADDFK => (Float, "an_f64(x + y)", None),
+        SUBI | SUBIK => (Int, "an_i64(an_subi(x, y))", None),
+        SUBU => (Uint, "an_u64(x - y)", None),
+        SUBF | SUBFK => (Float, "an_f64(x - y)", None),
+        MULI | MULIK => (Int, "an_i64(an_muli(x, y))", None),
+        MULU => (Uint, "an_u64(x * y)", None),
+        MULF | MULFK => (Float, "an_f64(x * y)", None),
+        DIVI => (Int, "an_i64(an_divi(x, y))", ZERO),
+        DIVU => (Uint, "an_u64(x / y)", ZERO),
+        DIVF => (Float, "an_f64(x / y)", ZERO),
+        REMI => (Int, "an_i64(an_remi(x, y))", ZERO),
+        REMU => (Uint, "an_u64(x % y)", ZERO),
+        REMF => (Float, "an_f64(fmod(x, y))", ZERO),
+        LSI  => (Int, "an_i64(an_lsi(x, y))", None),
+        LSU  => (Uint, "an_u64(an_lsu(x, y))", None),
+        RSI  => (Int, "an_i64(an_rsi(x, y))", None),
+        RSU  => (Uint, "an_u64(an_rsu(x, y))", None),
+
+        ANDI => (Int, "an_i64(x & y)", None),
+        ANDU => (Uint, "an_u64(x & y)", None),
+        ORI  => (Int, "an_i64(x | y)", None),
+        ORU  => (Uint, "an_u64(x | y)", None),
+        XORI => (Int, "an_i64(x ^ y)", None),
+        XORU => (Uint, "an_u64(x ^ y)", None),
+        ANDB => (Bool, "an_bool(x && y)", None),
+        ORB  => (Bool, "an_bool(x || y)", None),
+
+        ADDIC => (Int, "an_i64(an_addi(x, y))", overflow("an_addi_overflows(x, y)")),
+        ADDUC => (Uint, "an_u64(x + y)", overflow("x > UINT64_MAX - y")),
+        SUBIC => (Int, "an_i64(an_subi(x, y))", overflow("an_subi_overflows(x, y)")),
+        SUBUC => (Uint, "an_u64(x - y)", overflow("x < y")),
+        MULIC => (Int, "an_i64(an_muli(x, y))", overflow("an_muli_overflows(x, y)")),
+        MULUC => (Uint, "an_u64(x * y)", overflow("x != 0 && y > UINT64_MAX / x")),
+        LSIC  => (Int, "an_i64(an_lsi(x, y))", overflow("an_lsi_overflows(x, y)")),
+        LSUC  => (Uint, "an_u64(an_lsu(x, y))", overflow("an_lsu_overflows(x, y)")),
+        RSIC  => (Int, "an_i64(an_rsi(x, y))", overflow("(uint64_t)y > 63")),
+        RSUC  => (Uint, "an_u64(an_rsu(x, y))", overflow("y > 63")),
+
+        LSIW  => (Int, "an_i64(an_lsi(x, y & 63))", None),
+        LSUW  => (Uint, "an_u64(x << (y & 63))", None),
+        RSIW  => (Int, "an_i64(an_rsi(x, y & 63))", None),
+        RSUW  => (Uint, "an_u64(x >> (y & 63))", None),
+
+        ADDIS => (Int, "an_i64(an_addi_s(x, y))", None),
+        ADDUS => (Uint, "an_u64(x > UINT64_MAX - y ? UINT64_MAX : x + y)", None),
+        SUBIS => (Int, "an_i64(an_subi_s(x, y))", None),
+        SUBUS => (Uint, "an_u64(x < y ? 0 : x - y)", None),
+        MULIS => (Int, "an_i64(an_muli_s(x, y))", None),
+        MULUS => (Uint, "an_u64(x != 0 && y > UINT64_MAX / x ? UINT64_MAX : x * y)", None),
+        LSIS  => (Int, "an_i64(an_lsi_s(x, y))", None),
+        LSUS  => (Uint, "an_u64(an_lsu_s(x, y))", None),
+        RSIS  => (Int, "an_i64(an_rsi(x, y))", None),
+        RSUS  => (Uint, "an_u64(an_rsu(x, y))", None),
+
+        LTI | LTIK => (Int, "an_bool(x < y)", None),
+        LTU => (Uint, "an_bool(x < y)", None),
+        LTF | LTFK => (Float, "an_bool(x < y)", None),
+        GTI | GTIK => (Int, "an_bool(x > y)", None),
+        GTU => (Uint, "an_bool(x > y)", None),
+        GTF | GTFK => (Float, "an_bool(x > y)", None),
+        LEI | LEIK => (Int, "an_bool(x <= y)", None),
+        LEU => (Uint, "an_bool(x <= y)", None),
+        LEF | LEFK => (Float, "an_bool(x <= y)", None),
+        GEI | GEIK => (Int, "an_bool(x >= y)", None),
+        GEU => (Uint, "an_bool(x >= y)", None),
+        GEF | GEFK => (Float, "an_bool(x >= y)", None),
+        EQI | EQIK => (Int, "an_bool(x == y)", None),
+        EQU => (Uint, "an_bool(x == y)", None),
+        EQF | EQFK => (Float, "an_bool(x == y)", None),
+        NEI | NEIK => (Int, "an_bool(x != y)", None),
+        NEU => (Uint, "an_bool(x != y)", None),
+        NEF | NEFK => (Float, "an_bool(x != y)", None),
+
+        _ => return None,
+    })
+}
+
+
+impl Display for TranslateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranslateError::Verify(e) => write!(f, "{e}"),
+            TranslateError::Unsupported { offset, mnemonic } => write!(f, "'{mnemonic}' at offset {offset} needs a vm and can't be translated"),
+        }
+    }
+}
+
+
+impl std::error::Error for TranslateError {}
@@ -0,0 +1,87 @@
+use std::fmt::Write;
+
+use colored::{Color, Colorize};
+
+use crate::{VM, symbols::FunctionTable, debug_info::DebugInfo};
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backtrace {
+    /// The innermost frame comes first
+    pub frames: Vec<Frame>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// The offset of the first instruction of the function
+    pub function: usize,
+    /// The offset of the executing instruction, or for the callers
+    /// the offset the frame will continue from once the call returns
+    pub offset: usize,
+    pub is_caller: bool,
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Walks `self.current` and `self.callstack` to collect the
+    /// active frames
+    ///
+    /// After `VM::run` returns an error `self.current` still points
+    /// at the faulting instruction so this can be called to find out
+    /// how the program got there
+    ///
+    pub fn backtrace(&self) -> Backtrace {
+        let mut frames = Vec::with_capacity(self.callstack.len() + 1);
+
+        frames.push(Frame {
+            function: self.current.function,
+            offset: self.current.ptr as usize - self.current.base as usize,
+            is_caller: false,
+        });
+
+        for code in self.callstack.iter().rev() {
+            frames.push(Frame {
+                function: code.function,
+                offset: code.ptr as usize - code.base as usize,
+                is_caller: true,
+            });
+        }
+
+        Backtrace { frames }
+    }
+}
+
+
+impl Backtrace {
+    ///
+    /// Renders the backtrace with the function names from `functions`
+    ///
+    /// If `debug_info` is provided every frame also shows the line
+    /// of the `.an` file it's executing
+    ///
+    pub fn render(&self, functions: &FunctionTable, debug_info: Option<&DebugInfo>) -> String {
+        let mut string = String::new();
+
+        let _ = writeln!(string, "{}", "backtrace:".bold());
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            // the caller frames are paused after their `call`
+            // instruction so look at the byte before it
+            let offset = match (frame.is_caller, debug_info) {
+                (true, Some(debug_info)) => debug_info.instruction_start(frame.offset.saturating_sub(1)).unwrap_or(frame.offset),
+                _ => frame.offset,
+            };
+
+            let _ = writeln!(string, "{:>4}: {} at offset {}", index, functions.name_of(frame.function).bold(), offset);
+
+            let colour = if frame.is_caller { Color::BrightYellow } else { Color::BrightRed };
+            if let Some(snippet) = debug_info.and_then(|x| x.highlight(offset, colour)) {
+                let _ = write!(string, "{snippet}");
+            }
+        }
+
+        string
+    }
+}
@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use anatase::{program::Program, aot};
+
+
+///
+/// Translates a program to C
+///
+/// usage: anatase-aot [program.anb] [out.c]
+///
+/// The runtime the C file includes is written next to it as `anatase.h`,
+/// build the two with any C99 compiler, `cc -O2 out.c -lm`
+///
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| "test.anb".to_string());
+    let out = args.next().unwrap_or_else(|| "test.c".to_string());
+
+    let data = match std::fs::read(&path) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: can't read '{path}': {e}");
+            std::process::exit(1);
+        },
+    };
+
+    let program = match Program::from_bytes(&data) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        },
+    };
+
+    let source = match aot::translate(&program) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        },
+    };
+
+    let header = Path::new(&out).with_file_name("anatase.h");
+    for (path, contents) in [(Path::new(&out), source.as_str()), (&header, aot::RUNTIME)] {
+        if let Err(e) = std::fs::write(path, contents) {
+            eprintln!("error: can't write '{}': {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::{io::{stdin, stdout, Write}, sync::Arc};
+
+use anatase::{VM, program::Program, debugger::{Breakpoint, Stop}, decoder::{self, Value}, errors::VmError};
+use colored::{Color, Colorize};
+
+
+const HELP : &str = "\
+commands:
+    break <function>$<block> | break <offset>    set a breakpoint
+    delete <function>$<block> | delete <offset>  clear a breakpoint
+    step                                         execute one instruction
+    next                                         execute one instruction, stepping over calls
+    finish                                       run until the current function returns
+    continue                                     run until the next breakpoint
+    regs                                         show the registers of the current frame
+    print @<register>                            show a register
+    bt                                           show the backtrace
+    disasm                                       disassemble the current function
+    quit                                         exit the debugger";
+
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "test.anb".to_string());
+
+    let data = match std::fs::read(&path) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: can't read '{path}': {e}");
+            std::process::exit(1);
+        },
+    };
+
+    let program = match Program::from_bytes(&data) {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        },
+    };
+
+    let mut vm = VM::<true>::new(program);
+    if let Err(e) = vm.link() {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+
+    println!("loaded '{path}', type 'help' for the list of commands");
+
+    let mut debugger = Debugger { vm, finished: false };
+    let mut last = String::new();
+    let mut line = String::new();
+
+    loop {
+        print!("{} ", "(anatase)".bold());
+        let _ = stdout().flush();
+
+        line.clear();
+        match stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+
+        // an empty line repeats the last command
+        let command = match line.trim() {
+            "" => last.clone(),
+            v => v.to_string(),
+        };
+
+        if !debugger.command(&command) {
+            break
+        }
+
+        last = command;
+    }
+}
+
+
+struct Debugger {
+    vm: VM<true>,
+    finished: bool,
+}
+
+
+impl Debugger {
+    /// Runs `command` and returns false if the debugger should exit
+    fn command(&mut self, command: &str) -> bool {
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        let argument = argument.trim();
+
+        match name {
+            "" => (),
+
+            "break" | "b" => match self.breakpoint(argument) {
+                Some(v) => match self.vm.set_breakpoint(v) {
+                    Ok(offset) => println!("breakpoint set at offset {offset}"),
+                    Err(e) => println!("error: {e}"),
+                },
+                None => println!("error: expected '<function>$<block>' or an offset"),
+            },
+
+            "delete" | "d" => match self.breakpoint(argument) {
+                Some(v) => match self.vm.clear_breakpoint(v) {
+                    Ok(true) => println!("breakpoint cleared"),
+                    Ok(false) => println!("there's no breakpoint there"),
+                    Err(e) => println!("error: {e}"),
+                },
+                None => println!("error: expected '<function>$<block>' or an offset"),
+            },
+
+            "step" | "s" => self.execute(VM::step),
+            "next" | "n" => self.execute(VM::step_over),
+            "finish" | "f" => self.execute(VM::step_out),
+            "continue" | "c" => self.execute(VM::resume),
+
+            "regs" => {
+                for (index, value) in self.vm.registers().iter().enumerate() {
+                    println!("{:>4}: {}", format!("@{index}"), self.vm.format(*value));
+                }
+            },
+
+            "print" | "p" => {
+                let register = argument.strip_prefix('@').and_then(|x| x.parse::<usize>().ok());
+                match register.map(|x| self.vm.registers().get(x)) {
+                    Some(Some(v)) => println!("{argument} = {}", self.vm.format(*v)),
+                    Some(None) => println!("error: {argument} is outside of the frame"),
+                    None => println!("error: expected a register like '@3'"),
+                }
+            },
+
+            "bt" => {
+                let program = self.vm.program();
+                print!("{}", self.vm.backtrace().render(program.functions(), program.debug_info()));
+            },
+
+            "disasm" => self.disassemble(),
+            "help" | "h" => println!("{HELP}"),
+            "quit" | "q" => return false,
+
+            _ => println!("error: unknown command '{name}', type 'help' for the list of commands"),
+        }
+
+        true
+    }
+
+
+    fn breakpoint<'a>(&self, argument: &'a str) -> Option<Breakpoint<'a>> {
+        if let Some((function, block)) = argument.split_once('$') {
+            return Some(Breakpoint::Block { function, block })
+        }
+
+        argument.parse().ok().map(Breakpoint::Offset)
+    }
+
+
+    fn execute(&mut self, function: fn(&mut VM<true>) -> Result<Stop, VmError>) {
+        if self.finished {
+            println!("the program isn't running");
+            return
+        }
+
+        match function(&mut self.vm) {
+            Ok(Stop::Finished) => {
+                self.finished = true;
+                println!("the program finished, result is {}", self.vm.format(self.vm.stack.reg(0)));
+            },
+
+            Ok(Stop::Breakpoint(offset)) => {
+                println!("hit the breakpoint at offset {offset}");
+                self.show_location();
+            },
+
+            Ok(Stop::Step) => self.show_location(),
+
+            Err(e) => {
+                self.finished = true;
+
+                let program = self.vm.program();
+                println!("error: {e}");
+                print!("{}", self.vm.backtrace().render(program.functions(), program.debug_info()));
+            },
+        }
+    }
+
+
+    /// Shows the source line of the next instruction, or the
+    /// instruction itself if there's no debug info
+    fn show_location(&self) {
+        let program = self.vm.program();
+        let offset = self.vm.offset();
+
+        let name = program.functions().name_of(self.vm.location().function);
+        println!("{} at offset {offset}", name.bold());
+
+        let snippet = program.debug_info().and_then(|x| x.highlight(offset, Color::BrightGreen));
+        match snippet {
+            Some(v) => print!("{v}"),
+            None => if let Ok(instruction) = decoder::decode(program.bytecode(), offset) {
+                println!("  {instruction}");
+            },
+        }
+    }
+
+
+    fn disassemble(&self) {
+        let program = self.vm.program();
+        let functions = program.functions();
+        let function = self.vm.location().function;
+        let range = functions.range(function, program.bytecode().len());
+
+        let instructions = match decoder::decode_all(program.bytecode()) {
+            Ok(v) => v,
+            Err(e) => return println!("error: {e}"),
+        };
+
+        println!("{}:", functions.name_of(function).bold());
+
+        let breakpoints : Vec<_> = self.vm.breakpoints().collect();
+        for instruction in instructions.iter().filter(|x| range.contains(&x.offset)) {
+            if let Some(block) = program.debug_info().and_then(|x| x.block_at(instruction.offset)) {
+                println!("  ${block}");
+            }
+
+            let marker = if instruction.offset == self.vm.offset() { "=>" } else { "  " };
+            let breakpoint = if breakpoints.contains(&instruction.offset) { "*" } else { " " };
+
+            let mut line = format!("{marker}{breakpoint}{:>6}: {instruction}", instruction.offset);
+            for operand in &instruction.operands {
+                if let Value::Function(v) = operand {
+                    line.push_str(&format!("  -- {}", functions.name_of(*v as usize)));
+                }
+            }
+
+            match marker {
+                "=>" => println!("{}", line.bright_green()),
+                _ => println!("{line}"),
+            }
+        }
+    }
+}
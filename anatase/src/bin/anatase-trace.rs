@@ -0,0 +1,59 @@
+use std::fmt::Write;
+
+use anatase::{program::Program, trace};
+
+
+///
+/// Prints a trace written with `ANATASE_TRACE`
+///
+/// usage: anatase-trace <trace> [program.anb]
+///
+/// The program is only used to show the names of the functions
+///
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next()
+    else {
+        eprintln!("usage: anatase-trace <trace> [program.anb]");
+        std::process::exit(1);
+    };
+
+    let records = std::fs::read(&path).ok().and_then(|x| trace::parse(&x));
+    let Some(records) = records
+    else {
+        eprintln!("error: '{path}' isn't a valid trace");
+        std::process::exit(1);
+    };
+
+    let program = args.next().map(|x| {
+        let program = std::fs::read(&x).ok().and_then(|x| Program::from_bytes(&x).ok());
+        program.unwrap_or_else(|| {
+            eprintln!("error: '{x}' isn't a valid program");
+            std::process::exit(1);
+        })
+    });
+
+
+    let mut line = String::new();
+    for record in records {
+        line.clear();
+
+        let _ = write!(line, "{:>6} ", record.offset);
+        match &program {
+            Some(v) => { let _ = write!(line, "{:<16} ", v.functions().name_of(record.function)); },
+            None => { let _ = write!(line, "{:<16} ", format!("<{}>", record.function)); },
+        }
+
+        let _ = write!(line, "{:<28}", record.instruction.to_string());
+
+        for (index, (register, before, after)) in record.registers.iter().enumerate() {
+            if index != 0 {
+                line.push_str(", ");
+            }
+
+            let _ = write!(line, "@{register}: {before:?} -> {after:?}");
+        }
+
+        println!("{line}");
+    }
+}
@@ -14,9 +14,39 @@ pub const JMP : u8 = 11;
 pub const IJIF : u8 = 12;
 pub const IJNIF : u8 = 13;
 
+pub const JLTI : u8 = 14;
+pub const JLTF : u8 = 15;
+pub const JLEI : u8 = 16;
+pub const JLEF : u8 = 17;
+pub const JEQI : u8 = 18;
+pub const JEQF : u8 = 19;
+pub const JNEI : u8 = 20;
+pub const JNEF : u8 = 21;
+
 pub const CALL : u8 = 50;
+pub const CALLN : u8 = 51;
+pub const CALLW : u8 = 52;
+pub const TAILCALL : u8 = 53;
+
+
+pub const STRCAT : u8 = 60;
+pub const STRLEN : u8 = 61;
+pub const STREQ  : u8 = 62;
+pub const STRCMP : u8 = 63;
+pub const SUBSTR : u8 = 64;
+pub const CHARAT : u8 = 65;
+
+
+pub const ARRNEW  : u8 = 70;
+pub const ARRLEN  : u8 = 71;
+pub const ARRGET  : u8 = 72;
+pub const ARRSET  : u8 = 73;
+pub const ARRPUSH : u8 = 74;
+pub const ARRPOP  : u8 = 75;
 
 
+// integers wrap around on overflow and a shift by an amount
+// outside `0..64` shifts every bit out
 pub const ADDI : u8 = 100;
 pub const ADDU : u8 = 101;
 pub const ADDF : u8 = 102;
@@ -65,4 +95,269 @@ pub const CASTFI : u8 = 154;
 pub const CASTFU : u8 = 155;
 
 
+pub const ADDIK : u8 = 160;
+pub const ADDFK : u8 = 161;
+pub const SUBIK : u8 = 162;
+pub const SUBFK : u8 = 163;
+pub const MULIK : u8 = 164;
+pub const MULFK : u8 = 165;
+
+pub const LTIK : u8 = 166;
+pub const LTFK : u8 = 167;
+pub const GTIK : u8 = 168;
+pub const GTFK : u8 = 169;
+pub const LEIK : u8 = 170;
+pub const LEFK : u8 = 171;
+pub const GEIK : u8 = 172;
+pub const GEFK : u8 = 173;
+pub const EQIK : u8 = 174;
+pub const EQFK : u8 = 175;
+pub const NEIK : u8 = 176;
+pub const NEFK : u8 = 177;
+
+
+pub const ANDI : u8 = 180;
+pub const ANDU : u8 = 181;
+pub const ORI  : u8 = 182;
+pub const ORU  : u8 = 183;
+pub const XORI : u8 = 184;
+pub const XORU : u8 = 185;
+pub const NOTI : u8 = 186;
+pub const NOTU : u8 = 187;
+
+pub const ANDB : u8 = 188;
+pub const ORB  : u8 = 189;
+pub const NOTB : u8 = 190;
+
+// `negi` wraps like the rest of the plain integer opcodes
+pub const NEGI : u8 = 191;
+pub const NEGF : u8 = 192;
+
+
+// traps with `VmError::Overflow` on overflow, a shift by an amount
+// outside `0..64` or a left shift that loses bits
+pub const ADDIC : u8 = 200;
+pub const ADDUC : u8 = 201;
+pub const SUBIC : u8 = 202;
+pub const SUBUC : u8 = 203;
+pub const MULIC : u8 = 204;
+pub const MULUC : u8 = 205;
+pub const LSIC  : u8 = 206;
+pub const LSUC  : u8 = 207;
+pub const RSIC  : u8 = 208;
+pub const RSUC  : u8 = 209;
+
+// shifts that only use the low 6 bits of the amount, the plain
+// `addi`, `subi`, `muli`, `addu`, `subu` and `mulu` already wrap
+// so the assembler takes `addi_w` and the rest as their other name
+pub const LSIW  : u8 = 216;
+pub const LSUW  : u8 = 217;
+pub const RSIW  : u8 = 218;
+pub const RSUW  : u8 = 219;
+
+// clamps to the smallest or largest value on overflow
+pub const ADDIS : u8 = 220;
+pub const ADDUS : u8 = 221;
+pub const SUBIS : u8 = 222;
+pub const SUBUS : u8 = 223;
+pub const MULIS : u8 = 224;
+pub const MULUS : u8 = 225;
+pub const LSIS  : u8 = 226;
+pub const LSUS  : u8 = 227;
+pub const RSIS  : u8 = 228;
+pub const RSUS  : u8 = 229;
+
+
 pub const PRINT : u8 = 255;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// A register in the current frame (u8)
+    Reg,
+    /// A literal (u8)
+    U8,
+    /// An index into the constant table (u16)
+    Constant,
+    /// The offset of a block in the current function (u32)
+    Block,
+    /// The offset of a function (u32)
+    Function,
+    /// An index into the import table (u16)
+    Import,
+    /// A count (u8) followed by that many registers
+    RegList,
+}
+
+
+///
+/// Returns the mnemonic and the operands of `opcode`
+/// or `None` if it isn't a valid opcode
+///
+pub fn layout(opcode: u8) -> Option<(&'static str, &'static [Operand])> {
+    use Operand::*;
+
+    const UNARY  : &[Operand] = &[Reg, Reg];
+    const BINARY : &[Operand] = &[Reg, Reg, Reg];
+    // the right-hand side comes from the constant table
+    const IMMEDIATE : &[Operand] = &[Reg, Reg, Constant];
+    // compares two registers and jumps to the first block if it holds
+    const BRANCH : &[Operand] = &[Reg, Reg, Block, Block];
+
+    Some(match opcode {
+        RETURN => ("ret" , &[]),
+        COPY   => ("cpy" , UNARY),
+        SWAP   => ("swap", UNARY),
+        SET    => ("set" , &[Reg, Constant]),
+
+        PUSH => ("push", &[U8]),
+        POP  => ("pop" , &[U8]),
+
+        JIF   => ("jif"  , &[Reg, Block, Block]),
+        JNIF  => ("jnif" , &[Reg, Block, Block]),
+        JMP   => ("jmp"  , &[Block]),
+        IJIF  => ("ijif" , &[Reg, Block]),
+        IJNIF => ("ijnif", &[Reg, Block]),
+
+        JLTI => ("jlti", BRANCH),
+        JLTF => ("jltf", BRANCH),
+        JLEI => ("jlei", BRANCH),
+        JLEF => ("jlef", BRANCH),
+        JEQI => ("jeqi", BRANCH),
+        JEQF => ("jeqf", BRANCH),
+        JNEI => ("jnei", BRANCH),
+        JNEF => ("jnef", BRANCH),
+
+        CALL  => ("call"      , &[Reg, Function, RegList]),
+        CALLN => ("callnative", &[Reg, Import, RegList]),
+        // the return register followed by the arguments, the last registers of the frame
+        CALLW => ("callw"     , &[Reg, Function, U8]),
+        TAILCALL => ("tailcall", &[Function, RegList]),
+
+        STRCAT => ("strcat", BINARY),
+        STRLEN => ("strlen", UNARY),
+        STREQ  => ("streq" , BINARY),
+        STRCMP => ("strcmp", BINARY),
+        SUBSTR => ("substr", &[Reg, Reg, Reg, Reg]),
+        CHARAT => ("charat", BINARY),
+
+        ARRNEW  => ("arrnew" , BINARY),
+        ARRLEN  => ("arrlen" , UNARY),
+        ARRGET  => ("arrget" , BINARY),
+        ARRSET  => ("arrset" , BINARY),
+        ARRPUSH => ("arrpush", UNARY),
+        ARRPOP  => ("arrpop" , UNARY),
+
+        ADDI => ("addi", BINARY),
+        ADDU => ("addu", BINARY),
+        ADDF => ("addf", BINARY),
+        SUBI => ("subi", BINARY),
+        SUBU => ("subu", BINARY),
+        SUBF => ("subf", BINARY),
+        MULI => ("muli", BINARY),
+        MULU => ("mulu", BINARY),
+        MULF => ("mulf", BINARY),
+        DIVI => ("divi", BINARY),
+        DIVU => ("divu", BINARY),
+        DIVF => ("divf", BINARY),
+        REMI => ("remi", BINARY),
+        REMU => ("remu", BINARY),
+        REMF => ("remf", BINARY),
+        LSI  => ("lsi" , BINARY),
+        LSU  => ("lsu" , BINARY),
+        RSI  => ("rsi" , BINARY),
+        RSU  => ("rsu" , BINARY),
+
+        LTI => ("lti", BINARY),
+        LTU => ("ltu", BINARY),
+        LTF => ("ltf", BINARY),
+        GTI => ("gti", BINARY),
+        GTU => ("gtu", BINARY),
+        GTF => ("gtf", BINARY),
+        LEI => ("lei", BINARY),
+        LEU => ("leu", BINARY),
+        LEF => ("lef", BINARY),
+        GEI => ("gei", BINARY),
+        GEU => ("geu", BINARY),
+        GEF => ("gef", BINARY),
+        EQI => ("eqi", BINARY),
+        EQU => ("equ", BINARY),
+        EQF => ("eqf", BINARY),
+        NEI => ("nei", BINARY),
+        NEU => ("neu", BINARY),
+        NEF => ("nef", BINARY),
+
+        CASTIU => ("cast_iu", UNARY),
+        CASTIF => ("cast_if", UNARY),
+        CASTUI => ("cast_ui", UNARY),
+        CASTUF => ("cast_uf", UNARY),
+        CASTFI => ("cast_fi", UNARY),
+        CASTFU => ("cast_fu", UNARY),
+
+        ADDIK => ("addik", IMMEDIATE),
+        ADDFK => ("addfk", IMMEDIATE),
+        SUBIK => ("subik", IMMEDIATE),
+        SUBFK => ("subfk", IMMEDIATE),
+        MULIK => ("mulik", IMMEDIATE),
+        MULFK => ("mulfk", IMMEDIATE),
+
+        LTIK => ("ltik", IMMEDIATE),
+        LTFK => ("ltfk", IMMEDIATE),
+        GTIK => ("gtik", IMMEDIATE),
+        GTFK => ("gtfk", IMMEDIATE),
+        LEIK => ("leik", IMMEDIATE),
+        LEFK => ("lefk", IMMEDIATE),
+        GEIK => ("geik", IMMEDIATE),
+        GEFK => ("gefk", IMMEDIATE),
+        EQIK => ("eqik", IMMEDIATE),
+        EQFK => ("eqfk", IMMEDIATE),
+        NEIK => ("neik", IMMEDIATE),
+        NEFK => ("nefk", IMMEDIATE),
+
+        ANDI => ("andi", BINARY),
+        ANDU => ("andu", BINARY),
+        ORI  => ("ori" , BINARY),
+        ORU  => ("oru" , BINARY),
+        XORI => ("xori", BINARY),
+        XORU => ("xoru", BINARY),
+        NOTI => ("noti", UNARY),
+        NOTU => ("notu", UNARY),
+
+        ANDB => ("andb", BINARY),
+        ORB  => ("orb" , BINARY),
+        NOTB => ("notb", UNARY),
+
+        NEGI => ("negi", UNARY),
+        NEGF => ("negf", UNARY),
+
+        ADDIC => ("addi_c", BINARY),
+        ADDUC => ("addu_c", BINARY),
+        SUBIC => ("subi_c", BINARY),
+        SUBUC => ("subu_c", BINARY),
+        MULIC => ("muli_c", BINARY),
+        MULUC => ("mulu_c", BINARY),
+        LSIC  => ("lsi_c" , BINARY),
+        LSUC  => ("lsu_c" , BINARY),
+        RSIC  => ("rsi_c" , BINARY),
+        RSUC  => ("rsu_c" , BINARY),
+        LSIW  => ("lsi_w" , BINARY),
+        LSUW  => ("lsu_w" , BINARY),
+        RSIW  => ("rsi_w" , BINARY),
+        RSUW  => ("rsu_w" , BINARY),
+
+        ADDIS => ("addi_s", BINARY),
+        ADDUS => ("addu_s", BINARY),
+        SUBIS => ("subi_s", BINARY),
+        SUBUS => ("subu_s", BINARY),
+        MULIS => ("muli_s", BINARY),
+        MULUS => ("mulu_s", BINARY),
+        LSIS  => ("lsi_s" , BINARY),
+        LSUS  => ("lsu_s" , BINARY),
+        RSIS  => ("rsi_s" , BINARY),
+        RSUS  => ("rsu_s" , BINARY),
+
+        PRINT => ("print", &[Reg]),
+
+        _ => return None,
+    })
+}
@@ -0,0 +1,99 @@
+use anatase_common::snippet::highlight;
+use colored::Color;
+
+use crate::symbols::{take, take_str};
+
+
+///
+/// The optional debug section of an `.anb` file
+///
+/// It contains the name and source code of the `.an` file that
+/// was assembled followed by the line table, which maps the offset
+/// (u32) of every instruction to its source range (two u32s) and
+/// starts with the amount of entries (u32), and then the offset (u32)
+/// and name of every block until the end of the section
+///
+#[derive(Debug, Clone)]
+pub struct DebugInfo {
+    file_name: String,
+    source: String,
+    lines: Vec<(usize, usize, usize)>,
+    blocks: Vec<(usize, String)>,
+}
+
+
+impl DebugInfo {
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let file_name = take_str(&mut bytes)?;
+        let source = take_str(&mut bytes)?;
+
+        let count = u32::from_le_bytes(take(&mut bytes)?);
+        let mut lines = vec![];
+        for _ in 0..count {
+            let offset = u32::from_le_bytes(take(&mut bytes)?);
+            let start = u32::from_le_bytes(take(&mut bytes)?);
+            let end = u32::from_le_bytes(take(&mut bytes)?);
+
+            lines.push((offset as usize, start as usize, end as usize));
+        }
+
+        let mut blocks = vec![];
+        while !bytes.is_empty() {
+            let offset = u32::from_le_bytes(take(&mut bytes)?);
+            let name = take_str(&mut bytes)?;
+
+            blocks.push((offset as usize, name));
+        }
+
+        Some(Self { file_name, source, lines, blocks })
+    }
+
+
+    /// Returns the source range of the instruction that contains `offset`
+    pub fn source_range(&self, offset: usize) -> Option<(usize, usize)> {
+        let index = self.lines.partition_point(|x| x.0 <= offset);
+        let (_, start, end) = self.lines.get(index.checked_sub(1)?)?;
+        Some((*start, *end))
+    }
+
+
+    /// Returns the offset of the instruction that contains `offset`
+    pub fn instruction_start(&self, offset: usize) -> Option<usize> {
+        let index = self.lines.partition_point(|x| x.0 <= offset);
+        Some(self.lines.get(index.checked_sub(1)?)?.0)
+    }
+
+
+    /// Renders the source of the instruction that contains `offset`
+    pub fn highlight(&self, offset: usize, colour: Color) -> Option<String> {
+        let (start, end) = self.source_range(offset)?;
+        Some(highlight(&self.file_name, &self.source, start, end, None, colour))
+    }
+
+
+    ///
+    /// Returns the offset of the block `name` in the function
+    /// that spans `function`
+    ///
+    pub fn block(&self, function: std::ops::Range<usize>, name: &str) -> Option<usize> {
+        self.blocks.iter()
+            .find(|x| function.contains(&x.0) && x.1 == name)
+            .map(|x| x.0)
+    }
+
+
+    /// Returns the name of the block that starts at `offset`
+    pub fn block_at(&self, offset: usize) -> Option<&str> {
+        self.blocks.iter().find(|x| x.0 == offset).map(|x| x.1.as_str())
+    }
+
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
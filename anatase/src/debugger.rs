@@ -0,0 +1,197 @@
+use std::fmt::Display;
+
+use crate::{VM, Data, bytecode, decoder, errors::VmError};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint<'a> {
+    /// The instruction at this offset of the bytecode
+    Offset(usize),
+    /// The first instruction of a block, needs the debug info
+    Block { function: &'a str, block: &'a str },
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested instructions were executed
+    Step,
+    /// The next instruction has a breakpoint on it
+    Breakpoint(usize),
+    /// The program returned from its outermost function
+    Finished,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakpointError {
+    UnknownFunction(String),
+    UnknownBlock(String),
+    NotAnInstruction(usize),
+}
+
+
+///
+/// The debugger is only available with runtime checks so
+/// `VM::<false>::run` doesn't have to look for breakpoints
+///
+/// `VM::run` ignores breakpoints as well, a front-end drives
+/// the program with `step`, `step_over`, `step_out` and `resume`
+/// instead. Each of them executes at least one instruction so
+/// resuming from a breakpoint doesn't stop on it again
+///
+impl VM<true> {
+    /// Sets a breakpoint and returns the offset it was set at
+    pub fn set_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<usize, BreakpointError> {
+        let offset = self.resolve(breakpoint)?;
+        self.breakpoints.insert(offset);
+        Ok(offset)
+    }
+
+
+    /// Clears a breakpoint and returns whether it was set
+    pub fn clear_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<bool, BreakpointError> {
+        let offset = self.resolve(breakpoint)?;
+        Ok(self.breakpoints.remove(&offset))
+    }
+
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+
+    /// Executes a single instruction
+    pub fn step(&mut self) -> Result<Stop, VmError> {
+        self.link_before_running()?;
+
+        if self.execute()? {
+            return Ok(Stop::Finished)
+        }
+
+        Ok(Stop::Step)
+    }
+
+
+    /// Executes a single instruction, running a `call` until it returns
+    pub fn step_over(&mut self) -> Result<Stop, VmError> {
+        self.link_before_running()?;
+
+        let is_call = matches!(self.program.bytecode().get(self.offset()), Some(&bytecode::CALL | &bytecode::CALLW));
+        let depth = self.callstack.len();
+
+        if self.execute()? {
+            return Ok(Stop::Finished)
+        }
+
+        if !is_call {
+            return Ok(Stop::Step)
+        }
+
+        self.run_until(|vm| vm.callstack.len() <= depth)
+    }
+
+
+    /// Runs until the current function returns to its caller
+    pub fn step_out(&mut self) -> Result<Stop, VmError> {
+        self.link_before_running()?;
+
+        let depth = self.callstack.len();
+
+        if self.execute()? {
+            return Ok(Stop::Finished)
+        }
+
+        self.run_until(|vm| vm.callstack.len() < depth)
+    }
+
+
+    /// Runs until the next breakpoint or until the program finishes
+    pub fn resume(&mut self) -> Result<Stop, VmError> {
+        self.link_before_running()?;
+
+        if self.execute()? {
+            return Ok(Stop::Finished)
+        }
+
+        self.run_until(|_| false)
+    }
+
+
+    /// The offset of the next instruction
+    pub fn offset(&self) -> usize {
+        self.location().offset
+    }
+
+
+    /// The amount of calls that haven't returned yet
+    pub fn depth(&self) -> usize {
+        self.callstack.len()
+    }
+
+
+    /// The registers of the current frame, `@0` comes first
+    pub fn registers(&self) -> &[Data] {
+        &self.stack.values[self.stack.bottom..self.stack.top]
+    }
+
+
+    fn run_until(&mut self, done: impl Fn(&Self) -> bool) -> Result<Stop, VmError> {
+        loop {
+            let offset = self.offset();
+            if self.breakpoints.contains(&offset) {
+                return Ok(Stop::Breakpoint(offset))
+            }
+
+            if done(self) {
+                return Ok(Stop::Step)
+            }
+
+            if self.execute()? {
+                return Ok(Stop::Finished)
+            }
+        }
+    }
+
+
+    fn resolve(&self, breakpoint: Breakpoint) -> Result<usize, BreakpointError> {
+        match breakpoint {
+            Breakpoint::Offset(offset) => {
+                let instructions = decoder::decode_all(self.program.bytecode()).unwrap_or_default();
+
+                if !instructions.iter().any(|x| x.offset == offset) {
+                    return Err(BreakpointError::NotAnInstruction(offset))
+                }
+
+                Ok(offset)
+            },
+
+
+            Breakpoint::Block { function, block } => {
+                let functions = self.program.functions();
+                let Some(start) = functions.get(function)
+                else { return Err(BreakpointError::UnknownFunction(function.to_string())) };
+
+                let range = functions.range(start.offset, self.program.bytecode().len());
+
+                self.program.debug_info()
+                    .and_then(|x| x.block(range, block))
+                    .ok_or_else(|| BreakpointError::UnknownBlock(block.to_string()))
+            },
+        }
+    }
+}
+
+
+impl Display for BreakpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreakpointError::UnknownFunction(name) => write!(f, "the function '{name}' doesn't exist"),
+            BreakpointError::UnknownBlock(name) => write!(f, "the block '{name}' doesn't exist or there's no debug info"),
+            BreakpointError::NotAnInstruction(offset) => write!(f, "offset {offset} isn't the start of an instruction"),
+        }
+    }
+}
+
+
+impl std::error::Error for BreakpointError {}
@@ -0,0 +1,171 @@
+use std::fmt::Display;
+
+use crate::{bytecode::{self, Operand}, symbols::take};
+
+
+///
+/// A single decoded instruction
+///
+/// This is the shared view of the bytecode for everything that
+/// needs to look at instructions without executing them
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub offset: usize,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Value>,
+    /// The size of the instruction in bytes
+    pub len: usize,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Reg(u8),
+    U8(u8),
+    Constant(u16),
+    Block(u32),
+    Function(u32),
+    Import(u16),
+    RegList(Vec<u8>),
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    InvalidOpcode { offset: usize, opcode: u8 },
+    Truncated { offset: usize },
+}
+
+
+///
+/// Decodes the instruction that starts at `offset`
+///
+pub fn decode(bytecode: &[u8], offset: usize) -> Result<Instruction, DecodeError> {
+    let truncated = DecodeError::Truncated { offset };
+
+    let opcode = *bytecode.get(offset).ok_or(truncated)?;
+    let Some((mnemonic, layout)) = bytecode::layout(opcode)
+    else { return Err(DecodeError::InvalidOpcode { offset, opcode }) };
+
+    let mut bytes = bytecode.get(offset + 1..).ok_or(truncated)?;
+    let mut operands = Vec::with_capacity(layout.len());
+
+    for operand in layout {
+        let value = match operand {
+            Operand::Reg => Value::Reg(take::<1>(&mut bytes).ok_or(truncated)?[0]),
+            Operand::U8  => Value::U8 (take::<1>(&mut bytes).ok_or(truncated)?[0]),
+            Operand::Constant => Value::Constant(u16::from_le_bytes(take(&mut bytes).ok_or(truncated)?)),
+            Operand::Block    => Value::Block   (u32::from_le_bytes(take(&mut bytes).ok_or(truncated)?)),
+            Operand::Function => Value::Function(u32::from_le_bytes(take(&mut bytes).ok_or(truncated)?)),
+            Operand::Import   => Value::Import  (u16::from_le_bytes(take(&mut bytes).ok_or(truncated)?)),
+            Operand::RegList => {
+                let [count] = take(&mut bytes).ok_or(truncated)?;
+                let regs = bytes.get(..count as usize).ok_or(truncated)?;
+                bytes = &bytes[count as usize..];
+
+                Value::RegList(regs.to_vec())
+            },
+        };
+
+        operands.push(value);
+    }
+
+    let len = bytecode.len() - offset - bytes.len();
+    Ok(Instruction { offset, opcode, mnemonic, operands, len })
+}
+
+
+///
+/// Decodes every instruction in `bytecode` from the start to the end
+///
+pub fn decode_all(bytecode: &[u8]) -> Result<Vec<Instruction>, DecodeError> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+
+    while offset < bytecode.len() {
+        let instruction = decode(bytecode, offset)?;
+        offset += instruction.len;
+        instructions.push(instruction);
+    }
+
+    Ok(instructions)
+}
+
+
+impl Instruction {
+    /// The registers this instruction reads or writes
+    pub fn registers(&self) -> impl Iterator<Item = u8> + '_ {
+        // the arguments of a `callw` are the registers after the first one,
+        // a window that goes past @255 has none the verifier accepts
+        let window = match (self.opcode, self.operands.as_slice()) {
+            (bytecode::CALLW, &[Value::Reg(dst), _, Value::U8(argc)]) => dst.checked_add(argc).map(|end| (dst..end).map(|x| x + 1)),
+            _ => None,
+        };
+
+        self.operands.iter().flat_map(|x| match x {
+            Value::Reg(v) => std::slice::from_ref(v),
+            Value::RegList(v) => v.as_slice(),
+            _ => &[],
+        }).copied().chain(window.into_iter().flatten())
+    }
+
+
+    /// The offset of the next instruction
+    pub fn next(&self) -> usize {
+        self.offset + self.len
+    }
+}
+
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+
+        for operand in &self.operands {
+            if matches!(operand, Value::RegList(v) if v.is_empty()) {
+                continue
+            }
+
+            write!(f, " {operand}")?;
+        }
+
+        Ok(())
+    }
+}
+
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Reg(v) => write!(f, "@{v}"),
+            Value::U8(v) => write!(f, "{v}"),
+            Value::Constant(v) => write!(f, "#{v}"),
+            Value::Block(v) => write!(f, "${v}"),
+            Value::Function(v) => write!(f, "<{v}>"),
+            Value::Import(v) => write!(f, "%{v}"),
+            Value::RegList(v) => {
+                for (i, reg) in v.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+
+                    write!(f, "@{reg}")?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
+
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::InvalidOpcode { offset, opcode } => write!(f, "invalid opcode {opcode} at offset {offset}"),
+            DecodeError::Truncated { offset } => write!(f, "truncated instruction at offset {offset}"),
+        }
+    }
+}
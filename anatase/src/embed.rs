@@ -0,0 +1,159 @@
+use std::{fmt::Display, sync::Arc};
+
+use crate::{VM, Data, Code, errors::VmError, native::LinkError, program::{Program, LoadError}, backtrace::Backtrace};
+
+
+// every `VM::call` inside of a native runs the interpreter again
+// on the native stack, so they're limited well below `CALL_DEPTH`
+const NESTED_CALLS : usize = 16;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallError {
+    UnknownFunction(String),
+    ArgumentCount { expected: u8, found: usize },
+    /// The program couldn't be linked before the call
+    Link(LinkError),
+    /// The function failed, `backtrace` is where it failed
+    Runtime { error: VmError, backtrace: Backtrace },
+}
+
+
+impl VM<true> {
+    /// Loads the `.anb` file in `bytes` into a `VM` with runtime checks
+    pub fn load(bytes: &[u8]) -> Result<Self, LoadError> {
+        Ok(Self::new(Arc::new(Program::from_bytes(bytes)?)))
+    }
+}
+
+
+impl VM<false> {
+    ///
+    /// Loads the `.anb` file in `bytes` into a `VM` without runtime
+    /// checks, which fails if the bytecode doesn't pass verification
+    ///
+    pub fn load(bytes: &[u8]) -> Result<Self, LoadError> {
+        Self::new(Arc::new(Program::from_bytes(bytes)?)).map_err(LoadError::Verification)
+    }
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Calls the function `name` with `args` and returns what it returned
+    ///
+    /// The call gets a frame on top of the stack like a `call` instruction
+    /// would give it and the `VM` is put back the way it was afterwards, so
+    /// any number of functions can be called one after another
+    ///
+    /// The program is linked first if it isn't linked yet
+    ///
+    /// Natives can call back into the `VM` with this, the frames of
+    /// the calls they're inside of count towards `Limits::call_depth`
+    ///
+    /// A `VM<false>` doesn't check the kind of `args` against what the
+    /// function does with them, one of the wrong kind gives a wrong
+    /// result instead of a `VmError::TypeMismatch`
+    ///
+    /// A returned string or array is kept alive until it's given to
+    /// `VM::release`, or until the native returns for a call made
+    /// from a native
+    ///
+    pub fn call(&mut self, name: &str, args: &[Data]) -> Result<Data, CallError> {
+        let Some(function) = self.program.functions().get(name)
+        else { return Err(CallError::UnknownFunction(name.to_string())) };
+
+        if args.len() != function.argc as usize {
+            return Err(CallError::ArgumentCount { expected: function.argc, found: args.len() })
+        }
+
+        let offset = function.offset;
+        let argc = function.argc;
+
+        self.ensure_linked().map_err(CallError::Link)?;
+
+        let base = self.stack.top;
+        let bottom = self.stack.bottom;
+
+        if !self.can_call() || self.nested_calls == NESTED_CALLS || !self.stack.can_push(args.len() + 1) {
+            let error = VmError::StackOverflow(self.location());
+            return Err(CallError::Runtime { error, backtrace: self.backtrace() })
+        }
+
+        self.stack.push(args.len() + 1);
+        self.stack.values[base + 1..base + 1 + args.len()].copy_from_slice(args);
+        self.stack.bottom = base;
+
+        let code = Code::new(
+            unsafe { self.current.base.add(offset) },
+            self.current.base,
+            self.current.top,
+            0,
+            base,
+            argc,
+            offset,
+        );
+
+        // with an empty callstack the function's `ret` ends `run`
+        // and leaves its frame, and the return value, in place
+        let caller = std::mem::replace(&mut self.current, code);
+        let callstack = std::mem::take(&mut self.callstack);
+
+        // a native can call back into the `VM`, the frames put aside
+        // plus the caller's own frame still count towards the limit
+        let nested_depth = self.nested_depth;
+        self.nested_depth += callstack.len() + 1;
+        self.nested_calls += 1;
+
+        let result = self.run()
+            .map(|_| self.stack.values[base])
+            .map_err(|error| CallError::Runtime { error, backtrace: self.backtrace() });
+
+        self.current = caller;
+        self.callstack = callstack;
+        self.nested_depth = nested_depth;
+        self.nested_calls -= 1;
+        self.stack.bottom = bottom;
+        self.stack.top = base;
+
+        if let Some(index) = result.as_ref().ok().and_then(Data::as_object) {
+            match self.running_natives {
+                0 => self.held.push(index),
+                _ => self.temp_roots.push(index),
+            }
+        }
+
+        result
+    }
+
+
+    ///
+    /// Lets the garbage collector free `value`, which `VM::call`
+    /// returned, once nothing in the program refers to it anymore
+    ///
+    /// Each result of `VM::call` has to be released on its own,
+    /// anything else is ignored
+    ///
+    pub fn release(&mut self, value: Data) {
+        let Some(index) = value.as_object() else { return };
+
+        if let Some(position) = self.held.iter().rposition(|x| *x == index) {
+            self.held.swap_remove(position);
+        }
+    }
+}
+
+
+impl Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::UnknownFunction(name) => write!(f, "the function '{name}' doesn't exist"),
+            CallError::ArgumentCount { expected, found } => write!(f, "the function expects {expected} arguments but {found} were given"),
+            CallError::Link(error) => write!(f, "{error}"),
+            CallError::Runtime { error, .. } => write!(f, "{error}"),
+        }
+    }
+}
+
+
+impl std::error::Error for CallError {}
@@ -0,0 +1,100 @@
+use std::fmt::Display;
+
+use crate::Data;
+
+
+///
+/// Where in the bytecode an error happened
+///
+/// `offset` is the position of the faulting instruction and
+/// `function` is the position of the first instruction of the
+/// function that was executing, both relative to the start of the
+/// bytecode section
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub offset: usize,
+    pub function: usize,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    DivisionByZero(Location),
+    /// A checked integer operation overflowed
+    Overflow(Location),
+    InvalidOpcode(Location, u8),
+    TypeMismatch {
+        location: Location,
+        expected: u64,
+        found: u64,
+    },
+    StackOverflow(Location),
+    /// The registers of a `callw` aren't the last ones of the frame
+    InvalidWindow(Location),
+    OutOfMemory(Location),
+    IndexOutOfBounds {
+        location: Location,
+        index: i64,
+        len: usize,
+    },
+    /// An `arrpop` on an array with nothing left in it
+    EmptyArray(Location),
+    UnresolvedImport(Location, u16),
+    /// An error raised by a native function
+    Native {
+        location: Location,
+        message: String,
+    },
+    /// An `InterruptHandle` stopped the program, it can be resumed
+    Interrupted(Location),
+}
+
+
+impl VmError {
+    pub fn location(&self) -> Location {
+        match self {
+            | VmError::DivisionByZero(location)
+            | VmError::Overflow(location)
+            | VmError::InvalidOpcode(location, _)
+            | VmError::TypeMismatch { location, .. }
+            | VmError::StackOverflow(location)
+            | VmError::InvalidWindow(location)
+            | VmError::OutOfMemory(location)
+            | VmError::IndexOutOfBounds { location, .. }
+            | VmError::EmptyArray(location)
+            | VmError::UnresolvedImport(location, _)
+            | VmError::Native { location, .. }
+            | VmError::Interrupted(location) => *location,
+        }
+    }
+}
+
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::DivisionByZero(_) => write!(f, "division by zero")?,
+            VmError::Overflow(_) => write!(f, "integer overflow")?,
+            VmError::InvalidOpcode(_, opcode) => write!(f, "invalid opcode {opcode}")?,
+            VmError::TypeMismatch { expected, found, .. } => write!(
+                f, "type mismatch, expected {} found {}",
+                Data::tag_name(*expected), Data::tag_name(*found),
+            )?,
+            VmError::StackOverflow(_) => write!(f, "stack overflow")?,
+            VmError::InvalidWindow(_) => write!(f, "the call window doesn't end the frame")?,
+            VmError::OutOfMemory(_) => write!(f, "out of memory")?,
+            VmError::IndexOutOfBounds { index, len, .. } => write!(f, "index {index} is out of bounds for a length of {len}")?,
+            VmError::EmptyArray(_) => write!(f, "pop from an empty array")?,
+            VmError::UnresolvedImport(_, index) => write!(f, "import {index} isn't linked")?,
+            VmError::Native { message, .. } => write!(f, "{message}")?,
+            VmError::Interrupted(_) => write!(f, "interrupted")?,
+        }
+
+        let location = self.location();
+        write!(f, " at offset {} in function {}", location.offset, location.function)
+    }
+}
+
+
+impl std::error::Error for VmError {}
@@ -0,0 +1,54 @@
+use crate::{VM, errors::VmError};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Finished,
+    /// The fuel ran out, running the `VM` again continues
+    /// from the instruction that would've executed next
+    OutOfFuel,
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Runs the program like `VM::run` but stops once `fuel`
+    /// instructions have been executed
+    ///
+    /// This lets a host bound how long an untrusted program runs
+    /// or interleave several `VM`s on a single thread
+    ///
+    pub fn run_with_fuel(&mut self, fuel: u64) -> Result<Status, VmError> {
+        self.link_before_running()?;
+
+        for _ in 0..fuel {
+            if self.execute()? {
+                return Ok(Status::Finished)
+            }
+        }
+
+        Ok(Status::OutOfFuel)
+    }
+
+
+    ///
+    /// Like `VM::run_with_fuel` but each instruction costs `costs[opcode]`
+    ///
+    /// An instruction only executes if the fuel left covers its cost
+    ///
+    pub fn run_with_fuel_costs(&mut self, mut fuel: u64, costs: &[u64; 256]) -> Result<Status, VmError> {
+        self.link_before_running()?;
+
+        loop {
+            let cost = costs[self.current.peek() as usize];
+            fuel = match fuel.checked_sub(cost) {
+                Some(v) => v,
+                None => return Ok(Status::OutOfFuel),
+            };
+
+            if self.execute()? {
+                return Ok(Status::Finished)
+            }
+        }
+    }
+}
@@ -1,94 +1,229 @@
-use std::{mem::size_of, cell::Cell, sync::{atomic::{AtomicBool, Ordering, AtomicUsize}, Arc}, thread, time::Duration};
+use crate::{VM, Data};
 
-use crate::Stack;
-
-
-static IS_GC_RUNNING : AtomicBool = AtomicBool::new(false);
-static GC_REQUESTED : AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug)]
-pub struct MemoryPool<const DEBUG: bool> {
-    memory: Vec<Object>, // temporary
-    free: AtomicUsize,
+pub struct MemoryPool {
+    memory: Vec<Object>,
+    // the first slot of the free list, `memory.len()` if it's empty
+    free: usize,
+    live: usize,
 }
 
 
 #[derive(Debug)]
 pub struct Object {
     data: ObjectData,
-    marked: u8,
+    marked: bool,
 }
 
 
 #[derive(Debug)]
 pub enum ObjectData {
-    Data([u8; 32]),
+    String(String),
+    Array(Vec<Data>),
+    /// A slot in the free list that points to the next free slot
     Free(usize),
 }
 
-impl<const DEBUG: bool> MemoryPool<DEBUG> {
+
+impl MemoryPool {
     pub fn with_capacity(cap: usize) -> Self {
-        Self {
-            memory: (0..cap).map(|x| Object { data: ObjectData::Free(x+1), marked: 0 }).collect(),
-            free: AtomicUsize::new(0),
-        }
+        Self::with_objects(std::iter::empty(), cap)
     }
 
-    
-    pub fn add(&self, obj: Object) -> *mut Object {
-        if self.free.load(Ordering::SeqCst) >= self.memory.capacity() {
-            GarbageCollector::<DEBUG>::run_gc();
-            while IS_GC_RUNNING.load(Ordering::SeqCst) || GC_REQUESTED.load(Ordering::SeqCst) { std::hint::spin_loop() }
 
-            if self.free.load(Ordering::SeqCst) >= self.memory.capacity() {
-                panic!("out of memory")
-            }
+    ///
+    /// Creates a pool with `objects` in its first slots, in order,
+    /// and `free` free slots after them
+    ///
+    pub fn with_objects(objects: impl IntoIterator<Item = ObjectData>, free: usize) -> Self {
+        let mut memory : Vec<_> = objects.into_iter().map(|data| Object { data, marked: false }).collect();
+        let live = memory.len();
+
+        memory.extend((live..live + free).map(|x| Object { data: ObjectData::Free(x+1), marked: false }));
+
+        Self {
+            memory,
+            free: live,
+            live,
         }
+    }
 
-        let ptr = self.memory.get(self.free.load(Ordering::SeqCst)).unwrap() as *const Object;
-        let ptr = ptr as *mut Object;
 
-        let old = std::mem::replace(unsafe {&mut *ptr}, obj);
-        match old.data {
-            ObjectData::Free(v) => self.free.store(v, Ordering::SeqCst),
+    ///
+    /// Moves `data` into a free slot and returns its index
+    /// or `None` if there are no free slots left
+    ///
+    pub fn add(&mut self, data: ObjectData) -> Option<usize> {
+        let index = self.free;
+        let object = self.memory.get_mut(index)?;
+
+        let old = std::mem::replace(&mut object.data, data);
+        match old {
+            ObjectData::Free(v) => self.free = v,
             _ => panic!("replaced a not-freed-object")
         };
 
-        ptr
+        self.live += 1;
+        Some(index)
     }
-}
 
 
-pub struct GarbageCollector<const DEBUG: bool> {
-    
-}
+    pub fn get(&self, index: usize) -> &ObjectData {
+        &self.memory[index].data
+    }
+
+
+    pub fn get_mut(&mut self, index: usize) -> &mut ObjectData {
+        &mut self.memory[index].data
+    }
+
+
+    /// Returns the string `data` refers to if it is one
+    pub fn string(&self, data: Data) -> Option<&str> {
+        match self.memory.get(data.as_object()?)?.data {
+            ObjectData::String(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+
+    /// Returns the array `data` refers to if it is one
+    pub fn array(&self, data: Data) -> Option<&Vec<Data>> {
+        match self.memory.get(data.as_object()?)?.data {
+            ObjectData::Array(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+
+    pub fn array_mut(&mut self, data: Data) -> Option<&mut Vec<Data>> {
+        match self.memory.get_mut(data.as_object()?)?.data {
+            ObjectData::Array(ref mut v) => Some(v),
+            _ => None,
+        }
+    }
+
 
+    pub fn is_full(&self) -> bool {
+        self.free >= self.memory.len()
+    }
 
-impl<const DEBUG: bool> GarbageCollector<DEBUG> {
-    pub fn start(mem: Arc<MemoryPool<DEBUG>>, stack: SendPtr<Stack<DEBUG>>) -> ! {
-        loop {
-            if GC_REQUESTED.load(Ordering::SeqCst) {
-                println!("run gc");
-                IS_GC_RUNNING.store(true, Ordering::SeqCst);
 
-                thread::sleep(Duration::from_secs(1));
-                
-                IS_GC_RUNNING.store(false, Ordering::SeqCst);
-                GC_REQUESTED.store(false, Ordering::SeqCst);
+    /// The amount of objects that are currently allocated
+    pub fn live(&self) -> usize {
+        self.live
+    }
 
+
+    pub fn capacity(&self) -> usize {
+        self.memory.len()
+    }
+
+
+    fn mark(&mut self, roots: impl Iterator<Item = Data>, queue: &mut Vec<usize>) {
+        queue.extend(roots.filter_map(|x| x.as_object()));
+
+        while let Some(index) = queue.pop() {
+            let object = &mut self.memory[index];
+            if object.marked {
+                continue
             }
+
+            object.marked = true;
+            queue.extend(object.data.references().iter().filter_map(|x| x.as_object()));
         }
     }
 
 
-    pub fn run_gc() {
-        GC_REQUESTED.store(true, Ordering::SeqCst)
+    /// Frees every unmarked object and returns how many were freed
+    fn sweep(&mut self) -> usize {
+        let mut freed = 0;
+
+        for (index, object) in self.memory.iter_mut().enumerate() {
+            if matches!(object.data, ObjectData::Free(_)) {
+                continue
+            }
+
+            if object.marked {
+                object.marked = false;
+                continue
+            }
+
+            object.data = ObjectData::Free(self.free);
+            self.free = index;
+            freed += 1;
+        }
+
+        self.live -= freed;
+        freed
     }
 }
 
 
-pub struct SendPtr<T>(pub *mut T);
+impl ObjectData {
+    /// The values this object holds
+    fn references(&self) -> &[Data] {
+        match self {
+            ObjectData::String(_) => &[],
+            ObjectData::Array(v) => v,
+            ObjectData::Free(_) => &[],
+        }
+    }
+}
 
 
-unsafe impl<T: Send> Send for SendPtr<T> {}
-unsafe impl Send for Object {}
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Allocates `data` on the heap running the garbage
+    /// collector if there's no space left
+    ///
+    /// Returns `None` if the heap is still full after
+    /// the collection
+    ///
+    /// Inside of a native the object is kept alive until the native
+    /// returns, so it can allocate more than once before it puts the
+    /// objects somewhere the garbage collector can see them
+    ///
+    pub fn alloc(&mut self, data: ObjectData) -> Option<usize> {
+        if self.memory.is_full() {
+            self.collect_garbage();
+        }
+
+        let index = self.memory.add(data)?;
+        if self.running_natives > 0 {
+            self.temp_roots.push(index);
+        }
+
+        Some(index)
+    }
+
+
+    /// Allocates a string and returns the value that refers to it
+    pub fn alloc_string(&mut self, value: String) -> Option<Data> {
+        self.alloc(ObjectData::String(value)).map(Data::new_str)
+    }
+
+
+    ///
+    /// Frees every object that can't be reached from the stack or
+    /// the constants and returns how many objects were freed
+    ///
+    /// Every frame on the callstack lives in `self.stack` below its
+    /// `top`, so walking the stack covers all of them
+    ///
+    pub fn collect_garbage(&mut self) -> usize {
+        // the objects of the running natives and the ones the host
+        // holds on to are marked along with the roots
+        let mut queue = self.temp_roots.clone();
+        queue.extend_from_slice(&self.held);
+
+        let registers = self.stack.values[..self.stack.top].iter().copied();
+        self.memory.mark(registers, &mut queue);
+
+        let constants = self.constants.iter().copied();
+        self.memory.mark(constants, &mut queue);
+
+        self.memory.sweep()
+    }
+}
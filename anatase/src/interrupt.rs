@@ -0,0 +1,44 @@
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+use crate::VM;
+
+
+///
+/// Stops a running `VM` from another thread
+///
+/// The `VM` only looks at the handle on backward jumps and calls
+/// so a program that runs forever notices it soon enough while
+/// straight-line code doesn't pay for the check
+///
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    flag: Arc<AtomicBool>,
+}
+
+
+impl InterruptHandle {
+    ///
+    /// Makes the `VM` return `VmError::Interrupted` at the next
+    /// backward jump or call
+    ///
+    /// The request is cleared once the `VM` reports it, at which
+    /// point running it again continues from that instruction
+    ///
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle { flag: self.interrupt.clone() }
+    }
+
+
+    /// Returns whether an interrupt was requested and clears it
+    #[inline(always)]
+    pub(crate) fn take_interrupt(&self) -> bool {
+        self.interrupt.load(Ordering::Relaxed) && self.interrupt.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,683 @@
+use std::{collections::HashMap, sync::{Arc, atomic::AtomicBool}, fmt::Debug};
+
+use cranelift_codegen::{ir::{types, AbiParam, Block, InstBuilder, MemFlags, Value, Type, condcodes::{IntCC, FloatCC}}, settings::{self, Configurable}};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Module, default_libcall_names};
+
+use crate::{VM, Data, bytecode, decoder::{self, Instruction}, program::{Program, Constant}};
+
+
+/// How many calls it takes before a function is compiled
+const THRESHOLD : u32 = 1_000;
+
+
+///
+/// Compiles hot functions to native code with Cranelift
+///
+/// The compiled code works on the registers of the frame in `Stack`
+/// the same way the interpreter does so the two can take turns in
+/// the same frame. It starts at the first instruction of a function
+/// and returns the offset the interpreter continues from, which is
+/// the first instruction it doesn't handle itself, like a `call`,
+/// a `ret` or anything that would fail. The interpreter then runs
+/// that instruction so the errors are exactly those of `VM::execute`.
+///
+/// Only `VM::run` on a `VM<false>` runs compiled code as it doesn't
+/// check that the registers are inside the frame
+///
+pub(crate) struct Jit {
+    threshold: u32,
+    calls: HashMap<usize, u32>,
+    /// `None` for the functions that aren't worth compiling
+    compiled: HashMap<usize, Option<Compiled>>,
+    /// Created with the first compiled function
+    module: Option<JITModule>,
+}
+
+
+///
+/// What the compiled code works with, its fields are at
+/// the offsets the code is generated with
+///
+#[repr(C)]
+struct Frame {
+    /// The first register of the frame
+    regs: *mut Data,
+    /// `Stack::top`, written back when the code returns
+    top: usize,
+    /// The amount of slots in the stack
+    len: usize,
+    constants: *const Data,
+    interrupt: *const AtomicBool,
+}
+
+
+type Compiled = unsafe extern "C" fn(*mut Frame) -> u32;
+
+
+const REGS : i32 = 0;
+const TOP : i32 = 8;
+const LEN : i32 = 16;
+const CONSTANTS : i32 = 24;
+const INTERRUPT : i32 = 32;
+
+// a `Data` is its tag followed by its value
+const SIZE : i64 = 16;
+const VALUE : i32 = 8;
+
+
+impl Jit {
+    pub(crate) fn new() -> Self {
+        Self {
+            threshold: THRESHOLD,
+            calls: HashMap::new(),
+            compiled: HashMap::new(),
+            module: None,
+        }
+    }
+
+
+    /// How many functions have native code
+    pub(crate) fn compiled_count(&self) -> usize {
+        self.compiled.values().filter(|x| x.is_some()).count()
+    }
+
+
+    ///
+    /// Counts a call to the function at `function` and
+    /// returns its compiled code once it's hot enough
+    ///
+    fn get(&mut self, function: usize, program: &Program) -> Option<Compiled> {
+        if let Some(&compiled) = self.compiled.get(&function) {
+            return compiled
+        }
+
+        if self.threshold == u32::MAX {
+            return None
+        }
+
+        let calls = self.calls.entry(function).or_default();
+        *calls += 1;
+        if *calls <= self.threshold {
+            return None
+        }
+
+        let compiled = self.compile(function, program);
+        self.compiled.insert(function, compiled);
+        compiled
+    }
+
+
+    fn compile(&mut self, function: usize, program: &Program) -> Option<Compiled> {
+        let bytecode = program.bytecode();
+        let end = program.functions().iter()
+            .map(|x| x.offset)
+            .filter(|&x| x > function)
+            .min()
+            .unwrap_or(bytecode.len());
+
+        let mut instructions = vec![];
+        let mut offset = function;
+        while offset < end {
+            let instruction = decoder::decode(bytecode, offset).ok()?;
+            offset = instruction.next();
+            instructions.push(instruction);
+        }
+
+        // nothing to gain if it'd hand the function
+        // back to the interpreter straight away
+        if !supported(&instructions[0], program.constants()) {
+            return None
+        }
+
+
+        let module = match &mut self.module {
+            Some(v) => v,
+            None => self.module.insert(module()?),
+        };
+
+        let pointer = module.target_config().pointer_type();
+
+        let mut context = module.make_context();
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context.func.signature.returns.push(AbiParam::new(types::I32));
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+
+        Translator::new(builder, pointer, program.constants(), &instructions).translate();
+
+        let id = module.declare_anonymous_function(&context.func.signature).ok()?;
+        module.define_function(id, &mut context).ok()?;
+        module.clear_context(&mut context);
+        module.finalize_definitions().ok()?;
+
+        let code = module.get_finalized_function(id);
+        Some(unsafe { std::mem::transmute::<*const u8, Compiled>(code) })
+    }
+}
+
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // the `VM` owning this is the only one
+        // that has pointers to the compiled code
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+
+impl Debug for Jit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jit")
+            .field("threshold", &self.threshold)
+            .field("compiled", &self.compiled_count())
+            .finish()
+    }
+}
+
+
+fn module() -> Option<JITModule> {
+    let mut flags = settings::builder();
+    flags.set("use_colocated_libcalls", "false").ok()?;
+    flags.set("is_pic", "false").ok()?;
+    flags.set("opt_level", "speed").ok()?;
+
+    let isa = cranelift_native::builder().ok()?
+        .finish(settings::Flags::new(flags)).ok()?;
+
+    Some(JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())))
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Sets how many times a function has to be called before it's
+    /// compiled to native code, `u32::MAX` never compiles anything
+    ///
+    pub fn set_jit_threshold(&mut self, threshold: u32) {
+        self.jit.threshold = threshold;
+    }
+
+
+    /// How many functions were compiled, for tests
+    #[doc(hidden)]
+    pub fn jit_compiled_count(&self) -> usize {
+        self.jit.compiled_count()
+    }
+
+
+    ///
+    /// Runs the compiled code of the function that was just
+    /// called, if it's hot enough to have any
+    ///
+    /// `self.current` is left at the instruction the interpreter
+    /// continues from, returns whether any code ran
+    ///
+    pub(crate) fn enter_jit(&mut self) -> bool {
+        let function = self.current.function;
+        if self.current.ptr != unsafe { self.current.base.add(function) } {
+            return false
+        }
+
+        let Some(code) = self.jit.get(function, &self.program)
+        else { return false };
+
+        let mut frame = Frame {
+            regs: unsafe { self.stack.values.as_mut_ptr().add(self.stack.bottom) },
+            top: self.stack.top,
+            len: self.stack.values.len(),
+            constants: self.constants.as_ptr(),
+            interrupt: Arc::as_ptr(&self.interrupt),
+        };
+
+        let offset = unsafe { code(&mut frame) };
+
+        self.stack.top = frame.top;
+        self.current.jump(offset as usize);
+        true
+    }
+}
+
+
+///
+/// Whether the compiled code runs `instruction` itself
+///
+/// The constant operands of the immediates are known while
+/// compiling so a constant of the wrong type is left to the
+/// interpreter to report
+///
+fn supported(instruction: &Instruction, constants: &[Constant]) -> bool {
+    use bytecode::*;
+
+    match instruction.opcode {
+        | COPY | SWAP | SET | PUSH | POP
+        | JMP | JIF | JNIF | IJIF | IJNIF
+        | JLTI | JLTF | JLEI | JLEF | JEQI | JEQF | JNEI | JNEF
+        | ADDI | ADDF | SUBI | SUBF | MULI | MULF | DIVF
+        | LTI | LTF | GTI | GTF | LEI | LEF | GEI | GEF | EQI | EQF | NEI | NEF => true,
+
+        | ADDIK | SUBIK | MULIK | LTIK | GTIK | LEIK | GEIK | EQIK | NEIK => {
+            matches!(instruction.operands[2], decoder::Value::Constant(v) if matches!(constants[v as usize], Constant::Int(_)))
+        },
+
+        | ADDFK | SUBFK | MULFK | LTFK | GTFK | LEFK | GEFK | EQFK | NEFK => {
+            matches!(instruction.operands[2], decoder::Value::Constant(v) if matches!(constants[v as usize], Constant::Float(_)))
+        },
+
+        _ => false,
+    }
+}
+
+
+/// The type of the operands of an arithmetic or comparison instruction
+#[derive(Clone, Copy)]
+enum Kind {
+    Int,
+    Float,
+}
+
+
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    constants: &'a [Constant],
+    instructions: &'a [Instruction],
+
+    /// The block of each instruction, by offset
+    blocks: HashMap<usize, Block>,
+    /// The blocks of the backward jumps and where they jump from and to
+    checks: Vec<(Block, usize, Block)>,
+
+    frame: Value,
+    regs: Value,
+    len: Value,
+    constant_table: Value,
+    interrupt: Value,
+    top: Variable,
+}
+
+
+impl<'a> Translator<'a> {
+    fn new(mut builder: FunctionBuilder<'a>, pointer: Type, constants: &'a [Constant], instructions: &'a [Instruction]) -> Self {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+
+        let frame = builder.block_params(entry)[0];
+        let flags = MemFlags::trusted();
+
+        let regs = builder.ins().load(pointer, flags, frame, REGS);
+        let len = builder.ins().load(pointer, flags, frame, LEN);
+        let constant_table = builder.ins().load(pointer, flags, frame, CONSTANTS);
+        let interrupt = builder.ins().load(pointer, flags, frame, INTERRUPT);
+
+        let top = Variable::from_u32(0);
+        builder.declare_var(top, pointer);
+        let value = builder.ins().load(pointer, flags, frame, TOP);
+        builder.def_var(top, value);
+
+        let blocks = instructions.iter().map(|x| (x.offset, builder.create_block())).collect();
+
+        Self { builder, constants, instructions, blocks, checks: vec![], frame, regs, len, constant_table, interrupt, top }
+    }
+
+
+    fn translate(mut self) {
+        let first = self.blocks[&self.instructions[0].offset];
+        self.builder.ins().jump(first, &[]);
+
+        for instruction in self.instructions {
+            let block = self.blocks[&instruction.offset];
+            self.builder.switch_to_block(block);
+
+            if !supported(instruction, self.constants) {
+                self.exit(instruction.offset);
+                continue
+            }
+
+            self.instruction(instruction);
+        }
+
+        self.interrupt_checks();
+
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        use bytecode::*;
+
+        let offset = instruction.offset;
+        let operands = &instruction.operands;
+
+        let reg = |index: usize| match operands[index] {
+            decoder::Value::Reg(v) => v,
+            _ => unreachable!(),
+        };
+
+        let block = |index: usize| match operands[index] {
+            decoder::Value::Block(v) => v as usize,
+            _ => unreachable!(),
+        };
+
+        match instruction.opcode {
+            COPY => {
+                let (tag, value) = self.load(reg(1));
+                self.store(reg(0), tag, value);
+            },
+
+
+            SWAP => {
+                let (a, b) = (reg(0), reg(1));
+                let (tag_a, value_a) = self.load(a);
+                let (tag_b, value_b) = self.load(b);
+                self.store(a, tag_b, value_b);
+                self.store(b, tag_a, value_a);
+            },
+
+
+            SET => {
+                let decoder::Value::Constant(index) = operands[1] else { unreachable!() };
+
+                let flags = MemFlags::trusted();
+                let at = index as i32 * SIZE as i32;
+                let tag = self.builder.ins().load(types::I64, flags, self.constant_table, at);
+                let value = self.builder.ins().load(types::I64, flags, self.constant_table, at + VALUE);
+                self.store(reg(0), tag, value);
+            },
+
+
+            // going over the stack is left to the interpreter to report
+            PUSH => {
+                let decoder::Value::U8(amount) = operands[0] else { unreachable!() };
+
+                let top = self.builder.use_var(self.top);
+                let top = self.builder.ins().iadd_imm(top, amount as i64);
+                let full = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, top, self.len);
+                self.exit_if(full, offset);
+
+                self.builder.def_var(self.top, top);
+            },
+
+
+            POP => {
+                let decoder::Value::U8(amount) = operands[0] else { unreachable!() };
+
+                let top = self.builder.use_var(self.top);
+                let top = self.builder.ins().iadd_imm(top, -(amount as i64));
+                self.builder.def_var(self.top, top);
+            },
+
+
+            JMP => {
+                let target = self.edge(offset, block(0));
+                self.builder.ins().jump(target, &[]);
+                return
+            },
+
+
+            JIF | JNIF => {
+                let cond = self.condition(reg(0), offset);
+
+                let (mut yes, mut no) = (self.edge(offset, block(1)), self.edge(offset, block(2)));
+                if instruction.opcode == JNIF {
+                    std::mem::swap(&mut yes, &mut no);
+                }
+
+                self.builder.ins().brif(cond, yes, &[], no, &[]);
+                return
+            },
+
+
+            IJIF | IJNIF => {
+                let cond = self.condition(reg(0), offset);
+
+                let (mut yes, mut no) = (self.edge(offset, block(1)), self.blocks[&instruction.next()]);
+                if instruction.opcode == IJNIF {
+                    std::mem::swap(&mut yes, &mut no);
+                }
+
+                self.builder.ins().brif(cond, yes, &[], no, &[]);
+                return
+            },
+
+
+            JLTI | JLTF | JLEI | JLEF | JEQI | JEQF | JNEI | JNEF => {
+                let (kind, int, float) = match instruction.opcode {
+                    JLTI => (Kind::Int, IntCC::SignedLessThan, FloatCC::LessThan),
+                    JLTF => (Kind::Float, IntCC::SignedLessThan, FloatCC::LessThan),
+                    JLEI => (Kind::Int, IntCC::SignedLessThanOrEqual, FloatCC::LessThanOrEqual),
+                    JLEF => (Kind::Float, IntCC::SignedLessThanOrEqual, FloatCC::LessThanOrEqual),
+                    JEQI => (Kind::Int, IntCC::Equal, FloatCC::Equal),
+                    JEQF => (Kind::Float, IntCC::Equal, FloatCC::Equal),
+                    JNEI => (Kind::Int, IntCC::NotEqual, FloatCC::NotEqual),
+                    _    => (Kind::Float, IntCC::NotEqual, FloatCC::NotEqual),
+                };
+
+                let lhs = self.operand(reg(0), kind, offset);
+                let rhs = self.operand(reg(1), kind, offset);
+                let cond = self.compare(kind, int, float, lhs, rhs);
+
+                let (yes, no) = (self.edge(offset, block(2)), self.edge(offset, block(3)));
+                self.builder.ins().brif(cond, yes, &[], no, &[]);
+                return
+            },
+
+
+            opcode => {
+                let (kind, operation) = operation(opcode);
+
+                let lhs = self.operand(reg(1), kind, offset);
+                let rhs = match operands[2] {
+                    decoder::Value::Reg(v) => self.operand(v, kind, offset),
+                    decoder::Value::Constant(v) => match self.constants[v as usize] {
+                        Constant::Int(v) => self.builder.ins().iconst(types::I64, v),
+                        Constant::Float(v) => self.builder.ins().f64const(v),
+                        _ => unreachable!(),
+                    },
+                    _ => unreachable!(),
+                };
+
+                let (tag, value) = match operation {
+                    Operation::Compare(int, float) => {
+                        let cond = self.compare(kind, int, float, lhs, rhs);
+                        (Data::TAG_BOOL, self.builder.ins().uextend(types::I64, cond))
+                    },
+
+                    // a zero divisor is left to the interpreter to report
+                    Operation::Div => {
+                        let zero = self.builder.ins().f64const(0.0);
+                        let is_zero = self.builder.ins().fcmp(FloatCC::Equal, rhs, zero);
+                        self.exit_if(is_zero, offset);
+                        (Data::TAG_F64, self.builder.ins().fdiv(lhs, rhs))
+                    },
+
+                    Operation::Add | Operation::Sub | Operation::Mul => {
+                        let ins = self.builder.ins();
+                        let value = match (kind, operation) {
+                            (Kind::Int, Operation::Add) => ins.iadd(lhs, rhs),
+                            (Kind::Int, Operation::Sub) => ins.isub(lhs, rhs),
+                            (Kind::Int, _) => ins.imul(lhs, rhs),
+                            (Kind::Float, Operation::Add) => ins.fadd(lhs, rhs),
+                            (Kind::Float, Operation::Sub) => ins.fsub(lhs, rhs),
+                            (Kind::Float, _) => ins.fmul(lhs, rhs),
+                        };
+
+                        match kind {
+                            Kind::Int => (Data::TAG_I64, value),
+                            Kind::Float => (Data::TAG_F64, value),
+                        }
+                    },
+                };
+
+                let tag = self.builder.ins().iconst(types::I64, tag as i64);
+                self.store(reg(0), tag, value);
+            },
+        }
+
+
+        let next = self.blocks[&instruction.next()];
+        self.builder.ins().jump(next, &[]);
+    }
+
+
+    /// The tag and the value of a register, the value as an `i64`
+    fn load(&mut self, reg: u8) -> (Value, Value) {
+        let at = reg as i32 * SIZE as i32;
+        let tag = self.builder.ins().load(types::I64, MemFlags::trusted(), self.regs, at);
+        let value = self.builder.ins().load(types::I64, MemFlags::trusted(), self.regs, at + VALUE);
+        (tag, value)
+    }
+
+
+    /// Writes a register, `value` can be an `i64` or an `f64`
+    fn store(&mut self, reg: u8, tag: Value, value: Value) {
+        let at = reg as i32 * SIZE as i32;
+        self.builder.ins().store(MemFlags::trusted(), tag, self.regs, at);
+        self.builder.ins().store(MemFlags::trusted(), value, self.regs, at + VALUE);
+    }
+
+
+    /// Leaves to the interpreter at `offset` if the tag of `reg` isn't `tag`
+    fn expect_tag(&mut self, reg: u8, tag: u64, offset: usize) {
+        let at = reg as i32 * SIZE as i32;
+        let found = self.builder.ins().load(types::I64, MemFlags::trusted(), self.regs, at);
+        let mismatch = self.builder.ins().icmp_imm(IntCC::NotEqual, found, tag as i64);
+        self.exit_if(mismatch, offset);
+    }
+
+
+    /// The value of an `i64` or `f64` register
+    fn operand(&mut self, reg: u8, kind: Kind, offset: usize) -> Value {
+        let (tag, ty) = match kind {
+            Kind::Int => (Data::TAG_I64, types::I64),
+            Kind::Float => (Data::TAG_F64, types::F64),
+        };
+
+        self.expect_tag(reg, tag, offset);
+        self.builder.ins().load(ty, MemFlags::trusted(), self.regs, reg as i32 * SIZE as i32 + VALUE)
+    }
+
+
+    /// The value of a `bool` register
+    fn condition(&mut self, reg: u8, offset: usize) -> Value {
+        self.expect_tag(reg, Data::TAG_BOOL, offset);
+        self.builder.ins().load(types::I8, MemFlags::trusted(), self.regs, reg as i32 * SIZE as i32 + VALUE)
+    }
+
+
+    fn compare(&mut self, kind: Kind, int: IntCC, float: FloatCC, lhs: Value, rhs: Value) -> Value {
+        match kind {
+            Kind::Int => self.builder.ins().icmp(int, lhs, rhs),
+            Kind::Float => self.builder.ins().fcmp(float, lhs, rhs),
+        }
+    }
+
+
+    ///
+    /// The block a jump from `from` to `to` goes to
+    ///
+    /// A backward jump might be a loop so it goes through a block
+    /// that leaves to the interpreter if there's an interrupt, which
+    /// runs the jump again and stops
+    ///
+    fn edge(&mut self, from: usize, to: usize) -> Block {
+        let target = self.blocks[&to];
+        if to > from {
+            return target
+        }
+
+        // the current block isn't done yet so
+        // it's filled in after every instruction
+        let check = self.builder.create_block();
+        self.checks.push((check, from, target));
+        check
+    }
+
+
+    /// Fills in the blocks `edge` made
+    fn interrupt_checks(&mut self) {
+        for (check, from, target) in std::mem::take(&mut self.checks) {
+            self.builder.switch_to_block(check);
+
+            // not `trusted` so it's loaded again on every iteration
+            let flag = self.builder.ins().load(types::I8, MemFlags::new(), self.interrupt, 0);
+            let exit = self.builder.create_block();
+            self.builder.ins().brif(flag, exit, &[], target, &[]);
+
+            self.builder.switch_to_block(exit);
+            self.exit(from);
+        }
+    }
+
+
+    /// Returns to the interpreter at `offset`
+    fn exit(&mut self, offset: usize) {
+        let top = self.builder.use_var(self.top);
+        self.builder.ins().store(MemFlags::trusted(), top, self.frame, TOP);
+
+        let offset = self.builder.ins().iconst(types::I32, offset as i64);
+        self.builder.ins().return_(&[offset]);
+    }
+
+
+    /// Returns to the interpreter at `offset` if `cond` holds
+    fn exit_if(&mut self, cond: Value, offset: usize) {
+        let exit = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(cond, exit, &[], next, &[]);
+
+        self.builder.switch_to_block(exit);
+        self.exit(offset);
+
+        self.builder.switch_to_block(next);
+    }
+}
+
+
+#[derive(Clone, Copy)]
+enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Compare(IntCC, FloatCC),
+}
+
+
+/// What an arithmetic or comparison opcode does
+fn operation(opcode: u8) -> (Kind, Operation) {
+    use bytecode::*;
+    use Operation::*;
+
+    let compare = |int, float| Compare(int, float);
+
+    match opcode {
+        ADDI | ADDIK => (Kind::Int, Add),
+        ADDF | ADDFK => (Kind::Float, Add),
+        SUBI | SUBIK => (Kind::Int, Sub),
+        SUBF | SUBFK => (Kind::Float, Sub),
+        MULI | MULIK => (Kind::Int, Mul),
+        MULF | MULFK => (Kind::Float, Mul),
+        DIVF => (Kind::Float, Div),
+
+        LTI | LTIK => (Kind::Int, compare(IntCC::SignedLessThan, FloatCC::LessThan)),
+        LTF | LTFK => (Kind::Float, compare(IntCC::SignedLessThan, FloatCC::LessThan)),
+        GTI | GTIK => (Kind::Int, compare(IntCC::SignedGreaterThan, FloatCC::GreaterThan)),
+        GTF | GTFK => (Kind::Float, compare(IntCC::SignedGreaterThan, FloatCC::GreaterThan)),
+        LEI | LEIK => (Kind::Int, compare(IntCC::SignedLessThanOrEqual, FloatCC::LessThanOrEqual)),
+        LEF | LEFK => (Kind::Float, compare(IntCC::SignedLessThanOrEqual, FloatCC::LessThanOrEqual)),
+        GEI | GEIK => (Kind::Int, compare(IntCC::SignedGreaterThanOrEqual, FloatCC::GreaterThanOrEqual)),
+        GEF | GEFK => (Kind::Float, compare(IntCC::SignedGreaterThanOrEqual, FloatCC::GreaterThanOrEqual)),
+        EQI | EQIK => (Kind::Int, compare(IntCC::Equal, FloatCC::Equal)),
+        EQF | EQFK => (Kind::Float, compare(IntCC::Equal, FloatCC::Equal)),
+        NEI | NEIK => (Kind::Int, compare(IntCC::NotEqual, FloatCC::NotEqual)),
+        NEF | NEFK => (Kind::Float, compare(IntCC::NotEqual, FloatCC::NotEqual)),
+
+        _ => unreachable!("not an arithmetic or comparison opcode"),
+    }
+}
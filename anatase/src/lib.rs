@@ -1,16 +1,224 @@
-use std::{fmt::Debug, mem::size_of, borrow::BorrowMut};
+use std::{fmt::Debug, mem::size_of, borrow::BorrowMut, sync::{Arc, atomic::AtomicBool}, collections::{HashMap, HashSet}};
+
+use errors::Location;
+use garbage_collector::{MemoryPool, ObjectData};
+use native::NativeFunction;
+use program::{Program, Constant};
+use verifier::VerifyError;
 
 mod runtime;
-mod bytecode;
+mod shift;
+mod threaded;
+#[cfg(feature = "jit")]
+mod jit;
+pub mod aot;
+pub mod bytecode;
+pub mod backtrace;
+pub mod debug_info;
+pub mod debugger;
+pub mod decoder;
+pub mod embed;
+pub mod errors;
+pub mod fuel;
 pub mod garbage_collector;
+pub mod interrupt;
+pub mod native;
+pub mod profiler;
+pub mod program;
+pub mod symbols;
+pub mod trace;
+pub mod verifier;
+
+
+const STACK_SIZE : usize = 1_000_000 / size_of::<Data>();
+pub(crate) const HEAP_SIZE : usize = 1 << 16;
+const CALL_DEPTH : usize = 10_000;
 
 
 #[derive(Debug)]
 pub struct VM<const DEBUG: bool> {
     pub stack: Stack<DEBUG>,
-    pub callstack: Vec<Code<DEBUG>>,
-    pub current: Code<DEBUG>,
-    pub constants: Box<[Data]>,
+    pub memory: MemoryPool,
+    pub(crate) callstack: Vec<Code<DEBUG>>,
+    /// The most frames `callstack` can hold
+    max_depth: usize,
+    /// The frames of the callstacks `VM::call` put aside to
+    /// run a function, they still count towards `max_depth`
+    pub(crate) nested_depth: usize,
+    /// How many `VM::call`s are running inside of each other
+    pub(crate) nested_calls: usize,
+    /// The objects the running natives allocated, the garbage collector
+    /// keeps them alive until the `callnative` of their native returns
+    pub(crate) temp_roots: Vec<usize>,
+    /// How many natives are running inside of each other
+    pub(crate) running_natives: usize,
+    /// The objects `VM::call` returned that the host hasn't released
+    pub(crate) held: Vec<usize>,
+    pub(crate) current: Code<DEBUG>,
+    pub(crate) constants: Box<[Data]>,
+    /// The registered natives by name
+    natives: HashMap<String, NativeFunction<DEBUG>>,
+    /// The natives the imports of the program resolved to, in order
+    pub(crate) imports: Box<[NativeFunction<DEBUG>]>,
+    /// The offsets the debugger stops at
+    breakpoints: HashSet<usize>,
+    /// Set by an `InterruptHandle` to stop the program
+    interrupt: Arc<AtomicBool>,
+    /// The native code of the hot functions
+    #[cfg(feature = "jit")]
+    jit: jit::Jit,
+
+    // `current` and `callstack` point into the bytecode of
+    // the program, so it has to outlive them
+    program: Arc<Program>,
+}
+
+
+impl VM<true> {
+    /// Creates a `VM` that checks every instruction while it runs
+    pub fn new(program: Arc<Program>) -> Self {
+        Self::with_program(program)
+    }
+}
+
+
+impl VM<false> {
+    ///
+    /// Creates a `VM` that skips the runtime checks
+    ///
+    /// This is only safe for bytecode that passed verification
+    /// so the verification error is returned for any other program
+    ///
+    pub fn new(program: Arc<Program>) -> Result<Self, VerifyError> {
+        program.verification().map_err(Clone::clone)?;
+        Ok(Self::with_program(program))
+    }
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    fn with_program(program: Arc<Program>) -> Self {
+        let bytecode = program.bytecode().as_ptr_range();
+
+        // every `VM` has its own heap so the string constants have to be
+        // allocated for each one, they take the first slots and the
+        // program still gets `HEAP_SIZE` free slots after them
+        let strings = program.constants().iter().filter_map(|x| match x {
+            Constant::Str(v) => Some(ObjectData::String(v.to_string())),
+            _ => None,
+        });
+
+        let memory = MemoryPool::with_objects(strings, HEAP_SIZE);
+
+        let mut strings = 0;
+        let constants = program.constants().iter().map(|x| match x {
+            Constant::Int(v) => Data::new_i64(*v),
+            Constant::Float(v) => Data::new_f64(*v),
+            Constant::Bool(v) => Data::new_bool(*v),
+            Constant::Str(_) => {
+                strings += 1;
+                Data::new_str(strings - 1)
+            },
+        }).collect();
+
+        Self {
+            stack: Stack::with_capacity(STACK_SIZE),
+            memory,
+            callstack: Vec::with_capacity(128),
+            max_depth: CALL_DEPTH,
+            nested_depth: 0,
+            nested_calls: 0,
+            temp_roots: Vec::new(),
+            running_natives: 0,
+            held: Vec::new(),
+            current: Code::new(bytecode.start, bytecode.start, bytecode.end, 0, 0, 0, 0),
+            constants,
+            natives: HashMap::new(),
+            imports: Box::new([]),
+            breakpoints: HashSet::new(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "jit")]
+            jit: jit::Jit::new(),
+            program,
+        }
+    }
+
+
+    pub fn program(&self) -> &Arc<Program> {
+        &self.program
+    }
+
+
+    ///
+    /// Replaces the default limits, going over either of them
+    /// stops the program with a `StackOverflow`
+    ///
+    /// The stack never shrinks below the slots that are in use
+    ///
+    pub fn set_limits(&mut self, limits: Limits) {
+        let slots = limits.stack_slots.max(self.stack.top + 1);
+        self.stack.values.resize(slots, Data::new_uninit());
+        self.stack.values.shrink_to_fit();
+
+        self.max_depth = limits.call_depth;
+    }
+
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            call_depth: self.max_depth,
+            stack_slots: self.stack.values.len(),
+        }
+    }
+
+
+    /// Whether `call` can push another frame
+    #[inline(always)]
+    pub(crate) fn can_call(&self) -> bool {
+        self.nested_depth + self.callstack.len() < self.max_depth
+    }
+
+
+    ///
+    /// Formats `data` the way `print` does, with the contents of heap objects
+    ///
+    /// Arrays can contain themselves so only the strings inside of
+    /// an array are expanded, nested arrays are left as their index
+    ///
+    pub fn format(&self, data: Data) -> String {
+        match data.as_object().map(|x| self.memory.get(x)) {
+            Some(ObjectData::String(v)) => format!("str {v:?}"),
+            Some(ObjectData::Array(v)) => {
+                let values : Vec<_> = v.iter().map(|x| match self.memory.string(*x) {
+                    Some(v) => format!("str {v:?}"),
+                    None => format!("{x:?}"),
+                }).collect();
+
+                format!("array [{}]", values.join(", "))
+            },
+            _ => format!("{data:?}"),
+        }
+    }
+}
+
+
+///
+/// The limits are checked in both build modes, unlike
+/// most runtime checks
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The most calls that can be active at once
+    pub call_depth: usize,
+    /// The size of the stack the registers of every frame live in
+    pub stack_slots: usize,
+}
+
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { call_depth: CALL_DEPTH, stack_slots: STACK_SIZE }
+    }
 }
 
 
@@ -25,7 +233,7 @@ pub struct Stack<const DEBUG: bool> {
 impl<const DEBUG: bool> Stack<DEBUG> {
     pub fn with_capacity(cap: usize) -> Self {
         Self {
-            values: Vec::with_capacity(cap),
+            values: vec![Data::new_uninit(); cap],
             bottom: 0,
             top   : 0,
         }
@@ -81,13 +289,18 @@ impl<const DEBUG: bool> Stack<DEBUG> {
     }
 
 
+    #[inline(always)]
+    fn can_push(&self, amount: usize) -> bool {
+        self.top
+            .checked_add(amount)
+            .is_some_and(|x| x < self.values.len())
+    }
+
+
     #[inline(always)]
     fn push(&mut self, amount: usize) {
         if DEBUG {
-            assert!(self.top
-                .checked_add(amount)
-                .is_some_and(|x| x < self.values.capacity())
-            );
+            assert!(self.can_push(amount));
         }
 
         self.top += amount;
@@ -95,7 +308,9 @@ impl<const DEBUG: bool> Stack<DEBUG> {
 }
 
 
+// the compiled code of the jit relies on the layout
 #[derive(Clone, Copy)]
+#[repr(C)]
 pub struct Data {
     tag: u64,
     inner: InnerData,
@@ -108,7 +323,7 @@ impl Data {
         Self { tag, inner } 
     }
     
-    pub fn new_uninit() -> Self { Self::new(Self::TAG_UNINIT, InnerData { uninit: () })}
+    pub fn new_uninit() -> Self { Self::new(Self::TAG_UNINIT, InnerData { U64: 0 })}
     #[inline(always)]
     pub fn new_i64(val: i64) -> Self { Self::new(Self::TAG_I64, InnerData { I64: val }) }
     #[inline(always)]
@@ -117,22 +332,128 @@ impl Data {
     pub fn new_f64(val: f64) -> Self { Self::new(Self::TAG_F64, InnerData { F64: val }) }
     #[inline(always)]
     pub fn new_bool(val: bool) -> Self { Self::new(Self::TAG_BOOL, InnerData { Bool: val }) }
+    #[inline(always)]
+    pub(crate) fn new_str(index: usize) -> Self { Self::new(Self::TAG_STR, InnerData { Obj: index as u64 }) }
+    #[inline(always)]
+    pub(crate) fn new_arr(index: usize) -> Self { Self::new(Self::TAG_ARR, InnerData { Obj: index as u64 }) }
 
     const TAG_UNINIT : u64 = 0;
     const TAG_I64 : u64 = 1;
     const TAG_U64 : u64 = 2;
     const TAG_F64 : u64 = 3;
     const TAG_BOOL : u64 = 4;
+    const TAG_STR : u64 = 5;
+    const TAG_ARR : u64 = 6;
+
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.tag {
+            Self::TAG_I64 => Some(unsafe { self.inner.I64 }),
+            _ => None,
+        }
+    }
+
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.tag {
+            Self::TAG_U64 => Some(unsafe { self.inner.U64 }),
+            _ => None,
+        }
+    }
+
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.tag {
+            Self::TAG_F64 => Some(unsafe { self.inner.F64 }),
+            _ => None,
+        }
+    }
+
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.tag {
+            Self::TAG_BOOL => Some(unsafe { self.inner.Bool }),
+            _ => None,
+        }
+    }
+
+
+    ///
+    /// Reads a condition without looking at its tag
+    ///
+    /// Only the first byte is compared against zero, every kind of
+    /// data sets it so this is defined even if a host or an unchecked
+    /// `VM` hands over something that isn't a bool
+    ///
+    #[inline(always)]
+    pub(crate) fn is_true(self) -> bool {
+        unsafe { self.inner.Byte != 0 }
+    }
+
+
+    /// Returns the index of the heap object this value refers to
+    pub fn as_object(&self) -> Option<usize> {
+        match self.tag {
+            | Self::TAG_STR
+            | Self::TAG_ARR => Some(unsafe { self.inner.Obj } as usize),
+            _ => None,
+        }
+    }
+
+
+    ///
+    /// Returns the tag and the raw bits of the value
+    ///
+    /// Uninitialised values and the unused bytes of a
+    /// bool are read as zero
+    ///
+    pub(crate) fn to_bits(self) -> (u64, u64) {
+        let bits = match self.tag {
+            Self::TAG_UNINIT => 0,
+            Self::TAG_BOOL => unsafe { self.inner.Bool as u64 },
+            _ => unsafe { self.inner.U64 },
+        };
+
+        (self.tag, bits)
+    }
+
+
+    /// The inverse of `Data::to_bits`
+    pub(crate) fn from_bits(tag: u64, bits: u64) -> Self {
+        match tag {
+            Self::TAG_UNINIT => Self::new_uninit(),
+            Self::TAG_BOOL => Self::new_bool(bits != 0),
+            _ => Self::new(tag, InnerData { U64: bits }),
+        }
+    }
+
+
+    pub fn tag_name(tag: u64) -> &'static str {
+        match tag {
+            Self::TAG_UNINIT => "uninit",
+            Self::TAG_I64 => "int",
+            Self::TAG_U64 => "uint",
+            Self::TAG_F64 => "float",
+            Self::TAG_BOOL => "bool",
+            Self::TAG_STR => "str",
+            Self::TAG_ARR => "array",
+            _ => "unknown",
+        }
+    }
 }
 
 
 #[derive(Clone, Copy)]
+#[repr(C)]
 union InnerData {
     I64: i64,
     U64: u64,
     F64: f64,
     Bool: bool,
-    uninit: (),
+    /// The index of an object in the `MemoryPool`
+    Obj: u64,
+    /// The first byte of any of the others, see `Data::is_true`
+    Byte: u8,
 }
 
 
@@ -145,13 +466,18 @@ pub struct Code<const DEBUG: bool> {
     return_to: u8,
     offset: usize,
     argc: u8,
+    function: usize,
+    /// Whether a `callw` made this frame out of the caller's registers,
+    /// `argc` then stays the size of the window even after a `tailcall`
+    window: bool,
 }
 
 
 impl<const DEBUG: bool> Code<DEBUG> {
-    pub fn new(ptr: *const u8, base: *const u8, top: *const u8, return_to: u8, offset: usize, argc: u8) -> Self {
+    /// `top` is the end of the bytecode, one past the last instruction
+    pub(crate) fn new(ptr: *const u8, base: *const u8, top: *const u8, return_to: u8, offset: usize, argc: u8, function: usize) -> Self {
         let slf = Self { 
-            ptr, base, return_to, offset, top, argc
+            ptr, base, return_to, offset, top, argc, function, window: false,
         };
 
         slf.assert_ptr();
@@ -160,23 +486,32 @@ impl<const DEBUG: bool> Code<DEBUG> {
 
     #[inline(always)]
     fn next(&mut self) -> u8 {
+        self.assert_readable(1);
+
         unsafe {
             let data = *self.ptr;
             self.ptr = self.ptr.add(1);
-
-            self.assert_ptr();
             
             data
         }
     }
 
     
+    /// Returns the next byte without moving past it
+    #[inline(always)]
+    fn peek(&self) -> u8 {
+        self.assert_readable(1);
+        unsafe { *self.ptr }
+    }
+
+    
     #[inline(always)]
     fn read_as<T>(&mut self) -> T {
+        self.assert_readable(size_of::<T>());
+
         unsafe {
             let data = self.ptr.cast::<T>().read_unaligned();
             self.ptr = self.ptr.add(size_of::<T>());
-            self.assert_ptr();
             data
         }
     }
@@ -191,12 +526,29 @@ impl<const DEBUG: bool> Code<DEBUG> {
     }
 
 
+    fn location_of(&self, ptr: *const u8) -> Location {
+        Location {
+            offset: ptr as usize - self.base as usize,
+            function: self.function,
+        }
+    }
+
+
     fn assert_ptr(&self) {
         if DEBUG {
             assert!(self.ptr >= self.base);
             assert!(self.ptr <= self.top);
         }
     }
+
+
+    #[inline(always)]
+    fn assert_readable(&self, amount: usize) {
+        if DEBUG {
+            self.assert_ptr();
+            assert!(self.top as usize - self.ptr as usize >= amount);
+        }
+    }
 }
 
 
@@ -208,6 +560,8 @@ impl Debug for Data {
                 Self::TAG_U64 => write!(f, "uint {:?}", self.inner.U64),
                 Self::TAG_F64 => write!(f, "float {:?}", self.inner.F64),
                 Self::TAG_BOOL => write!(f, "bool {:?}", self.inner.Bool),
+                Self::TAG_STR => write!(f, "str #{:?}", self.inner.Obj),
+                Self::TAG_ARR => write!(f, "array #{:?}", self.inner.Obj),
                 Self::TAG_UNINIT => write!(f, "uninit"),
                 _ => panic!("unexpected type {}", self.tag),
             }
@@ -1,95 +1,117 @@
-#![feature(iter_next_chunk)]
+use std::{time::Instant, sync::Arc, env, fs::File, io::BufWriter};
 
-use std::{time::Instant, mem::size_of, env, sync::Arc, cell::Cell};
-
-use anatase::{VM, Stack, Code, Data, garbage_collector::{MemoryPool, Object, GarbageCollector, SendPtr, ObjectData}};
-use archiver::Packed;
+use anatase::{VM, program::Program, profiler::{OpcodeProfile, FunctionProfile, Weight}, trace::Tracer};
 
 fn main() {
     let data = std::fs::read("test.anb").unwrap();
-    let data = Packed::from_bytes(&data).unwrap();
-    let mut data : Vec<_> = data.into();
-
-    let bytecode = data.pop().unwrap();
-    let constants = data.pop().unwrap();
-    let constants = parse_constants(&constants.0);
-
-    let mut vm = VM::<true> {
-        stack: Stack::with_capacity(1_000_000 / size_of::<Data>()),
-        callstack: Vec::with_capacity(128),
-        current: Code::new(
-            bytecode.0.as_ptr(),
-            bytecode.0.as_ptr(),
-            bytecode.0.last().unwrap() as *const u8,
-            0,
-            0,
-            0,
-        ),
-        constants: constants.into(),
+    let program = match Program::from_bytes(&data) {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        },
     };
-    
 
-    if let Ok(v) = env::var("ANATASE_WATCH_REG") {
-        let delay : usize = env::var("ANATASE_WATCH_PERIOD").map(|x| x.parse().unwrap()).unwrap_or(200usize);
+    // the runtime checks of `VM::<true>` still assume the bytecode
+    // is well formed, so bytecode that fails verification isn't run
+    match VM::<false>::new(program) {
+        Ok(vm) => execute(vm),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        },
+    }
+}
 
-        let mut regs = vec![];
-        for i in v.split(',') {
-            let r : u8 = i.parse().unwrap();
-            regs.push(vm.stack.reg_ptr(r) as usize);
-        }
 
-        let base = vm.stack.reg_ptr(0) as usize;
-        std::thread::spawn(move || {
-            let mut timer = Instant::now();
-            loop {
-                if timer.elapsed().as_millis() > delay as u128 {
-                    timer = Instant::now();
-                    for r in &regs {
-                        println!("WATCH {}: {:?}", (*r - base) / size_of::<Data>(), unsafe { *(*r as *const Data) });
-                        
-                    }
-                }
-            }
-        });
+fn execute<const DEBUG: bool>(mut vm: VM<DEBUG>) {
+    if let Err(e) = vm.link() {
+        eprintln!("error: {e}");
+        std::process::exit(1);
     }
-    
+
+    // a comma separated list of 'opcodes' or 'functions', 'time' and
+    // 'json', only one of the profilers can run and 'opcodes' wins
+    let profile = env::var("ANATASE_PROFILE").unwrap_or_default();
+    let profile : Vec<_> = profile.split(',').collect();
+
+    let mut opcodes = profile.contains(&"opcodes").then(|| OpcodeProfile::new(profile.contains(&"time")));
+    let mut functions = profile.contains(&"functions").then(FunctionProfile::new);
+
+    let mut tracer = tracer(&vm);
 
     let timer = Instant::now();
-    vm.run();
+    let result = match (&mut opcodes, &mut functions, &mut tracer) {
+        (Some(v), _, _) => vm.run_profiled(v),
+        (None, Some(v), _) => vm.run_function_profiled(v),
+        (None, None, Some(v)) => vm.run_traced(v),
+        (None, None, None) => vm.run(),
+    };
     let end = timer.elapsed();
+
+    if let Some(Err(e)) = tracer.map(Tracer::finish) {
+        eprintln!("error: can't write the trace: {e}");
+    }
+
+    if let Some(opcodes) = opcodes {
+        match profile.contains(&"json") {
+            true => println!("{}", opcodes.to_json()),
+            false => print!("{}", opcodes.report()),
+        }
+    } else if let Some(functions) = functions {
+        // the folded stacks go to the file in ANATASE_FLAMEGRAPH
+        let path = env::var("ANATASE_FLAMEGRAPH").unwrap_or_else(|_| "profile.folded".to_string());
+        let weight = if profile.contains(&"time") { Weight::Time } else { Weight::Instructions };
+
+        let table = vm.program().functions();
+        print!("{}", functions.report(table));
+
+        if let Err(e) = std::fs::write(&path, functions.folded(table, weight)) {
+            eprintln!("error: can't write the profile to '{path}': {e}");
+        }
+    }
+
+    if let Err(e) = result {
+        let program = vm.program();
+
+        eprintln!("error: {e}");
+        eprint!("{}", vm.backtrace().render(program.functions(), program.debug_info()));
+        std::process::exit(1);
+    }
     
     
     println!("finished in {}", end.as_secs_f64());
-    println!("result is {:?}", vm.stack.reg(0));
+    println!("result is {}", vm.format(vm.stack.reg(0)));
 }
 
 
-fn parse_constants(bytes: &[u8]) -> Vec<Data> {
-    let mut iter = bytes.iter().copied();
-    let mut vec = vec![];
-
-    while let Some(v) = iter.next() {
-        match v {
-            0 => {
-                let bytes = iter.next_chunk::<8>().unwrap();
-                let num = i64::from_le_bytes(bytes);
-                vec.push(Data::new_i64(num));
-            }
-            1 => {
-                let bytes = iter.next_chunk::<8>().unwrap();
-                let num = f64::from_le_bytes(bytes);
-                vec.push(Data::new_f64(num));
-            }
-            2 => {
-                todo!()
-            }
-
-            3 => vec.push(Data::new_bool(true)),
-            4 => vec.push(Data::new_bool(false)),
-
-            _ => unreachable!(),
-        }
-    }
+///
+/// Creates the tracer for the file in ANATASE_TRACE which traces the
+/// comma separated list of functions in ANATASE_TRACE_FUNCTIONS, or
+/// every function if it isn't set
+///
+fn tracer<const DEBUG: bool>(vm: &VM<DEBUG>) -> Option<Tracer<BufWriter<File>>> {
+    let path = env::var("ANATASE_TRACE").ok()?;
+    let file = match File::create(&path) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: can't create the trace file '{path}': {e}");
+            std::process::exit(1);
+        },
+    };
 
-    vec
-}
\ No newline at end of file
+    let tracer = Tracer::new(BufWriter::new(file));
+    let Ok(names) = env::var("ANATASE_TRACE_FUNCTIONS")
+    else { return Some(tracer) };
+
+    let functions = vm.program().functions();
+    let filter = names.split(',').filter_map(|name| match functions.get(name) {
+        Some(v) => Some(v.offset),
+        None => {
+            eprintln!("warning: can't trace '{name}', the function doesn't exist");
+            None
+        },
+    });
+
+    Some(tracer.with_filter(filter.collect::<Vec<_>>()))
+}
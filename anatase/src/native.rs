@@ -0,0 +1,100 @@
+use std::fmt::Display;
+
+use crate::{VM, Data, errors::{VmError, Location}};
+
+
+///
+/// A function implemented by the host that `.an` programs
+/// can call with `callnative`
+///
+/// The arguments are copies of the registers given to
+/// `callnative` and the returned value is written to its
+/// destination register
+///
+/// Everything a native allocates with `VM::alloc` stays alive
+/// until it returns, even if a later allocation collects garbage
+///
+pub type NativeFunction<const DEBUG: bool> = fn(&mut VM<DEBUG>, &[Data]) -> Result<Data, VmError>;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    MissingImport(String),
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Registers `function` under `name`, replacing any
+    /// function that was already registered with that name
+    ///
+    /// The imports of the program are resolved when it starts
+    /// running, or by `VM::link`
+    ///
+    pub fn register_native(&mut self, name: &str, function: NativeFunction<DEBUG>) {
+        self.natives.insert(name.to_string(), function);
+    }
+
+
+    ///
+    /// Resolves every import of the program against the registered
+    /// natives, failing on the first import that isn't registered
+    ///
+    /// Running a program links it if it isn't linked yet, calling
+    /// this reports a missing native by name before anything runs
+    /// and picks up natives that were replaced after linking
+    ///
+    pub fn link(&mut self) -> Result<(), LinkError> {
+        let imports = self.program.imports().iter()
+            .map(|x| self.natives.get(x).copied().ok_or_else(|| LinkError::MissingImport(x.clone())))
+            .collect::<Result<_, _>>()?;
+
+        self.imports = imports;
+        Ok(())
+    }
+
+
+    /// Links the program unless every import is already resolved
+    pub(crate) fn ensure_linked(&mut self) -> Result<(), LinkError> {
+        if self.imports.len() == self.program.imports().len() {
+            return Ok(())
+        }
+
+        self.link()
+    }
+
+
+    ///
+    /// Links the program before it runs, so a missing native stops
+    /// it before the first instruction instead of at the `callnative`
+    /// that needs it
+    ///
+    pub(crate) fn link_before_running(&mut self) -> Result<(), VmError> {
+        self.ensure_linked().map_err(|LinkError::MissingImport(name)| {
+            let index = self.program.imports().iter().position(|x| *x == name);
+            VmError::UnresolvedImport(self.location(), index.unwrap_or_default() as u16)
+        })
+    }
+
+
+    ///
+    /// The location of the instruction that is executing
+    ///
+    /// Inside of a native this is the `callnative` that called it
+    ///
+    pub fn location(&self) -> Location {
+        self.current.location_of(self.current.ptr)
+    }
+}
+
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::MissingImport(name) => write!(f, "the native function '{name}' isn't registered"),
+        }
+    }
+}
+
+
+impl std::error::Error for LinkError {}
@@ -0,0 +1,288 @@
+use std::{time::{Duration, Instant}, fmt::Write, collections::HashMap};
+
+use crate::{VM, bytecode, errors::VmError, symbols::FunctionTable};
+
+
+///
+/// How many times each opcode was executed and, if
+/// timing was enabled, how long they took in total
+///
+#[derive(Debug, Clone)]
+pub struct OpcodeProfile {
+    counts: Box<[u64; 256]>,
+    times: Option<Box<[Duration; 256]>>,
+}
+
+
+///
+/// The instructions executed and the time spent in each function,
+/// split up by the stack of calls that led to it
+///
+/// Functions are identified by their offset like in a `Location`
+///
+#[derive(Debug, Clone, Default)]
+pub struct FunctionProfile {
+    stacks: HashMap<Vec<usize>, (u64, Duration)>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weight {
+    Instructions,
+    /// In nanoseconds
+    Time,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpcodeEntry {
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub count: u64,
+    pub time: Option<Duration>,
+}
+
+
+impl OpcodeProfile {
+    ///
+    /// Timing every instruction costs far more than executing
+    /// most of them so the times are only useful relative to
+    /// each other
+    ///
+    pub fn new(timing: bool) -> Self {
+        Self {
+            counts: Box::new([0; 256]),
+            times: timing.then(|| Box::new([Duration::ZERO; 256])),
+        }
+    }
+
+
+    /// The executed opcodes, the most expensive first
+    pub fn entries(&self) -> Vec<OpcodeEntry> {
+        let mut entries : Vec<_> = (0..=255u8)
+            .filter(|&x| self.counts[x as usize] != 0)
+            .map(|x| OpcodeEntry {
+                opcode: x,
+                mnemonic: bytecode::layout(x).map_or("<invalid>", |x| x.0),
+                count: self.counts[x as usize],
+                time: self.times.as_ref().map(|t| t[x as usize]),
+            })
+            .collect();
+
+        entries.sort_by(|a, b| b.time.cmp(&a.time).then(b.count.cmp(&a.count)));
+        entries
+    }
+
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+
+    /// Renders the profile as a table
+    pub fn report(&self) -> String {
+        let total = self.total().max(1);
+        let mut string = String::new();
+
+        let _ = write!(string, "{:<10} {:>14} {:>8}", "opcode", "count", "%");
+        if self.times.is_some() {
+            let _ = write!(string, " {:>14} {:>10}", "time (ms)", "avg (ns)");
+        }
+        let _ = writeln!(string);
+
+        for entry in self.entries() {
+            let percent = entry.count as f64 / total as f64 * 100.0;
+            let _ = write!(string, "{:<10} {:>14} {:>7.2}%", entry.mnemonic, entry.count, percent);
+
+            if let Some(time) = entry.time {
+                let avg = time.as_nanos() as f64 / entry.count as f64;
+                let _ = write!(string, " {:>14.3} {:>10.1}", time.as_secs_f64() * 1000.0, avg);
+            }
+
+            let _ = writeln!(string);
+        }
+
+        string
+    }
+
+
+    /// Renders the profile as a JSON array with one object per opcode
+    pub fn to_json(&self) -> String {
+        let entries : Vec<_> = self.entries().iter().map(|x| {
+            let time = x.time.map_or("null".to_string(), |x| x.as_nanos().to_string());
+            format!(r#"{{"opcode":{},"mnemonic":"{}","count":{},"time_ns":{}}}"#, x.opcode, x.mnemonic, x.count, time)
+        }).collect();
+
+        format!("[{}]", entries.join(","))
+    }
+}
+
+
+impl FunctionProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+    ///
+    /// Returns the instructions executed and the time spent in
+    /// every function itself, not counting the functions it called,
+    /// the most expensive first
+    ///
+    pub fn functions(&self) -> Vec<(usize, u64, Duration)> {
+        let mut functions : HashMap<usize, (u64, Duration)> = HashMap::new();
+        for (stack, (count, time)) in &self.stacks {
+            let Some(&function) = stack.last() else { continue };
+
+            let entry = functions.entry(function).or_default();
+            entry.0 += count;
+            entry.1 += *time;
+        }
+
+        let mut functions : Vec<_> = functions.into_iter().map(|(f, (c, t))| (f, c, t)).collect();
+        functions.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)).then(a.0.cmp(&b.0)));
+        functions
+    }
+
+
+    ///
+    /// Renders the profile in the folded stack format flamegraph
+    /// tools take, one `caller;callee count` line per call stack
+    ///
+    pub fn folded(&self, functions: &FunctionTable, weight: Weight) -> String {
+        let mut lines : Vec<_> = self.stacks.iter().map(|(stack, (count, time))| {
+            let names : Vec<_> = stack.iter().map(|x| functions.name_of(*x)).collect();
+            let value = match weight {
+                Weight::Instructions => *count as u128,
+                Weight::Time => time.as_nanos(),
+            };
+
+            format!("{} {value}", names.join(";"))
+        }).collect();
+
+        lines.sort();
+
+        let mut string = lines.join("\n");
+        string.push('\n');
+        string
+    }
+
+
+    /// Renders the time spent in each function as a table
+    pub fn report(&self, functions: &FunctionTable) -> String {
+        let mut string = String::new();
+        let _ = writeln!(string, "{:<24} {:>14} {:>14}", "function", "instructions", "time (ms)");
+
+        for (function, count, time) in self.functions() {
+            let _ = writeln!(string, "{:<24} {:>14} {:>14.3}", functions.name_of(function), count, time.as_secs_f64() * 1000.0);
+        }
+
+        string
+    }
+
+
+    fn add(&mut self, stack: &[usize], count: u64, time: Duration) {
+        if let Some(v) = self.stacks.get_mut(stack) {
+            v.0 += count;
+            v.1 += time;
+            return
+        }
+
+        self.stacks.insert(stack.to_vec(), (count, time));
+    }
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Runs the program like `VM::run` while adding every
+    /// executed instruction to `profile`
+    ///
+    /// This is a separate loop so `VM::run` doesn't have
+    /// to check whether it's profiling
+    ///
+    pub fn run_profiled(&mut self, profile: &mut OpcodeProfile) -> Result<(), VmError> {
+        self.link_before_running()?;
+
+        loop {
+            let opcode = self.current.peek();
+            profile.counts[opcode as usize] += 1;
+
+            let finished = match profile.times {
+                Some(ref mut times) => {
+                    let timer = Instant::now();
+                    let finished = self.execute()?;
+                    times[opcode as usize] += timer.elapsed();
+                    finished
+                },
+
+                None => self.execute()?,
+            };
+
+            if finished {
+                return Ok(())
+            }
+        }
+    }
+
+
+    ///
+    /// Runs the program like `VM::run` while attributing the executed
+    /// instructions and the time to the function executing them
+    ///
+    /// The time is only measured when a `call`, `tailcall` or `ret`
+    /// changes the function executing so it's far cheaper than timing
+    /// every instruction
+    ///
+    pub fn run_function_profiled(&mut self, profile: &mut FunctionProfile) -> Result<(), VmError> {
+        self.link_before_running()?;
+
+        let mut stack : Vec<_> = self.callstack.iter()
+            .map(|x| x.function)
+            .chain(std::iter::once(self.current.function))
+            .collect();
+
+        let mut count = 0;
+        let mut timer = Instant::now();
+
+        let result = loop {
+            let depth = self.callstack.len();
+            let function = self.current.function;
+            let finished = match self.execute() {
+                Ok(v) => v,
+                Err(e) => break Err(e),
+            };
+
+            count += 1;
+
+            // a `tailcall` replaces the function without a new frame
+            let replaced = self.callstack.len() == depth && self.current.function != function;
+
+            if finished || self.callstack.len() != depth || replaced {
+                profile.add(&stack, count, timer.elapsed());
+                count = 0;
+                timer = Instant::now();
+
+                if replaced {
+                    if let Some(last) = stack.last_mut() {
+                        *last = self.current.function;
+                    }
+                } else if self.callstack.len() > depth {
+                    stack.push(self.current.function);
+                } else {
+                    stack.pop();
+                }
+            }
+
+            if finished {
+                break Ok(())
+            }
+        };
+
+        if count != 0 {
+            profile.add(&stack, count, timer.elapsed());
+        }
+
+        result
+    }
+}
@@ -0,0 +1,199 @@
+use std::{fmt::Display, sync::OnceLock};
+
+use archiver::Packed;
+
+use crate::{HEAP_SIZE, symbols::{FunctionTable, take, take_str}, debug_info::DebugInfo, threaded::Threaded, verifier::{self, VerifyError}};
+
+
+///
+/// A loaded `.anb` file
+///
+/// The program owns everything a `VM` executes so a `VM` holding
+/// it can never point to freed bytecode, and since it's never
+/// mutated it can be shared between any number of `VM`s
+///
+#[derive(Debug)]
+pub struct Program {
+    constants: Box<[Constant]>,
+    bytecode: Box<[u8]>,
+    functions: FunctionTable,
+    /// The names of the natives the program calls
+    imports: Box<[String]>,
+    debug_info: Option<DebugInfo>,
+
+    verification: Result<(), VerifyError>,
+    /// Decoded the first time a `VM<false>` runs the program
+    threaded: OnceLock<Threaded>,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(Box<str>),
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    InvalidArchive,
+    MissingSection(&'static str),
+    InvalidSection(&'static str),
+    /// The program can't run without runtime checks
+    Verification(VerifyError),
+    /// The string constants don't fit in the heap of a `VM`
+    TooManyConstants(usize),
+}
+
+
+impl Program {
+    ///
+    /// Creates a program and verifies its bytecode
+    ///
+    /// Every `VM` allocates the string constants on its own heap,
+    /// on top of the room the program gets, so a program can't
+    /// have more of them than that room
+    ///
+    pub fn new(constants: Vec<Constant>, bytecode: Vec<u8>, functions: FunctionTable, imports: Vec<String>, debug_info: Option<DebugInfo>) -> Result<Self, LoadError> {
+        let strings = constants.iter().filter(|x| matches!(x, Constant::Str(_))).count();
+        if strings > HEAP_SIZE {
+            return Err(LoadError::TooManyConstants(strings))
+        }
+
+        let verification = verifier::verify(&bytecode, constants.len(), imports.len(), &functions);
+
+        Ok(Self {
+            constants: constants.into(),
+            bytecode: bytecode.into(),
+            functions,
+            imports: imports.into(),
+            debug_info,
+            verification,
+            threaded: OnceLock::new(),
+        })
+    }
+
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        let packed = Packed::from_bytes(bytes).ok_or(LoadError::InvalidArchive)?;
+        Self::from_packed(packed)
+    }
+
+
+    ///
+    /// Loads the sections of an `.anb` file, in order
+    /// - the constants
+    /// - the bytecode
+    /// - the function table
+    /// - the import table
+    /// - the debug info, which is optional
+    ///
+    pub fn from_packed(packed: Packed) -> Result<Self, LoadError> {
+        let sections : Vec<_> = packed.into();
+        let mut sections = sections.into_iter();
+
+        let constants = sections.next().ok_or(LoadError::MissingSection("constants"))?;
+        let constants = parse_constants(&constants.0).ok_or(LoadError::InvalidSection("constants"))?;
+
+        let bytecode = sections.next().ok_or(LoadError::MissingSection("bytecode"))?.0;
+
+        let functions = sections.next().ok_or(LoadError::MissingSection("functions"))?;
+        let functions = FunctionTable::from_bytes(&functions.0).ok_or(LoadError::InvalidSection("functions"))?;
+
+        let imports = sections.next().ok_or(LoadError::MissingSection("imports"))?;
+        let imports = parse_imports(&imports.0).ok_or(LoadError::InvalidSection("imports"))?;
+
+        let debug_info = match sections.next() {
+            Some(v) => Some(DebugInfo::from_bytes(&v.0).ok_or(LoadError::InvalidSection("debug info"))?),
+            None => None,
+        };
+
+        Self::new(constants, bytecode, functions, imports, debug_info)
+    }
+
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+
+    pub fn bytecode(&self) -> &[u8] {
+        &self.bytecode
+    }
+
+
+    pub fn functions(&self) -> &FunctionTable {
+        &self.functions
+    }
+
+
+    pub fn imports(&self) -> &[String] {
+        &self.imports
+    }
+
+
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+
+    /// The result of verifying the bytecode when the program was loaded
+    pub fn verification(&self) -> Result<(), &VerifyError> {
+        self.verification.as_ref().map(|_| ())
+    }
+
+
+    /// Only valid for a program that passed verification
+    pub(crate) fn threaded(&self) -> &Threaded {
+        self.threaded.get_or_init(|| Threaded::new(&self.bytecode))
+    }
+}
+
+
+fn parse_constants(mut bytes: &[u8]) -> Option<Vec<Constant>> {
+    let mut vec = vec![];
+
+    while let Some([v]) = take(&mut bytes) {
+        match v {
+            0 => vec.push(Constant::Int(i64::from_le_bytes(take(&mut bytes)?))),
+            1 => vec.push(Constant::Float(f64::from_le_bytes(take(&mut bytes)?))),
+            2 => vec.push(Constant::Str(take_str(&mut bytes)?.into())),
+
+            3 => vec.push(Constant::Bool(true)),
+            4 => vec.push(Constant::Bool(false)),
+
+            _ => return None,
+        }
+    }
+
+    Some(vec)
+}
+
+
+fn parse_imports(mut bytes: &[u8]) -> Option<Vec<String>> {
+    let mut vec = vec![];
+
+    while !bytes.is_empty() {
+        vec.push(take_str(&mut bytes)?);
+    }
+
+    Some(vec)
+}
+
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::InvalidArchive => write!(f, "the file isn't a valid archive"),
+            LoadError::MissingSection(v) => write!(f, "the {v} section is missing"),
+            LoadError::InvalidSection(v) => write!(f, "the {v} section is corrupt"),
+            LoadError::Verification(e) => write!(f, "{e}"),
+            LoadError::TooManyConstants(n) => write!(f, "the program has {n} string constants but at most {HEAP_SIZE} are allowed"),
+        }
+    }
+}
+
+
+impl std::error::Error for LoadError {}
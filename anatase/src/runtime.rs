@@ -1,10 +1,139 @@
-use std::ops::Div;
+use std::ops::{Div, Neg, Not};
 
-use crate::{VM, Code, bytecode, Data};
+use crate::{VM, Code, bytecode, Data, errors::VmError, garbage_collector::ObjectData, shift::Shift};
 
 
 impl<const DEBUG: bool> VM<DEBUG> {
-    pub fn run(&mut self) {
+    ///
+    /// Runs the program until it returns from the function that was
+    /// executing when it was called
+    ///
+    pub fn run(&mut self) -> Result<(), VmError> {
+        self.link_before_running()?;
+
+        // a `VM<false>` only runs verified programs which
+        // are safe to run from the pre-decoded instructions
+        if !DEBUG {
+            return self.run_threaded()
+        }
+
+        while !self.execute()? {}
+        Ok(())
+    }
+
+
+    ///
+    /// Executes the instruction at `self.current` and returns whether
+    /// that finished the program
+    ///
+    /// Everything that drives the `VM` one instruction at a time, like
+    /// `VM::run` or the debugger, goes through here
+    ///
+    #[inline(always)]
+    pub(crate) fn execute(&mut self) -> Result<bool, VmError> {
+        // the start of the instruction that is executing,
+        // errors rewind `self.current` back to it
+        let start = self.current.ptr;
+
+
+        macro_rules! location {
+            () => {{
+                self.current.ptr = start;
+                self.current.location_of(start)
+            }}
+        }
+
+
+        macro_rules! expect_tag {
+            ($val: expr, $tag: ident) => {
+                if DEBUG && $val.tag != Data::$tag {
+                    return Err(VmError::TypeMismatch {
+                        location: location!(),
+                        expected: Data::$tag,
+                        found: $val.tag,
+                    })
+                }
+            }
+        }
+
+
+        // reads the heap object `$val` refers to with `MemoryPool::$getter`
+        macro_rules! object {
+            ($val: expr, $getter: ident, $tag: ident) => {{
+                let val = $val;
+                match self.memory.$getter(val) {
+                    Some(v) => v,
+                    None => return Err(VmError::TypeMismatch {
+                        location: location!(),
+                        expected: Data::$tag,
+                        found: val.tag,
+                    }),
+                }
+            }}
+        }
+
+
+        macro_rules! string {
+            ($val: expr) => { object!($val, string, TAG_STR) }
+        }
+
+
+        macro_rules! alloc {
+            ($val: expr, $new: ident) => {{
+                let val = $val;
+                match self.alloc(val) {
+                    Some(v) => Data::$new(v),
+                    None => return Err(VmError::OutOfMemory(location!())),
+                }
+            }}
+        }
+
+
+        // only a backward jump or a call can keep the program running
+        // forever so those are the only places that check for interrupts
+        macro_rules! check_interrupt {
+            () => {
+                if self.take_interrupt() {
+                    return Err(VmError::Interrupted(location!()))
+                }
+            }
+        }
+
+
+        // a jump to a lower offset might be a loop
+        macro_rules! jump {
+            ($pos: expr) => {{
+                self.current.jump($pos as usize);
+                if self.current.ptr <= start {
+                    check_interrupt!();
+                }
+            }}
+        }
+
+
+        macro_rules! out_of_bounds {
+            ($index: expr, $len: expr) => {
+                return Err(VmError::IndexOutOfBounds { location: location!(), index: $index, len: $len })
+            }
+        }
+
+
+        macro_rules! int {
+            ($val: expr) => {{
+                let val = $val;
+                expect_tag!(val, TAG_I64);
+                unsafe { val.inner.I64 }
+            }}
+        }
+
+
+        // `lhs.method(rhs)` for a method name and `lhs op rhs` for an operator
+        macro_rules! apply {
+            ($lhs: expr, $method: ident, $rhs: expr) => { $lhs.$method($rhs) };
+            ($lhs: expr, $tt: tt, $rhs: expr) => { $lhs $tt $rhs };
+        }
+
+
         macro_rules! arithmetic_operation {
             ($tt: tt, $tag: ident, $kind: ident) => { arithmetic_operation!($tt, $tag, $kind, $tag, $kind) };
 
@@ -17,14 +146,12 @@ impl<const DEBUG: bool> VM<DEBUG> {
                     let lhs = self.stack.reg(lhs);
                     let rhs = self.stack.reg(rhs);
 
-                    if DEBUG {
-                        assert_eq!(lhs.tag, Data::$tag);
-                        assert_eq!(rhs.tag, Data::$tag);
-                    }
+                    expect_tag!(lhs, $tag);
+                    expect_tag!(rhs, $tag);
 
                     let result = Data::new(
                         Data::$exp_tag,
-                        unsafe { crate::InnerData { $exp: lhs.inner.$kind $tt rhs.inner.$kind } },
+                        unsafe { crate::InnerData { $exp: apply!(lhs.inner.$kind, $tt, rhs.inner.$kind) } },
                     );
 
                     self.stack.set_reg(dst, result);
@@ -34,7 +161,7 @@ impl<const DEBUG: bool> VM<DEBUG> {
 
         
         macro_rules! arithmetic_division_operation {
-            ($tag: ident, $kind: ident, $zero: literal) => {
+            ($tt: tt, $tag: ident, $kind: ident, $zero: literal) => {
                 {
                     let dst = self.current.next();
                     let lhs = self.current.next();
@@ -44,19 +171,17 @@ impl<const DEBUG: bool> VM<DEBUG> {
                     let rhs = self.stack.reg(rhs);
 
 
-                    if DEBUG {
-                        assert_eq!(lhs.tag, Data::$tag);
-                        assert_eq!(rhs.tag, Data::$tag);
-                    }
+                    expect_tag!(lhs, $tag);
+                    expect_tag!(rhs, $tag);
 
                     if unsafe { rhs.inner.$kind } == $zero {
-                        panic!("division by zero")
+                        return Err(VmError::DivisionByZero(location!()))
                     }
                     
 
                     let result = Data::new(
                         Data::$tag,
-                        unsafe { crate::InnerData { $kind: lhs.inner.$kind / rhs.inner.$kind } },
+                        unsafe { crate::InnerData { $kind: apply!(lhs.inner.$kind, $tt, rhs.inner.$kind) } },
                     );
 
                     self.stack.set_reg(dst, result);
@@ -65,6 +190,123 @@ impl<const DEBUG: bool> VM<DEBUG> {
         }
 
 
+        // `arithmetic_operation` with a `checked_*` method, `None` is an overflow
+        macro_rules! checked_operation {
+            ($method: ident, $tag: ident, $kind: ident) => {
+                {
+                    let dst = self.current.next();
+                    let lhs = self.current.next();
+                    let rhs = self.current.next();
+
+                    let lhs = self.stack.reg(lhs);
+                    let rhs = self.stack.reg(rhs);
+
+                    expect_tag!(lhs, $tag);
+                    expect_tag!(rhs, $tag);
+
+                    let Some(result) = (unsafe { lhs.inner.$kind.$method(rhs.inner.$kind) })
+                    else { return Err(VmError::Overflow(location!())) };
+
+                    self.stack.set_reg(dst, Data::new(Data::$tag, crate::InnerData { $kind: result }));
+                }
+            }
+        }
+
+
+        // `arithmetic_operation` with the right-hand side from the constant table
+        macro_rules! immediate_operation {
+            ($tt: tt, $tag: ident, $kind: ident) => { immediate_operation!($tt, $tag, $kind, $tag, $kind) };
+
+            ($tt: tt, $tag: ident, $kind: ident, $exp_tag: ident, $exp: ident) => {
+                {
+                    let dst = self.current.next();
+                    let lhs = self.current.next();
+                    let rhs = self.current.read_as::<u16>();
+
+                    let lhs = self.stack.reg(lhs);
+                    let rhs = self.constants[rhs as usize];
+
+                    expect_tag!(lhs, $tag);
+                    expect_tag!(rhs, $tag);
+
+                    let result = Data::new(
+                        Data::$exp_tag,
+                        unsafe { crate::InnerData { $exp: apply!(lhs.inner.$kind, $tt, rhs.inner.$kind) } },
+                    );
+
+                    self.stack.set_reg(dst, result);
+                }
+            }
+        }
+
+
+        // `val.method()` on a single register
+        macro_rules! unary_operation {
+            ($method: ident, $tag: ident, $kind: ident) => {
+                {
+                    let dst = self.current.next();
+                    let val = self.current.next();
+
+                    let val = self.stack.reg(val);
+                    expect_tag!(val, $tag);
+
+                    let result = Data::new(
+                        Data::$tag,
+                        unsafe { crate::InnerData { $kind: val.inner.$kind.$method() } },
+                    );
+
+                    self.stack.set_reg(dst, result);
+                }
+            }
+        }
+
+
+        // `arithmetic_operation` on bools, which are read with `Data::is_true`
+        // since a `VM<false>` doesn't check that they are bools
+        macro_rules! bool_operation {
+            ($tt: tt) => {
+                {
+                    let dst = self.current.next();
+                    let lhs = self.current.next();
+                    let rhs = self.current.next();
+
+                    let lhs = self.stack.reg(lhs);
+                    let rhs = self.stack.reg(rhs);
+
+                    expect_tag!(lhs, TAG_BOOL);
+                    expect_tag!(rhs, TAG_BOOL);
+
+                    self.stack.set_reg(dst, Data::new_bool(lhs.is_true() $tt rhs.is_true()));
+                }
+            }
+        }
+
+
+        // a comparison fused with a `jif` on its result
+        macro_rules! compare_jump {
+            ($tt: tt, $tag: ident, $kind: ident) => {
+                {
+                    let lhs = self.current.next();
+                    let rhs = self.current.next();
+                    let yes = self.current.read_as::<u32>();
+                    let no = self.current.read_as::<u32>();
+
+                    let lhs = self.stack.reg(lhs);
+                    let rhs = self.stack.reg(rhs);
+
+                    expect_tag!(lhs, $tag);
+                    expect_tag!(rhs, $tag);
+
+                    if unsafe { lhs.inner.$kind $tt rhs.inner.$kind } {
+                        jump!(yes);
+                    } else {
+                        jump!(no);
+                    }
+                }
+            }
+        }
+
+
         macro_rules! cast_instruction {
             ($tag: ident, $field: ident | $ty: ty, $target_tag: ident, $target_field: ident) => {{
                 println!("cast");
@@ -73,9 +315,7 @@ impl<const DEBUG: bool> VM<DEBUG> {
 
                 let val = self.stack.reg(val);
 
-                if DEBUG {
-                    assert!(val.tag == Data::$tag);
-                }
+                expect_tag!(val, $tag);
                 
                 let result = unsafe { val.inner.$field as $ty };
 
@@ -86,226 +326,626 @@ impl<const DEBUG: bool> VM<DEBUG> {
             }}
         }
         
-        loop {
-            let value = self.current.next();
+        let value = self.current.next();
 
-            match value {
-                bytecode::RETURN => {
-                    let Some(current) = self.callstack.pop() else { break };
+        match value {
+            bytecode::RETURN => {
+                let Some(current) = self.callstack.pop() else { return Ok(true) };
 
-                    let ret_val = self.stack.reg(0);
-                    let ret_reg = self.current.return_to;
-                    let argc = self.current.argc;
-                    
-                    self.current = current;
-                    self.stack.bottom = self.current.offset;
+                let ret_val = self.stack.reg(0);
+                let ret_reg = self.current.return_to;
+                let argc = self.current.argc;
+                let window = self.current.window;
+                
+                self.current = current;
+                self.stack.bottom = self.current.offset;
+
+                self.stack.set_reg(ret_reg, ret_val);
 
-                    self.stack.set_reg(ret_reg, ret_val);
+                // the registers of a window are still the caller's
+                if window {
+                    self.stack.top = self.stack.bottom + ret_reg as usize + argc as usize + 1;
+                } else {
                     self.stack.pop(argc as usize);
-                },
+                }
+            },
 
 
-                bytecode::COPY => {
-                    let dst = self.current.next();
-                    let src = self.current.next();
+            bytecode::COPY => {
+                let dst = self.current.next();
+                let src = self.current.next();
 
-                    let val = self.stack.reg(src);
-                    self.stack.set_reg(dst, val);
-                },
+                let val = self.stack.reg(src);
+                self.stack.set_reg(dst, val);
+            },
 
 
-                bytecode::SWAP => {
-                    let v1 = self.current.next();
-                    let v2 = self.current.next();
+            bytecode::SWAP => {
+                let v1 = self.current.next();
+                let v2 = self.current.next();
 
-                    let val1 = self.stack.reg(v1);
-                    let val2 = self.stack.reg(v2);
-                    self.stack.set_reg(v1, val2);
-                    self.stack.set_reg(v2, val1);
-                },
+                let val1 = self.stack.reg(v1);
+                let val2 = self.stack.reg(v2);
+                self.stack.set_reg(v1, val2);
+                self.stack.set_reg(v2, val1);
+            },
 
 
-                bytecode::SET => {
-                    let dst = self.current.next();
-                    let val = self.current.read_as::<u16>();
-                    let val = self.constants[val as usize];
+            bytecode::SET => {
+                let dst = self.current.next();
+                let val = self.current.read_as::<u16>();
+                let val = self.constants[val as usize];
 
-                    self.stack.set_reg(dst, val);
-                },
+                self.stack.set_reg(dst, val);
+            },
 
 
-                bytecode::PUSH => {
-                    let amount = self.current.next();
-                    self.stack.push(amount as usize);
+            bytecode::PUSH => {
+                let amount = self.current.next();
+
+                if !self.stack.can_push(amount as usize) {
+                    return Err(VmError::StackOverflow(location!()))
                 }
 
+                self.stack.push(amount as usize);
+            }
+
+
+            bytecode::POP => {
+                let amount = self.current.next();
+                self.stack.pop(amount as usize);
+            }
+
+
+            bytecode::PRINT => {
+                let reg = self.current.next();
+                let val = self.stack.reg(reg);
+                println!("print: {}", self.format(val));
+            }
+
+
+            bytecode::JIF => {
+                let cond = self.current.next();
+                let yes = self.current.read_as::<u32>();
+                let no = self.current.read_as::<u32>();
 
-                bytecode::POP => {
-                    let amount = self.current.next();
-                    self.stack.pop(amount as usize);
+                let cond = self.stack.reg(cond);
+                expect_tag!(cond, TAG_BOOL);
+                let cond = cond.is_true();
+                
+
+                if cond {
+                    jump!(yes);
+                } else {
+                    jump!(no);
                 }
+            }
 
 
-                bytecode::PRINT => {
-                    let reg = self.current.next();
-                    let val = self.stack.reg(reg);
-                    println!("print: {val:?}");
+            bytecode::IJIF => {
+                let cond = self.current.next();
+                let yes = self.current.read_as::<u32>();
+
+                let cond = self.stack.reg(cond);
+                expect_tag!(cond, TAG_BOOL);
+                let cond = cond.is_true();
+                
+
+                if cond {
+                    jump!(yes);
                 }
+            }
 
 
-                bytecode::JIF => {
-                    let cond = self.current.next();
-                    let yes = self.current.read_as::<u32>();
-                    let no = self.current.read_as::<u32>();
+            bytecode::JNIF => {
+                let cond = self.current.next();
+                let yes = self.current.read_as::<u32>();
+                let no = self.current.read_as::<u32>();
 
-                    let cond = self.stack.reg(cond);
-                    let cond = unsafe { cond.inner.Bool };
-                    
+                let cond = self.stack.reg(cond);
+                expect_tag!(cond, TAG_BOOL);
+                let cond = cond.is_true();
+                
 
-                    if cond {
-                        self.current.jump(yes as usize);
-                    } else {
-                        self.current.jump(no as usize);
-                    }
+                if !cond {
+                    jump!(yes);
+                } else {
+                    jump!(no);
                 }
+            }
 
 
-                bytecode::IJIF => {
-                    let cond = self.current.next();
-                    let yes = self.current.read_as::<u32>();
+            bytecode::IJNIF => {
+                let cond = self.current.next();
+                let yes = self.current.read_as::<u32>();
 
-                    let cond = self.stack.reg(cond);
-                    let cond = unsafe { cond.inner.Bool };
-                    
+                let cond = self.stack.reg(cond);
+                expect_tag!(cond, TAG_BOOL);
+                let cond = cond.is_true();
+                
 
-                    if cond {
-                        self.current.jump(yes as usize);
-                    }
+                if !cond {
+                    jump!(yes);
                 }
+            }
+            
 
+            bytecode::JMP => {
+                let pos = self.current.read_as::<u32>();
+                jump!(pos);
+            }
 
-                bytecode::JNIF => {
-                    let cond = self.current.next();
-                    let yes = self.current.read_as::<u32>();
-                    let no = self.current.read_as::<u32>();
 
-                    let cond = self.stack.reg(cond);
-                    let cond = unsafe { cond.inner.Bool };
-                    
+            bytecode::JLTI => compare_jump!(< , TAG_I64, I64),
+            bytecode::JLTF => compare_jump!(< , TAG_F64, F64),
+            bytecode::JLEI => compare_jump!(<=, TAG_I64, I64),
+            bytecode::JLEF => compare_jump!(<=, TAG_F64, F64),
+            bytecode::JEQI => compare_jump!(==, TAG_I64, I64),
+            bytecode::JEQF => compare_jump!(==, TAG_F64, F64),
+            bytecode::JNEI => compare_jump!(!=, TAG_I64, I64),
+            bytecode::JNEF => compare_jump!(!=, TAG_F64, F64),
 
-                    if !cond {
-                        self.current.jump(yes as usize);
-                    } else {
-                        self.current.jump(no as usize);
-                    }
+
+            bytecode::CALL => {
+                let dst = self.current.next();
+                let goto = self.current.read_as::<u32>();
+                let argc = self.current.next() as usize;
+
+                check_interrupt!();
+
+                if !self.can_call() || !self.stack.can_push(argc + 1) {
+                    return Err(VmError::StackOverflow(location!()))
                 }
 
+                self.stack.push(argc + 1);
 
-                bytecode::IJNIF => {
-                    let cond = self.current.next();
-                    let yes = self.current.read_as::<u32>();
+                let temp = self.stack.top - argc - self.stack.bottom;
+                for v in 0..argc {
+                    let reg = self.stack.reg(self.current.next());
+                    self.stack.set_reg((temp + v) as u8, reg);
+                }
 
-                    let cond = self.stack.reg(cond);
-                    let cond = unsafe { cond.inner.Bool };
-                    
+                let code = Code::new(
+                    unsafe { self.current.base.add(goto as usize) },
+                    self.current.base,
+                    self.current.top,
+                    dst,
+                    self.stack.top - argc - 1,
+                    argc as u8,
+                    goto as usize,
+                );
 
-                    if !cond {
-                        self.current.jump(yes as usize);
-                    }
+                self.callstack.push(std::mem::replace(&mut self.current, code));
+
+                self.stack.bottom = self.current.offset;
+
+            }
+
+
+            bytecode::CALLW => {
+                let dst = self.current.next();
+                let goto = self.current.read_as::<u32>();
+                let argc = self.current.next() as usize;
+
+                check_interrupt!();
+
+                if !self.can_call() {
+                    return Err(VmError::StackOverflow(location!()))
                 }
-                
 
-                bytecode::JMP => {
-                    let pos = self.current.read_as::<u32>();
-                    self.current.jump(pos as usize);
+                // `dst` and the arguments after it are the last registers
+                // of the frame so they already are the callee's frame
+                let bottom = self.stack.bottom + dst as usize;
+                if DEBUG && bottom + argc + 1 != self.stack.top {
+                    return Err(VmError::InvalidWindow(location!()))
                 }
 
+                let mut code = Code::new(
+                    unsafe { self.current.base.add(goto as usize) },
+                    self.current.base,
+                    self.current.top,
+                    dst,
+                    bottom,
+                    argc as u8,
+                    goto as usize,
+                );
+                code.window = true;
 
-                bytecode::CALL => {
-                    let dst = self.current.next();
-                    let goto = self.current.read_as::<u32>();
-                    let argc = self.current.next() as usize;
+                self.callstack.push(std::mem::replace(&mut self.current, code));
 
-                    self.stack.push(argc + 1);
+                self.stack.bottom = self.current.offset;
+            }
 
-                    let temp = self.stack.top - argc - self.stack.bottom;
-                    for v in 0..argc {
-                        let reg = self.stack.reg(self.current.next());
-                        self.stack.set_reg((temp + v) as u8, reg);
-                    }
 
-                    let code = Code::new(
-                        unsafe { self.current.base.add(goto as usize) },
-                        self.current.base,
-                        self.current.top,
-                        dst,
-                        self.stack.top - argc - 1,
-                        argc as u8,
-                    );
+            bytecode::TAILCALL => {
+                let goto = self.current.read_as::<u32>();
+                let argc = self.current.next() as usize;
+
+                check_interrupt!();
 
-                    self.callstack.push(std::mem::replace(&mut self.current, code));
+                // covers both the copies and the frame of the callee
+                if !self.stack.can_push(argc + 1) {
+                    return Err(VmError::StackOverflow(location!()))
+                }
+
+                // the arguments can be any of `@1..@n` so they're
+                // copied above the frame before moving them down
+                let top = self.stack.top;
+                for v in 0..argc {
+                    let reg = self.stack.reg(self.current.next());
+                    self.stack.values[top + v] = reg;
+                }
 
-                    self.stack.bottom = self.current.offset;
+                let bottom = self.stack.bottom;
+                self.stack.values.copy_within(top..top + argc, bottom + 1);
+                self.stack.top = bottom + argc + 1;
 
+                if !self.current.window {
+                    self.current.argc = argc as u8;
                 }
 
+                self.current.function = goto as usize;
+                self.current.jump(goto as usize);
+            }
 
-                bytecode::ADDI => arithmetic_operation!(+, TAG_I64, I64),
-                bytecode::ADDU => arithmetic_operation!(+, TAG_U64, U64),
-                bytecode::ADDF => arithmetic_operation!(+, TAG_F64, F64),
-                bytecode::SUBI => arithmetic_operation!(-, TAG_I64, I64),
-                bytecode::SUBU => arithmetic_operation!(-, TAG_U64, U64),
-                bytecode::SUBF => arithmetic_operation!(-, TAG_F64, F64),
-                bytecode::MULI => arithmetic_operation!(*, TAG_I64, I64),
-                bytecode::MULU => arithmetic_operation!(*, TAG_U64, U64),
-                bytecode::MULF => arithmetic_operation!(*, TAG_F64, F64),
-                bytecode::REMI => arithmetic_operation!(%, TAG_I64, I64),
-                bytecode::REMU => arithmetic_operation!(%, TAG_U64, U64),
-                bytecode::REMF => arithmetic_operation!(%, TAG_F64, F64),
-                bytecode::LSI  => arithmetic_operation!(<<, TAG_I64, I64),
-                bytecode::LSU  => arithmetic_operation!(<<, TAG_U64, U64),
-                bytecode::RSI  => arithmetic_operation!(>>, TAG_I64, I64),
-                bytecode::RSU  => arithmetic_operation!(>>, TAG_U64, U64),
-                bytecode::DIVI => arithmetic_division_operation!(TAG_I64, I64,   0),
-                bytecode::DIVU => arithmetic_division_operation!(TAG_U64, U64,   0),
-                bytecode::DIVF => arithmetic_division_operation!(TAG_F64, F64, 0.0),
-
-
-                bytecode::LTI => arithmetic_operation!(< , TAG_I64, I64, TAG_BOOL, Bool),
-                bytecode::LTU => arithmetic_operation!(< , TAG_U64, U64, TAG_BOOL, Bool),
-                bytecode::LTF => arithmetic_operation!(< , TAG_F64, F64, TAG_BOOL, Bool),
-                bytecode::GTI => arithmetic_operation!(> , TAG_I64, I64, TAG_BOOL, Bool),
-                bytecode::GTU => arithmetic_operation!(> , TAG_U64, U64, TAG_BOOL, Bool),
-                bytecode::GTF => arithmetic_operation!(> , TAG_F64, F64, TAG_BOOL, Bool),
-                bytecode::LEI => arithmetic_operation!(<=, TAG_I64, I64, TAG_BOOL, Bool),
-                bytecode::LEU => arithmetic_operation!(<=, TAG_U64, U64, TAG_BOOL, Bool),
-                bytecode::LEF => arithmetic_operation!(<=, TAG_F64, F64, TAG_BOOL, Bool),
-                bytecode::GEI => arithmetic_operation!(>=, TAG_I64, I64, TAG_BOOL, Bool),
-                bytecode::GEU => arithmetic_operation!(>=, TAG_U64, U64, TAG_BOOL, Bool),
-                bytecode::GEF => arithmetic_operation!(>=, TAG_F64, F64, TAG_BOOL, Bool),
-                bytecode::EQI => arithmetic_operation!(==, TAG_I64, I64, TAG_BOOL, Bool),
-                bytecode::EQU => arithmetic_operation!(==, TAG_U64, U64, TAG_BOOL, Bool),
-                bytecode::EQF => arithmetic_operation!(==, TAG_F64, F64, TAG_BOOL, Bool),
-                bytecode::NEI => arithmetic_operation!(!=, TAG_I64, I64, TAG_BOOL, Bool),
-                bytecode::NEU => arithmetic_operation!(!=, TAG_U64, U64, TAG_BOOL, Bool),
-                bytecode::NEF => arithmetic_operation!(!=, TAG_F64, F64, TAG_BOOL, Bool),
-
-
-                bytecode::CASTIU => cast_instruction!(TAG_I64, I64 | u64, TAG_U64, U64),
-                bytecode::CASTIF => cast_instruction!(TAG_I64, I64 | f64, TAG_F64, F64),
-                
-                bytecode::CASTUI => cast_instruction!(TAG_U64, U64 | i64, TAG_I64, I64),
-                bytecode::CASTUF => cast_instruction!(TAG_U64, U64 | f64, TAG_F64, F64),
-                
-                bytecode::CASTFI => cast_instruction!(TAG_F64, F64 | i64, TAG_I64, I64),
-                bytecode::CASTFU => cast_instruction!(TAG_F64, F64 | i64, TAG_I64, I64),
 
-                _ => panic!("unreachable {value}"),
+            bytecode::CALLN => {
+                let dst = self.current.next();
+                let index = self.current.read_as::<u16>();
+                let argc = self.current.next();
+
+                let mut args = Vec::with_capacity(argc as usize);
+                for _ in 0..argc {
+                    args.push(self.stack.reg(self.current.next()));
+                }
+
+                let Some(&function) = self.imports.get(index as usize)
+                else { return Err(VmError::UnresolvedImport(location!(), index)) };
+
+                // natives see the `callnative` as the current instruction
+                // and an error leaves it there like any other error
+                let next = std::mem::replace(&mut self.current.ptr, start);
+                let roots = self.temp_roots.len();
+
+                self.running_natives += 1;
+                let result = function(self, &args);
+                self.running_natives -= 1;
+
+                // the result goes into a register before anything else
+                // can allocate so the objects don't need the roots anymore
+                self.temp_roots.truncate(roots);
+                let result = result?;
+                self.current.ptr = next;
+
+                self.stack.set_reg(dst, result);
+            }
+
+
+            bytecode::STRCAT => {
+                let dst = self.current.next();
+                let lhs = self.current.next();
+                let rhs = self.current.next();
+
+                let lhs = string!(self.stack.reg(lhs));
+                let rhs = string!(self.stack.reg(rhs));
+
+                let result = alloc!(ObjectData::String(format!("{lhs}{rhs}")), new_str);
+                self.stack.set_reg(dst, result);
+            }
+
+
+            bytecode::STRLEN => {
+                let dst = self.current.next();
+                let val = self.current.next();
+
+                let val = string!(self.stack.reg(val));
+                let len = val.chars().count();
+
+                self.stack.set_reg(dst, Data::new_i64(len as i64));
+            }
+
+
+            bytecode::STREQ => {
+                let dst = self.current.next();
+                let lhs = self.current.next();
+                let rhs = self.current.next();
+
+                let lhs = string!(self.stack.reg(lhs));
+                let rhs = string!(self.stack.reg(rhs));
+
+                self.stack.set_reg(dst, Data::new_bool(lhs == rhs));
+            }
+
+
+            bytecode::STRCMP => {
+                let dst = self.current.next();
+                let lhs = self.current.next();
+                let rhs = self.current.next();
+
+                let lhs = string!(self.stack.reg(lhs));
+                let rhs = string!(self.stack.reg(rhs));
+
+                self.stack.set_reg(dst, Data::new_i64(lhs.cmp(rhs) as i64));
             }
+
+
+            bytecode::SUBSTR => {
+                let dst = self.current.next();
+                let val = self.current.next();
+                let from = self.current.next();
+                let to = self.current.next();
+
+                let from = int!(self.stack.reg(from));
+                let to = int!(self.stack.reg(to));
+                let val = string!(self.stack.reg(val));
+
+                let Some(start) = char_offset(val, from)
+                else { out_of_bounds!(from, val.chars().count()) };
+
+                let Some(end) = char_offset(val, to).filter(|&x| x >= start)
+                else { out_of_bounds!(to, val.chars().count()) };
+
+                let result = alloc!(ObjectData::String(val[start..end].to_string()), new_str);
+                self.stack.set_reg(dst, result);
+            }
+
+
+            bytecode::CHARAT => {
+                let dst = self.current.next();
+                let val = self.current.next();
+                let index = self.current.next();
+
+                let index = int!(self.stack.reg(index));
+                let val = string!(self.stack.reg(val));
+
+                let Some(char) = usize::try_from(index).ok().and_then(|x| val.chars().nth(x))
+                else { out_of_bounds!(index, val.chars().count()) };
+
+                let result = alloc!(ObjectData::String(char.to_string()), new_str);
+                self.stack.set_reg(dst, result);
+            }
+
+
+            bytecode::ARRNEW => {
+                let dst = self.current.next();
+                let len = self.current.next();
+                let val = self.current.next();
+
+                let len = int!(self.stack.reg(len));
+                let val = self.stack.reg(val);
+
+                let Ok(len) = usize::try_from(len)
+                else { out_of_bounds!(len, 0) };
+
+                // a length that can't be allocated is an error of the
+                // program, `vec!` would abort the whole process
+                let mut arr = Vec::new();
+                if arr.try_reserve_exact(len).is_err() {
+                    return Err(VmError::OutOfMemory(location!()))
+                }
+
+                arr.resize(len, val);
+                let result = alloc!(ObjectData::Array(arr), new_arr);
+                self.stack.set_reg(dst, result);
+            }
+
+
+            bytecode::ARRLEN => {
+                let dst = self.current.next();
+                let arr = self.current.next();
+
+                let arr = object!(self.stack.reg(arr), array, TAG_ARR);
+                let len = arr.len();
+
+                self.stack.set_reg(dst, Data::new_i64(len as i64));
+            }
+
+
+            bytecode::ARRGET => {
+                let dst = self.current.next();
+                let arr = self.current.next();
+                let index = self.current.next();
+
+                let index = int!(self.stack.reg(index));
+                let arr = object!(self.stack.reg(arr), array, TAG_ARR);
+
+                let Some(&val) = usize::try_from(index).ok().and_then(|x| arr.get(x))
+                else { out_of_bounds!(index, arr.len()) };
+
+                self.stack.set_reg(dst, val);
+            }
+
+
+            bytecode::ARRSET => {
+                let arr = self.current.next();
+                let index = self.current.next();
+                let val = self.current.next();
+
+                let index = int!(self.stack.reg(index));
+                let val = self.stack.reg(val);
+                let arr = object!(self.stack.reg(arr), array_mut, TAG_ARR);
+
+                let len = arr.len();
+                let Some(slot) = usize::try_from(index).ok().and_then(|x| arr.get_mut(x))
+                else { out_of_bounds!(index, len) };
+
+                *slot = val;
+            }
+
+
+            bytecode::ARRPUSH => {
+                let arr = self.current.next();
+                let val = self.current.next();
+
+                let val = self.stack.reg(val);
+                let arr = object!(self.stack.reg(arr), array_mut, TAG_ARR);
+
+                // like `arrnew`, growing past what can be allocated
+                // is an error of the program instead of an abort
+                if arr.try_reserve(1).is_err() {
+                    return Err(VmError::OutOfMemory(location!()))
+                }
+
+                arr.push(val);
+            }
+
+
+            bytecode::ARRPOP => {
+                let dst = self.current.next();
+                let arr = self.current.next();
+
+                let arr = object!(self.stack.reg(arr), array_mut, TAG_ARR);
+
+                let Some(val) = arr.pop()
+                else { return Err(VmError::EmptyArray(location!())) };
+
+                self.stack.set_reg(dst, val);
+            }
+
+
+            bytecode::ADDI => arithmetic_operation!(wrapping_add, TAG_I64, I64),
+            bytecode::ADDU => arithmetic_operation!(wrapping_add, TAG_U64, U64),
+            bytecode::ADDF => arithmetic_operation!(+, TAG_F64, F64),
+            bytecode::SUBI => arithmetic_operation!(wrapping_sub, TAG_I64, I64),
+            bytecode::SUBU => arithmetic_operation!(wrapping_sub, TAG_U64, U64),
+            bytecode::SUBF => arithmetic_operation!(-, TAG_F64, F64),
+            bytecode::MULI => arithmetic_operation!(wrapping_mul, TAG_I64, I64),
+            bytecode::MULU => arithmetic_operation!(wrapping_mul, TAG_U64, U64),
+            bytecode::MULF => arithmetic_operation!(*, TAG_F64, F64),
+            bytecode::LSI  => arithmetic_operation!(shift_left , TAG_I64, I64),
+            bytecode::LSU  => arithmetic_operation!(shift_left , TAG_U64, U64),
+            bytecode::RSI  => arithmetic_operation!(shift_right, TAG_I64, I64),
+            bytecode::RSU  => arithmetic_operation!(shift_right, TAG_U64, U64),
+            // `i64::MIN / -1` is the one quotient that overflows
+            bytecode::DIVI => arithmetic_division_operation!(wrapping_div, TAG_I64, I64,   0),
+            bytecode::DIVU => arithmetic_division_operation!(/, TAG_U64, U64,   0),
+            bytecode::DIVF => arithmetic_division_operation!(/, TAG_F64, F64, 0.0),
+            bytecode::REMI => arithmetic_division_operation!(wrapping_rem, TAG_I64, I64,   0),
+            bytecode::REMU => arithmetic_division_operation!(%, TAG_U64, U64,   0),
+            bytecode::REMF => arithmetic_division_operation!(%, TAG_F64, F64, 0.0),
+
+            bytecode::ANDI => arithmetic_operation!(&, TAG_I64, I64),
+            bytecode::ANDU => arithmetic_operation!(&, TAG_U64, U64),
+            bytecode::ORI  => arithmetic_operation!(|, TAG_I64, I64),
+            bytecode::ORU  => arithmetic_operation!(|, TAG_U64, U64),
+            bytecode::XORI => arithmetic_operation!(^, TAG_I64, I64),
+            bytecode::XORU => arithmetic_operation!(^, TAG_U64, U64),
+            bytecode::NOTI => unary_operation!(not, TAG_I64, I64),
+            bytecode::NOTU => unary_operation!(not, TAG_U64, U64),
+
+            bytecode::ANDB => bool_operation!(&),
+            bytecode::ORB  => bool_operation!(|),
+
+            bytecode::NOTB => {
+                let dst = self.current.next();
+                let val = self.current.next();
+
+                let val = self.stack.reg(val);
+                expect_tag!(val, TAG_BOOL);
+
+                self.stack.set_reg(dst, Data::new_bool(!val.is_true()));
+            }
+
+            bytecode::NEGI => unary_operation!(wrapping_neg, TAG_I64, I64),
+            bytecode::NEGF => unary_operation!(neg, TAG_F64, F64),
+
+            bytecode::ADDIC => checked_operation!(checked_add, TAG_I64, I64),
+            bytecode::ADDUC => checked_operation!(checked_add, TAG_U64, U64),
+            bytecode::SUBIC => checked_operation!(checked_sub, TAG_I64, I64),
+            bytecode::SUBUC => checked_operation!(checked_sub, TAG_U64, U64),
+            bytecode::MULIC => checked_operation!(checked_mul, TAG_I64, I64),
+            bytecode::MULUC => checked_operation!(checked_mul, TAG_U64, U64),
+            bytecode::LSIC  => checked_operation!(checked_shift_left , TAG_I64, I64),
+            bytecode::LSUC  => checked_operation!(checked_shift_left , TAG_U64, U64),
+            bytecode::RSIC  => checked_operation!(checked_shift_right, TAG_I64, I64),
+            bytecode::RSUC  => checked_operation!(checked_shift_right, TAG_U64, U64),
+
+            bytecode::LSIW  => arithmetic_operation!(wrapping_shift_left , TAG_I64, I64),
+            bytecode::LSUW  => arithmetic_operation!(wrapping_shift_left , TAG_U64, U64),
+            bytecode::RSIW  => arithmetic_operation!(wrapping_shift_right, TAG_I64, I64),
+            bytecode::RSUW  => arithmetic_operation!(wrapping_shift_right, TAG_U64, U64),
+
+            bytecode::ADDIS => arithmetic_operation!(saturating_add, TAG_I64, I64),
+            bytecode::ADDUS => arithmetic_operation!(saturating_add, TAG_U64, U64),
+            bytecode::SUBIS => arithmetic_operation!(saturating_sub, TAG_I64, I64),
+            bytecode::SUBUS => arithmetic_operation!(saturating_sub, TAG_U64, U64),
+            bytecode::MULIS => arithmetic_operation!(saturating_mul, TAG_I64, I64),
+            bytecode::MULUS => arithmetic_operation!(saturating_mul, TAG_U64, U64),
+            bytecode::LSIS  => arithmetic_operation!(saturating_shift_left , TAG_I64, I64),
+            bytecode::LSUS  => arithmetic_operation!(saturating_shift_left , TAG_U64, U64),
+            bytecode::RSIS  => arithmetic_operation!(saturating_shift_right, TAG_I64, I64),
+            bytecode::RSUS  => arithmetic_operation!(saturating_shift_right, TAG_U64, U64),
+
+
+            bytecode::LTI => arithmetic_operation!(< , TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::LTU => arithmetic_operation!(< , TAG_U64, U64, TAG_BOOL, Bool),
+            bytecode::LTF => arithmetic_operation!(< , TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::GTI => arithmetic_operation!(> , TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::GTU => arithmetic_operation!(> , TAG_U64, U64, TAG_BOOL, Bool),
+            bytecode::GTF => arithmetic_operation!(> , TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::LEI => arithmetic_operation!(<=, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::LEU => arithmetic_operation!(<=, TAG_U64, U64, TAG_BOOL, Bool),
+            bytecode::LEF => arithmetic_operation!(<=, TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::GEI => arithmetic_operation!(>=, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::GEU => arithmetic_operation!(>=, TAG_U64, U64, TAG_BOOL, Bool),
+            bytecode::GEF => arithmetic_operation!(>=, TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::EQI => arithmetic_operation!(==, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::EQU => arithmetic_operation!(==, TAG_U64, U64, TAG_BOOL, Bool),
+            bytecode::EQF => arithmetic_operation!(==, TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::NEI => arithmetic_operation!(!=, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::NEU => arithmetic_operation!(!=, TAG_U64, U64, TAG_BOOL, Bool),
+            bytecode::NEF => arithmetic_operation!(!=, TAG_F64, F64, TAG_BOOL, Bool),
+
+
+            bytecode::CASTIU => cast_instruction!(TAG_I64, I64 | u64, TAG_U64, U64),
+            bytecode::CASTIF => cast_instruction!(TAG_I64, I64 | f64, TAG_F64, F64),
             
+            bytecode::CASTUI => cast_instruction!(TAG_U64, U64 | i64, TAG_I64, I64),
+            bytecode::CASTUF => cast_instruction!(TAG_U64, U64 | f64, TAG_F64, F64),
+            
+            bytecode::CASTFI => cast_instruction!(TAG_F64, F64 | i64, TAG_I64, I64),
+            bytecode::CASTFU => cast_instruction!(TAG_F64, F64 | i64, TAG_I64, I64),
+
+
+            bytecode::ADDIK => immediate_operation!(wrapping_add, TAG_I64, I64),
+            bytecode::ADDFK => immediate_operation!(+, TAG_F64, F64),
+            bytecode::SUBIK => immediate_operation!(wrapping_sub, TAG_I64, I64),
+            bytecode::SUBFK => immediate_operation!(-, TAG_F64, F64),
+            bytecode::MULIK => immediate_operation!(wrapping_mul, TAG_I64, I64),
+            bytecode::MULFK => immediate_operation!(*, TAG_F64, F64),
+
+            bytecode::LTIK => immediate_operation!(< , TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::LTFK => immediate_operation!(< , TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::GTIK => immediate_operation!(> , TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::GTFK => immediate_operation!(> , TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::LEIK => immediate_operation!(<=, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::LEFK => immediate_operation!(<=, TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::GEIK => immediate_operation!(>=, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::GEFK => immediate_operation!(>=, TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::EQIK => immediate_operation!(==, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::EQFK => immediate_operation!(==, TAG_F64, F64, TAG_BOOL, Bool),
+            bytecode::NEIK => immediate_operation!(!=, TAG_I64, I64, TAG_BOOL, Bool),
+            bytecode::NEFK => immediate_operation!(!=, TAG_F64, F64, TAG_BOOL, Bool),
+
+            _ => return Err(VmError::InvalidOpcode(location!(), value)),
         }
+
+        Ok(false)
     }
 
 }
 
 
+///
+/// Returns the byte offset of the `index`th character of `str`
+///
+/// `index` can be one past the last character for the end of a range
+///
+fn char_offset(str: &str, index: i64) -> Option<usize> {
+    let index = usize::try_from(index).ok()?;
+
+    str.char_indices()
+        .map(|x| x.0)
+        .chain(std::iter::once(str.len()))
+        .nth(index)
+}
@@ -0,0 +1,90 @@
+///
+/// Shifts of the integer types that are defined for every amount,
+/// unlike `<<` and `>>` which panic or mask the amount once it's
+/// outside `0..64`
+///
+pub(crate) trait Shift: Sized {
+    /// An amount outside `0..64` shifts every bit out
+    fn shift_left(self, amount: Self) -> Self;
+    /// An amount outside `0..64` leaves only the sign
+    fn shift_right(self, amount: Self) -> Self;
+
+    /// `None` if the amount is outside `0..64` or a bit is lost
+    fn checked_shift_left(self, amount: Self) -> Option<Self>;
+    /// `None` if the amount is outside `0..64`
+    fn checked_shift_right(self, amount: Self) -> Option<Self>;
+
+    /// Only the low 6 bits of the amount are used
+    fn wrapping_shift_left(self, amount: Self) -> Self;
+    /// Only the low 6 bits of the amount are used
+    fn wrapping_shift_right(self, amount: Self) -> Self;
+
+    /// The largest or smallest value, by the sign, if a bit is lost
+    fn saturating_shift_left(self, amount: Self) -> Self;
+    /// Same as `shift_right`, shifting right can't overflow
+    fn saturating_shift_right(self, amount: Self) -> Self;
+}
+
+
+fn bits(amount: impl TryInto<u32>) -> Option<u32> {
+    amount.try_into().ok().filter(|&n| n < 64)
+}
+
+
+macro_rules! shift {
+    ($ty: ty) => {
+        impl Shift for $ty {
+            fn shift_left(self, amount: Self) -> Self {
+                bits(amount).map_or(0, |n| self << n)
+            }
+
+
+            fn shift_right(self, amount: Self) -> Self {
+                // the sign for `i64` and 0 for `u64`
+                let fill = self >> 63 >> 1;
+                bits(amount).map_or(fill, |n| self >> n)
+            }
+
+
+            fn checked_shift_left(self, amount: Self) -> Option<Self> {
+                let n = bits(amount)?;
+                let result = self << n;
+                (result >> n == self).then_some(result)
+            }
+
+
+            fn checked_shift_right(self, amount: Self) -> Option<Self> {
+                bits(amount).map(|n| self >> n)
+            }
+
+
+            fn wrapping_shift_left(self, amount: Self) -> Self {
+                self.wrapping_shl(amount as u32)
+            }
+
+
+            fn wrapping_shift_right(self, amount: Self) -> Self {
+                self.wrapping_shr(amount as u32)
+            }
+
+
+            fn saturating_shift_left(self, amount: Self) -> Self {
+                // `MIN` for a negative `i64` and `MAX` otherwise
+                let saturated = <$ty>::MAX ^ (self >> 63 >> 1);
+                match self {
+                    0 => 0,
+                    _ => self.checked_shift_left(amount).unwrap_or(saturated),
+                }
+            }
+
+
+            fn saturating_shift_right(self, amount: Self) -> Self {
+                self.shift_right(amount)
+            }
+        }
+    }
+}
+
+
+shift!(i64);
+shift!(u64);
@@ -0,0 +1,119 @@
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub offset: usize,
+    pub argc: u8,
+}
+
+
+///
+/// The functions of a program as exported by the assembler
+///
+/// Every entry is the function's offset (u32), its argument
+/// count (u8) and its name (u64 length followed by the bytes)
+///
+#[derive(Debug, Clone, Default)]
+pub struct FunctionTable {
+    functions: Vec<Function>,
+}
+
+
+impl FunctionTable {
+    /// A table of `(offset, argc, name)` entries, for programs built without the assembler
+    pub fn new(entries: &[(u32, u8, &str)]) -> Self {
+        let functions = entries.iter()
+            .map(|&(offset, argc, name)| Function { name: name.to_string(), offset: offset as usize, argc })
+            .collect();
+
+        Self { functions }
+    }
+
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mut functions = vec![];
+
+        while !bytes.is_empty() {
+            let offset = u32::from_le_bytes(take(&mut bytes)?);
+            let [argc] = take(&mut bytes)?;
+            let name = take_str(&mut bytes)?;
+
+            functions.push(Function { name, offset: offset as usize, argc })
+        }
+
+        Some(Self { functions })
+    }
+
+
+    /// The table in the format `from_bytes` reads
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for function in &self.functions {
+            bytes.extend_from_slice(&(function.offset as u32).to_le_bytes());
+            bytes.push(function.argc);
+            bytes.extend_from_slice(&(function.name.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(function.name.as_bytes());
+        }
+
+        bytes
+    }
+
+
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|x| x.name == name)
+    }
+
+
+    /// Returns the function that starts at `offset`
+    pub fn at(&self, offset: usize) -> Option<&Function> {
+        self.functions.iter().find(|x| x.offset == offset)
+    }
+
+
+    ///
+    /// Returns the bytecode range of the function starting at `offset`
+    ///
+    /// Functions are laid out one after another so a function
+    /// ends where the next one starts
+    ///
+    pub fn range(&self, offset: usize, bytecode_len: usize) -> std::ops::Range<usize> {
+        let end = self.functions.iter()
+            .map(|x| x.offset)
+            .filter(|&x| x > offset)
+            .min()
+            .unwrap_or(bytecode_len);
+
+        offset..end
+    }
+
+
+    pub fn name_of(&self, offset: usize) -> &str {
+        match self.at(offset) {
+            Some(v) => &v.name,
+            None => "<bootstrap>",
+        }
+    }
+
+
+    pub fn iter(&self) -> impl Iterator<Item = &Function> {
+        self.functions.iter()
+    }
+}
+
+
+pub(crate) fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (value, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*value)
+}
+
+
+pub(crate) fn take_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = u64::from_le_bytes(take(bytes)?) as usize;
+    if bytes.len() < len {
+        return None
+    }
+
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+    String::from_utf8(value.to_vec()).ok()
+}
@@ -0,0 +1,545 @@
+use std::ops::{Neg, Not};
+
+use crate::{VM, Code, Data, InnerData, bytecode, decoder::{self, Value}, errors::VmError, shift::Shift};
+
+
+///
+/// The bytecode of a program decoded into fixed-width instructions
+///
+/// `VM::run` executes these instead of decoding the operands of
+/// every instruction as it goes, jumps and calls point at the index
+/// of their target so the hot loop never has to look up an offset
+///
+/// The `VM` still keeps track of the byte offsets in `VM::current`
+/// so everything else, like backtraces, works the same, it's only
+/// synced when the hot loop hands an instruction over to `VM::execute`
+///
+#[derive(Debug)]
+pub(crate) struct Threaded {
+    ops: Box<[Op]>,
+    /// The offset of each instruction, by index
+    offsets: Box<[u32]>,
+    /// The index of the instruction at each offset, `u32::MAX` if
+    /// an instruction doesn't start there
+    indices: Box<[u32]>,
+}
+
+
+///
+/// A decoded instruction
+///
+/// The registers are in `a`, `b` and `c` in the order of the operands
+/// and the rest of the operands go in `x` and `y`, jump targets are
+/// indices into `Threaded::ops`
+///
+#[derive(Debug, Clone, Copy)]
+struct Op {
+    opcode: u8,
+    a: u8,
+    b: u8,
+    c: u8,
+    x: u32,
+    y: u32,
+    /// Set on a comparison that's followed by an `ijif` or `ijnif` of
+    /// its result, the comparison jumps to `y` if the result is this
+    /// and skips over the jump otherwise
+    branch: Option<bool>,
+}
+
+
+impl Threaded {
+    ///
+    /// Decodes `bytecode`, which has to have passed verification
+    /// so every instruction decodes and every target is an instruction
+    ///
+    pub(crate) fn new(bytecode: &[u8]) -> Self {
+        let instructions = decoder::decode_all(bytecode).expect("verified bytecode decodes");
+
+        let mut indices = vec![u32::MAX; bytecode.len() + 1];
+        for (index, instruction) in instructions.iter().enumerate() {
+            indices[instruction.offset] = index as u32;
+        }
+
+        let mut ops = instructions.iter().enumerate().map(|(index, instruction)| {
+            let mut op = Op { opcode: instruction.opcode, a: 0, b: 0, c: 0, x: 0, y: 0, branch: None };
+            let mut registers = 0;
+            let mut targets = 0;
+
+            for operand in &instruction.operands {
+                let register = match *operand {
+                    Value::Reg(v) | Value::U8(v) => v,
+
+                    Value::Block(v) => {
+                        let target = indices[v as usize];
+                        match targets {
+                            0 => op.x = target,
+                            _ => op.y = target,
+                        }
+
+                        targets += 1;
+                        continue
+                    },
+
+                    Value::Constant(v) | Value::Import(v) => { op.x = v as u32; continue },
+                    Value::Function(v) => { op.x = indices[v as usize]; continue },
+
+                    // calls are left to `VM::execute`
+                    Value::RegList(_) => continue,
+                };
+
+                match registers {
+                    0 => op.a = register,
+                    1 => op.b = register,
+                    2 => op.c = register,
+                    _ => (),
+                }
+
+                registers += 1;
+            }
+
+            // a `jif` picks its target with a conditional move, which makes
+            // the next instruction wait on the condition, so one that falls
+            // through to the next instruction either way becomes an `ijif`
+            let next = index as u32 + 1;
+            match (op.opcode, op.x == next, op.y == next) {
+                (bytecode::JIF , _, true) => op.opcode = bytecode::IJIF,
+                (bytecode::JNIF, _, true) => op.opcode = bytecode::IJNIF,
+                (bytecode::JIF , true, _) => { op.opcode = bytecode::IJNIF; op.x = op.y },
+                (bytecode::JNIF, true, _) => { op.opcode = bytecode::IJIF; op.x = op.y },
+                _ => (),
+            }
+
+            op
+        }).collect::<Vec<_>>();
+
+        for i in 1..ops.len() {
+            let jump = ops[i];
+            let branch = match jump.opcode {
+                bytecode::IJIF => true,
+                bytecode::IJNIF => false,
+                _ => continue,
+            };
+
+            let cmp = &mut ops[i - 1];
+            let is_comparison = matches!(cmp.opcode, bytecode::LTI..=bytecode::NEF | bytecode::LTIK..=bytecode::NEFK);
+            if is_comparison && cmp.a == jump.a {
+                cmp.y = jump.x;
+                cmp.branch = Some(branch);
+            }
+        }
+
+        Self {
+            ops: ops.into(),
+            offsets: instructions.iter().map(|x| x.offset as u32).collect(),
+            indices: indices.into(),
+        }
+    }
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Runs the program from its pre-decoded instructions
+    ///
+    /// The hot loop handles the moves, jumps, arithmetic, `callw` and `ret`
+    /// itself and hands everything else to `VM::execute`, including anything
+    /// that would fail so the errors are exactly those of `VM::execute`
+    ///
+    /// Nothing here checks that registers are in the frame or that jumps
+    /// land on an instruction so this relies on the program being verified,
+    /// which is why only `VM<false>` uses it
+    ///
+    /// The verifier doesn't look at tags so those aren't checked either,
+    /// conditions are read with `Data::is_true` which works for any data
+    ///
+    pub(crate) fn run_threaded(&mut self) -> Result<(), VmError> {
+        let program = self.program.clone();
+        let threaded = program.threaded();
+
+        let base = self.current.base;
+        let ops = threaded.ops.as_ptr();
+
+        let interrupt = self.interrupt.clone();
+
+        let index_of = |vm: &Self| threaded.indices[vm.current.ptr as usize - base as usize] as usize;
+
+        let mut ip = index_of(self);
+        let mut regs = unsafe { self.stack.values.as_mut_ptr().add(self.stack.bottom) };
+
+        macro_rules! reg {
+            ($reg: expr) => { unsafe { *regs.add($reg as usize) } }
+        }
+
+
+        macro_rules! set_reg {
+            ($reg: expr, $val: expr) => {{ let val = $val; unsafe { *regs.add($reg as usize) = val } }}
+        }
+
+
+        // `lhs.method(rhs)` for a method name and `lhs op rhs` for an operator
+        macro_rules! apply {
+            ($lhs: expr, $method: ident, $rhs: expr) => { $lhs.$method($rhs) };
+            ($lhs: expr, $tt: tt, $rhs: expr) => { $lhs $tt $rhs };
+        }
+
+
+        // syncs `self.current` and lets `VM::execute` run the instruction
+        macro_rules! fallback {
+            () => {{
+                self.current.ptr = unsafe { base.add(threaded.offsets[ip] as usize) };
+                if self.execute_cold()? {
+                    return Ok(())
+                }
+
+                ip = index_of(self);
+                regs = unsafe { self.stack.values.as_mut_ptr().add(self.stack.bottom) };
+                continue
+            }}
+        }
+
+
+        // a jump to a lower index might be a loop so
+        // it has to look for interrupts like `VM::execute`
+        macro_rules! jump {
+            ($target: expr) => {{
+                let target = $target as usize;
+                if target <= ip && interrupt.load(std::sync::atomic::Ordering::Relaxed) {
+                    fallback!()
+                }
+
+                ip = target;
+                continue
+            }}
+        }
+
+
+        loop {
+            let op = unsafe { &*ops.add(ip) };
+
+
+            macro_rules! binary {
+                ($tt: tt, $tag: ident, $kind: ident) => { binary!($tt, $kind, $tag, $kind) };
+
+                ($tt: tt, $kind: ident, $exp_tag: ident, $exp: ident) => {{
+                    let lhs = reg!(op.b);
+                    let rhs = reg!(op.c);
+
+                    let result = unsafe { InnerData { $exp: apply!(lhs.inner.$kind, $tt, rhs.inner.$kind) } };
+                    set_reg!(op.a, Data::new(Data::$exp_tag, result));
+                }}
+            }
+
+
+            macro_rules! immediate {
+                ($tt: tt, $tag: ident, $kind: ident) => { immediate!($tt, $kind, $tag, $kind) };
+
+                ($tt: tt, $kind: ident, $exp_tag: ident, $exp: ident) => {{
+                    let lhs = reg!(op.b);
+                    let rhs = unsafe { *self.constants.get_unchecked(op.x as usize) };
+
+                    let result = unsafe { InnerData { $exp: apply!(lhs.inner.$kind, $tt, rhs.inner.$kind) } };
+                    set_reg!(op.a, Data::new(Data::$exp_tag, result));
+                }}
+            }
+
+
+            macro_rules! unary {
+                ($method: ident, $tag: ident, $kind: ident) => {{
+                    let val = reg!(op.b);
+
+                    let result = unsafe { InnerData { $kind: val.inner.$kind.$method() } };
+                    set_reg!(op.a, Data::new(Data::$tag, result));
+                }}
+            }
+
+
+            macro_rules! compare_jump {
+                ($tt: tt, $kind: ident) => {{
+                    let lhs = reg!(op.a);
+                    let rhs = reg!(op.b);
+
+                    jump!(if unsafe { lhs.inner.$kind $tt rhs.inner.$kind } { op.x } else { op.y })
+                }}
+            }
+
+
+            // a comparison that was merged with the jump after it
+            // takes the jump itself, see `Op::branch`
+            macro_rules! comparison {
+                ($($tt: tt)*) => {{
+                    $($tt)*;
+
+                    if let Some(branch) = op.branch {
+                        if reg!(op.a).is_true() == branch {
+                            jump!(op.y)
+                        }
+
+                        ip += 2;
+                        continue
+                    }
+                }}
+            }
+
+
+            // a zero divisor goes to `VM::execute` to report the error
+            macro_rules! division {
+                ($tt: tt, $tag: ident, $kind: ident, $zero: literal) => {{
+                    let lhs = reg!(op.b);
+                    let rhs = reg!(op.c);
+
+                    if unsafe { rhs.inner.$kind } == $zero {
+                        fallback!()
+                    }
+
+                    let result = unsafe { InnerData { $kind: apply!(lhs.inner.$kind, $tt, rhs.inner.$kind) } };
+                    set_reg!(op.a, Data::new(Data::$tag, result));
+                }}
+            }
+
+
+            match op.opcode {
+                // the bootstrap returning ends the program
+                // which is left to `VM::execute`
+                bytecode::RETURN if !self.callstack.is_empty() => {
+                    let ret_val = reg!(0);
+                    let ret_reg = self.current.return_to;
+                    let argc = self.current.argc;
+                    let window = self.current.window;
+
+                    self.current = self.callstack.pop().unwrap();
+                    self.stack.bottom = self.current.offset;
+                    regs = unsafe { self.stack.values.as_mut_ptr().add(self.stack.bottom) };
+
+                    set_reg!(ret_reg, ret_val);
+
+                    if window {
+                        self.stack.top = self.stack.bottom + ret_reg as usize + argc as usize + 1;
+                    } else {
+                        self.stack.top -= argc as usize;
+                    }
+
+                    ip = index_of(self);
+                    continue
+                },
+
+
+                // nothing is copied so the call only has to switch frames,
+                // the caller's `current.ptr` is synced to where it returns to
+                bytecode::CALLW => {
+                    if !self.can_call() || interrupt.load(std::sync::atomic::Ordering::Relaxed) {
+                        fallback!()
+                    }
+
+                    let offset = threaded.offsets[op.x as usize] as usize;
+                    let bottom = self.stack.bottom + op.a as usize;
+
+                    self.current.ptr = unsafe { base.add(threaded.offsets[ip + 1] as usize) };
+
+                    let mut code = Code::new(
+                        unsafe { base.add(offset) },
+                        base,
+                        self.current.top,
+                        op.a,
+                        bottom,
+                        op.b,
+                        offset,
+                    );
+                    code.window = true;
+
+                    self.callstack.push(std::mem::replace(&mut self.current, code));
+
+                    self.stack.bottom = bottom;
+                    regs = unsafe { self.stack.values.as_mut_ptr().add(bottom) };
+
+                    ip = op.x as usize;
+
+                    #[cfg(feature = "jit")]
+                    if self.enter_jit() {
+                        ip = index_of(self);
+                    }
+
+                    continue
+                },
+
+
+                // the callee might be hot enough to have native code
+                #[cfg(feature = "jit")]
+                bytecode::CALL | bytecode::TAILCALL => {
+                    self.current.ptr = unsafe { base.add(threaded.offsets[ip] as usize) };
+                    if self.execute_cold()? {
+                        return Ok(())
+                    }
+
+                    self.enter_jit();
+
+                    ip = index_of(self);
+                    regs = unsafe { self.stack.values.as_mut_ptr().add(self.stack.bottom) };
+                    continue
+                },
+
+
+                bytecode::COPY => set_reg!(op.a, reg!(op.b)),
+
+                bytecode::SWAP => {
+                    let val = reg!(op.a);
+                    set_reg!(op.a, reg!(op.b));
+                    set_reg!(op.b, val);
+                },
+
+                bytecode::SET => set_reg!(op.a, unsafe { *self.constants.get_unchecked(op.x as usize) }),
+
+                bytecode::PUSH => {
+                    if !self.stack.can_push(op.a as usize) {
+                        fallback!()
+                    }
+
+                    self.stack.top += op.a as usize;
+                },
+
+                bytecode::POP => self.stack.top -= op.a as usize,
+
+
+                bytecode::JMP => jump!(op.x),
+
+                bytecode::JIF => {
+                    let cond = reg!(op.a);
+                    jump!(if cond.is_true() { op.x } else { op.y })
+                },
+
+                bytecode::JNIF => {
+                    let cond = reg!(op.a);
+                    jump!(if !cond.is_true() { op.x } else { op.y })
+                },
+
+                bytecode::IJIF => {
+                    let cond = reg!(op.a);
+                    if cond.is_true() { jump!(op.x) }
+                },
+
+                bytecode::IJNIF => {
+                    let cond = reg!(op.a);
+                    if !cond.is_true() { jump!(op.x) }
+                },
+
+
+                bytecode::JLTI => compare_jump!(< , I64),
+                bytecode::JLTF => compare_jump!(< , F64),
+                bytecode::JLEI => compare_jump!(<=, I64),
+                bytecode::JLEF => compare_jump!(<=, F64),
+                bytecode::JEQI => compare_jump!(==, I64),
+                bytecode::JEQF => compare_jump!(==, F64),
+                bytecode::JNEI => compare_jump!(!=, I64),
+                bytecode::JNEF => compare_jump!(!=, F64),
+
+
+                bytecode::ADDI => binary!(wrapping_add, TAG_I64, I64),
+                bytecode::ADDU => binary!(wrapping_add, TAG_U64, U64),
+                bytecode::ADDF => binary!(+, TAG_F64, F64),
+                bytecode::SUBI => binary!(wrapping_sub, TAG_I64, I64),
+                bytecode::SUBU => binary!(wrapping_sub, TAG_U64, U64),
+                bytecode::SUBF => binary!(-, TAG_F64, F64),
+                bytecode::MULI => binary!(wrapping_mul, TAG_I64, I64),
+                bytecode::MULU => binary!(wrapping_mul, TAG_U64, U64),
+                bytecode::MULF => binary!(*, TAG_F64, F64),
+                bytecode::DIVI => division!(wrapping_div, TAG_I64, I64,   0),
+                bytecode::DIVU => division!(/, TAG_U64, U64,   0),
+                bytecode::DIVF => division!(/, TAG_F64, F64, 0.0),
+                bytecode::REMI => division!(wrapping_rem, TAG_I64, I64,   0),
+                bytecode::REMU => division!(%, TAG_U64, U64,   0),
+                bytecode::REMF => division!(%, TAG_F64, F64, 0.0),
+                bytecode::LSI  => binary!(shift_left , TAG_I64, I64),
+                bytecode::LSU  => binary!(shift_left , TAG_U64, U64),
+                bytecode::RSI  => binary!(shift_right, TAG_I64, I64),
+                bytecode::RSU  => binary!(shift_right, TAG_U64, U64),
+
+                bytecode::ANDI => binary!(&, TAG_I64, I64),
+                bytecode::ANDU => binary!(&, TAG_U64, U64),
+                bytecode::ORI  => binary!(|, TAG_I64, I64),
+                bytecode::ORU  => binary!(|, TAG_U64, U64),
+                bytecode::XORI => binary!(^, TAG_I64, I64),
+                bytecode::XORU => binary!(^, TAG_U64, U64),
+                bytecode::NOTI => unary!(not, TAG_I64, I64),
+                bytecode::NOTU => unary!(not, TAG_U64, U64),
+
+                bytecode::ANDB => set_reg!(op.a, Data::new_bool(reg!(op.b).is_true() & reg!(op.c).is_true())),
+                bytecode::ORB  => set_reg!(op.a, Data::new_bool(reg!(op.b).is_true() | reg!(op.c).is_true())),
+                bytecode::NOTB => set_reg!(op.a, Data::new_bool(!reg!(op.b).is_true())),
+
+                bytecode::NEGI => unary!(wrapping_neg, TAG_I64, I64),
+                bytecode::NEGF => unary!(neg, TAG_F64, F64),
+
+                bytecode::LSIW  => binary!(wrapping_shift_left , TAG_I64, I64),
+                bytecode::LSUW  => binary!(wrapping_shift_left , TAG_U64, U64),
+                bytecode::RSIW  => binary!(wrapping_shift_right, TAG_I64, I64),
+                bytecode::RSUW  => binary!(wrapping_shift_right, TAG_U64, U64),
+
+                bytecode::ADDIS => binary!(saturating_add, TAG_I64, I64),
+                bytecode::ADDUS => binary!(saturating_add, TAG_U64, U64),
+                bytecode::SUBIS => binary!(saturating_sub, TAG_I64, I64),
+                bytecode::SUBUS => binary!(saturating_sub, TAG_U64, U64),
+                bytecode::MULIS => binary!(saturating_mul, TAG_I64, I64),
+                bytecode::MULUS => binary!(saturating_mul, TAG_U64, U64),
+                bytecode::LSIS  => binary!(saturating_shift_left , TAG_I64, I64),
+                bytecode::LSUS  => binary!(saturating_shift_left , TAG_U64, U64),
+                bytecode::RSIS  => binary!(saturating_shift_right, TAG_I64, I64),
+                bytecode::RSUS  => binary!(saturating_shift_right, TAG_U64, U64),
+
+
+                bytecode::LTI => comparison!(binary!(< , I64, TAG_BOOL, Bool)),
+                bytecode::LTU => comparison!(binary!(< , U64, TAG_BOOL, Bool)),
+                bytecode::LTF => comparison!(binary!(< , F64, TAG_BOOL, Bool)),
+                bytecode::GTI => comparison!(binary!(> , I64, TAG_BOOL, Bool)),
+                bytecode::GTU => comparison!(binary!(> , U64, TAG_BOOL, Bool)),
+                bytecode::GTF => comparison!(binary!(> , F64, TAG_BOOL, Bool)),
+                bytecode::LEI => comparison!(binary!(<=, I64, TAG_BOOL, Bool)),
+                bytecode::LEU => comparison!(binary!(<=, U64, TAG_BOOL, Bool)),
+                bytecode::LEF => comparison!(binary!(<=, F64, TAG_BOOL, Bool)),
+                bytecode::GEI => comparison!(binary!(>=, I64, TAG_BOOL, Bool)),
+                bytecode::GEU => comparison!(binary!(>=, U64, TAG_BOOL, Bool)),
+                bytecode::GEF => comparison!(binary!(>=, F64, TAG_BOOL, Bool)),
+                bytecode::EQI => comparison!(binary!(==, I64, TAG_BOOL, Bool)),
+                bytecode::EQU => comparison!(binary!(==, U64, TAG_BOOL, Bool)),
+                bytecode::EQF => comparison!(binary!(==, F64, TAG_BOOL, Bool)),
+                bytecode::NEI => comparison!(binary!(!=, I64, TAG_BOOL, Bool)),
+                bytecode::NEU => comparison!(binary!(!=, U64, TAG_BOOL, Bool)),
+                bytecode::NEF => comparison!(binary!(!=, F64, TAG_BOOL, Bool)),
+
+
+                bytecode::ADDIK => immediate!(wrapping_add, TAG_I64, I64),
+                bytecode::ADDFK => immediate!(+, TAG_F64, F64),
+                bytecode::SUBIK => immediate!(wrapping_sub, TAG_I64, I64),
+                bytecode::SUBFK => immediate!(-, TAG_F64, F64),
+                bytecode::MULIK => immediate!(wrapping_mul, TAG_I64, I64),
+                bytecode::MULFK => immediate!(*, TAG_F64, F64),
+
+                bytecode::LTIK => comparison!(immediate!(< , I64, TAG_BOOL, Bool)),
+                bytecode::LTFK => comparison!(immediate!(< , F64, TAG_BOOL, Bool)),
+                bytecode::GTIK => comparison!(immediate!(> , I64, TAG_BOOL, Bool)),
+                bytecode::GTFK => comparison!(immediate!(> , F64, TAG_BOOL, Bool)),
+                bytecode::LEIK => comparison!(immediate!(<=, I64, TAG_BOOL, Bool)),
+                bytecode::LEFK => comparison!(immediate!(<=, F64, TAG_BOOL, Bool)),
+                bytecode::GEIK => comparison!(immediate!(>=, I64, TAG_BOOL, Bool)),
+                bytecode::GEFK => comparison!(immediate!(>=, F64, TAG_BOOL, Bool)),
+                bytecode::EQIK => comparison!(immediate!(==, I64, TAG_BOOL, Bool)),
+                bytecode::EQFK => comparison!(immediate!(==, F64, TAG_BOOL, Bool)),
+                bytecode::NEIK => comparison!(immediate!(!=, I64, TAG_BOOL, Bool)),
+                bytecode::NEFK => comparison!(immediate!(!=, F64, TAG_BOOL, Bool)),
+
+                _ => fallback!(),
+            }
+
+            ip += 1;
+        }
+    }
+
+
+    ///
+    /// `VM::execute` behind a call that's never inlined, the hot loop
+    /// falls back to it from a lot of places and a copy of it at each
+    /// one would make the frame of `run_threaded` huge in debug builds
+    ///
+    #[inline(never)]
+    fn execute_cold(&mut self) -> Result<bool, VmError> {
+        self.execute()
+    }
+}
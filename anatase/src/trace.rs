@@ -0,0 +1,235 @@
+use std::{io::{self, Write}, collections::{HashMap, HashSet}};
+
+use crate::{VM, Data, decoder::{self, Instruction}, errors::VmError, symbols::take};
+
+
+const MAGIC : &[u8] = b"ANTRACE\x02";
+
+
+///
+/// Writes every instruction the `VM` executes to a sink
+///
+/// The trace starts with `MAGIC`, after which every executed
+/// instruction is written as
+/// - its offset (u32) and the offset of its function (u32)
+/// - the length of the instruction (u16) and its bytes
+/// - the amount of registers it uses (u16) and for each of them
+///   the register (u8) and its value before and after the instruction
+///   (the tag and the bits of the value, two u64s each)
+///
+/// The instruction is stored as is and decoded again when the trace
+/// is read so a trace can be read without the program it came from
+///
+/// A `call` can be longer than 255 bytes and use all 256 registers
+/// so neither length fits in a u8
+///
+pub struct Tracer<W: Write> {
+    sink: W,
+    functions: Option<HashSet<usize>>,
+    instructions: HashMap<usize, Instruction>,
+    error: Option<io::Error>,
+    started: bool,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct TraceRecord {
+    pub offset: usize,
+    pub function: usize,
+    pub instruction: Instruction,
+    /// Every register the instruction uses, once, with its values before and after
+    pub registers: Vec<(u8, Data, Data)>,
+}
+
+
+impl<W: Write> Tracer<W> {
+    pub fn new(sink: W) -> Self {
+        Self {
+            sink,
+            functions: None,
+            instructions: HashMap::new(),
+            error: None,
+            started: false,
+        }
+    }
+
+
+    ///
+    /// Only traces the instructions of the functions that start
+    /// at one of `functions`, like the function of a `Location`
+    ///
+    pub fn with_filter(mut self, functions: impl IntoIterator<Item = usize>) -> Self {
+        self.functions = Some(functions.into_iter().collect());
+        self
+    }
+
+
+    ///
+    /// Flushes the sink and returns it, or the first error
+    /// writing to it ran into
+    ///
+    /// Tracing stops at the first error but the program keeps
+    /// running so it's only reported here
+    ///
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e)
+        }
+
+        if !self.started {
+            self.sink.write_all(MAGIC)?;
+        }
+
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+
+
+    fn traces(&self, function: usize) -> bool {
+        self.error.is_none() && self.functions.as_ref().is_none_or(|x| x.contains(&function))
+    }
+
+
+    fn write(&mut self, bytecode: &[u8], record: &TraceRecord) {
+        let result = (|| {
+            if !self.started {
+                self.sink.write_all(MAGIC)?;
+                self.started = true;
+            }
+
+            let instruction = &bytecode[record.offset..record.instruction.next()];
+
+            let mut bytes = Vec::with_capacity(12 + instruction.len() + record.registers.len() * 33);
+            bytes.extend_from_slice(&(record.offset as u32).to_le_bytes());
+            bytes.extend_from_slice(&(record.function as u32).to_le_bytes());
+            bytes.extend_from_slice(&(instruction.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(instruction);
+
+            bytes.extend_from_slice(&(record.registers.len() as u16).to_le_bytes());
+            for (register, before, after) in &record.registers {
+                bytes.push(*register);
+                for data in [before, after] {
+                    let (tag, bits) = data.to_bits();
+                    bytes.extend_from_slice(&tag.to_le_bytes());
+                    bytes.extend_from_slice(&bits.to_le_bytes());
+                }
+            }
+
+            self.sink.write_all(&bytes)
+        })();
+
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+
+///
+/// Reads a trace written by a `Tracer`, returns `None`
+/// if it's not a trace or if it's corrupt
+///
+pub fn parse(bytes: &[u8]) -> Option<Vec<TraceRecord>> {
+    let mut bytes = bytes.strip_prefix(MAGIC)?;
+    let mut records = vec![];
+
+    while !bytes.is_empty() {
+        let offset = u32::from_le_bytes(take(&mut bytes)?) as usize;
+        let function = u32::from_le_bytes(take(&mut bytes)?) as usize;
+
+        let len = u16::from_le_bytes(take(&mut bytes)?) as usize;
+        let instruction = bytes.get(..len)?;
+        bytes = &bytes[len..];
+
+        let mut instruction = decoder::decode(instruction, 0).ok()?;
+        instruction.offset = offset;
+
+        let count = u16::from_le_bytes(take(&mut bytes)?);
+        let mut registers = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let [register] = take(&mut bytes)?;
+            let mut data = || {
+                let tag = u64::from_le_bytes(take(&mut bytes)?);
+                let bits = u64::from_le_bytes(take(&mut bytes)?);
+                (Data::tag_name(tag) != "unknown").then(|| Data::from_bits(tag, bits))
+            };
+
+            let before = data()?;
+            let after = data()?;
+            registers.push((register, before, after));
+        }
+
+        records.push(TraceRecord { offset, function, instruction, registers });
+    }
+
+    Some(records)
+}
+
+
+impl<const DEBUG: bool> VM<DEBUG> {
+    ///
+    /// Runs the program like `VM::run` while writing every
+    /// executed instruction to `tracer`
+    ///
+    /// The registers are read from the frame the instruction was
+    /// executed in, even if it was a `call` or a `ret` that left it
+    ///
+    pub fn run_traced<W: Write>(&mut self, tracer: &mut Tracer<W>) -> Result<(), VmError> {
+        self.link_before_running()?;
+
+        let program = self.program.clone();
+        let bytecode = program.bytecode();
+
+        loop {
+            let location = self.location();
+            if !tracer.traces(location.function) {
+                if self.execute()? {
+                    return Ok(())
+                }
+
+                continue
+            }
+
+            let instruction = match tracer.instructions.get(&location.offset) {
+                Some(v) => v.clone(),
+                None => {
+                    let Ok(v) = decoder::decode(bytecode, location.offset)
+                    // let the `VM` report the invalid instruction
+                    else {
+                        if self.execute()? {
+                            return Ok(())
+                        }
+
+                        continue
+                    };
+
+                    tracer.instructions.insert(location.offset, v.clone());
+                    v
+                },
+            };
+
+            let bottom = self.stack.bottom;
+            let read = |vm: &Self, register: u8| vm.stack.values
+                .get(bottom + register as usize)
+                .copied()
+                .unwrap_or_else(Data::new_uninit);
+
+            let mut before : Vec<(u8, Data)> = Vec::with_capacity(instruction.operands.len());
+            for register in instruction.registers() {
+                if !before.iter().any(|x| x.0 == register) {
+                    before.push((register, read(self, register)));
+                }
+            }
+
+            let result = self.execute();
+
+            let registers = before.into_iter().map(|(x, v)| (x, v, read(self, x))).collect();
+            let record = TraceRecord { offset: location.offset, function: location.function, instruction, registers };
+            tracer.write(bytecode, &record);
+
+            if result? {
+                return Ok(())
+            }
+        }
+    }
+}
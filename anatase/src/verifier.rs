@@ -0,0 +1,221 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{bytecode, decoder::{self, Instruction, Value, DecodeError}, symbols::FunctionTable};
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    Decode(DecodeError),
+    InvalidTarget { offset: usize, target: u32 },
+    InvalidConstant { offset: usize, index: u16 },
+    InvalidImport { offset: usize, index: u16 },
+    InvalidRegister { offset: usize, register: u8, frame_size: usize },
+    ArgumentCount { offset: usize, expected: u8, found: usize },
+    StackUnderflow { offset: usize },
+    /// The registers of a `callw` aren't the last ones of the frame or go past `@255`
+    InvalidWindow { offset: usize, frame_size: usize },
+    /// Two paths reach the same instruction with frames of different sizes
+    FrameMismatch { offset: usize, expected: usize, found: usize },
+    /// A `ret` with a frame that isn't the size the caller expects back
+    InvalidReturn { offset: usize, expected: usize, found: usize },
+    FallsOffEnd { offset: usize },
+}
+
+
+///
+/// Checks that `bytecode` can be executed without any of the
+/// bounds checks `VM::<true>` does
+///
+/// - every instruction decodes fully
+/// - every jump targets the start of an instruction in the same function
+/// - every call targets the start of a function with the same argument count
+/// - every `set` refers to a constant that exists
+/// - every `callnative` refers to an import that exists
+/// - every register is inside the frame at that point of the function
+/// - every `callw` window is made up of the last registers of the frame
+/// - every path to an instruction reaches it with the same frame size
+/// - every `ret` leaves the frame the calling convention expects
+/// - execution can't run past the end of a function
+///
+/// The frame of a function starts with `argc + 1` registers and then
+/// grows and shrinks with `push` and `pop`. A function pops its own
+/// `@0` before it returns, leaving `argc` registers for `ret` to pop
+/// so calls give back the frame the way they found it. The code before
+/// the first function, the bootstrap, has a single register `main`
+/// returns into and ends the program with its `ret`.
+///
+pub fn verify(bytecode: &[u8], constant_count: usize, import_count: usize, functions: &FunctionTable) -> Result<(), VerifyError> {
+    let instructions = decoder::decode_all(bytecode).map_err(VerifyError::Decode)?;
+    let instructions : HashMap<usize, Instruction> = instructions.into_iter().map(|x| (x.offset, x)).collect();
+
+    // the offset, the frame size at the start and the one at `ret`
+    let mut starts : Vec<_> = functions.iter().map(|x| (x.offset, x.argc as usize + 1, Some(x.argc as usize))).collect();
+    starts.sort();
+
+    // the bootstrap, runs from the start until the first function
+    if starts.first().is_none_or(|x| x.0 != 0) {
+        starts.insert(0, (0, 1, None));
+    }
+
+
+    for (index, &(start, frame_size, returns)) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).map_or(bytecode.len(), |x| x.0);
+        if start >= bytecode.len() {
+            return Err(VerifyError::FallsOffEnd { offset: start })
+        }
+
+        verify_function(&instructions, functions, constant_count, import_count, start..end, frame_size, returns)?;
+    }
+
+    Ok(())
+}
+
+
+fn verify_function(
+    instructions: &HashMap<usize, Instruction>,
+    functions: &FunctionTable,
+    constant_count: usize,
+    import_count: usize,
+    range: std::ops::Range<usize>,
+    frame_size: usize,
+    returns: Option<usize>,
+) -> Result<(), VerifyError> {
+
+    // the frame size each instruction is reached with
+    let mut frame_sizes : HashMap<usize, usize> = HashMap::new();
+    let mut queue = vec![(range.start, frame_size)];
+
+    while let Some((offset, frame_size)) = queue.pop() {
+        let Some(instruction) = instructions.get(&offset)
+        else { return Err(VerifyError::InvalidTarget { offset, target: offset as u32 }) };
+
+        match frame_sizes.get(&offset) {
+            Some(&v) if v == frame_size => continue,
+            Some(&v) => return Err(VerifyError::FrameMismatch { offset, expected: v, found: frame_size }),
+            None => frame_sizes.insert(offset, frame_size),
+        };
+
+
+        for register in instruction.registers() {
+            if register as usize >= frame_size {
+                return Err(VerifyError::InvalidRegister { offset, register, frame_size })
+            }
+        }
+
+
+        let mut frame_size = frame_size;
+        let mut falls_through = true;
+
+        for operand in &instruction.operands {
+            match *operand {
+                Value::Constant(index) if index as usize >= constant_count
+                    => return Err(VerifyError::InvalidConstant { offset, index }),
+
+                Value::Import(index) if index as usize >= import_count
+                    => return Err(VerifyError::InvalidImport { offset, index }),
+
+
+                Value::Block(target) => {
+                    if !range.contains(&(target as usize)) || !instructions.contains_key(&(target as usize)) {
+                        return Err(VerifyError::InvalidTarget { offset, target })
+                    }
+
+                    queue.push((target as usize, frame_size));
+                },
+
+
+                Value::Function(target) => {
+                    let Some(function) = functions.at(target as usize)
+                    else { return Err(VerifyError::InvalidTarget { offset, target }) };
+
+                    let argc = match instruction.operands.last() {
+                        Some(Value::RegList(args)) => args.len(),
+                        Some(&Value::U8(argc)) => argc as usize,
+                        _ => unreachable!(),
+                    };
+
+                    if argc != function.argc as usize {
+                        return Err(VerifyError::ArgumentCount { offset, expected: function.argc, found: argc })
+                    }
+                },
+
+
+                Value::U8(amount) if instruction.opcode == bytecode::PUSH => frame_size += amount as usize,
+                Value::U8(amount) if instruction.opcode == bytecode::POP => {
+                    frame_size = match frame_size.checked_sub(amount as usize) {
+                        Some(v) => v,
+                        None => return Err(VerifyError::StackUnderflow { offset }),
+                    };
+                },
+
+                _ => (),
+            }
+        }
+
+
+        if let (bytecode::CALLW, &[Value::Reg(dst), _, Value::U8(argc)]) = (instruction.opcode, instruction.operands.as_slice()) {
+            if dst.checked_add(argc).is_none() || dst as usize + argc as usize + 1 != frame_size {
+                return Err(VerifyError::InvalidWindow { offset, frame_size })
+            }
+        }
+
+
+        if let (bytecode::RETURN, Some(expected)) = (instruction.opcode, returns) {
+            if frame_size != expected {
+                return Err(VerifyError::InvalidReturn { offset, expected, found: frame_size })
+            }
+        }
+
+
+        match instruction.opcode {
+            | bytecode::RETURN
+            | bytecode::TAILCALL
+            | bytecode::JMP
+            | bytecode::JIF
+            | bytecode::JNIF
+            | bytecode::JLTI
+            | bytecode::JLTF
+            | bytecode::JLEI
+            | bytecode::JLEF
+            | bytecode::JEQI
+            | bytecode::JEQF
+            | bytecode::JNEI
+            | bytecode::JNEF => falls_through = false,
+
+            _ => (),
+        }
+
+
+        if falls_through {
+            if !range.contains(&instruction.next()) {
+                return Err(VerifyError::FallsOffEnd { offset })
+            }
+
+            queue.push((instruction.next(), frame_size));
+        }
+    }
+
+    Ok(())
+}
+
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Decode(e) => write!(f, "{e}"),
+            VerifyError::InvalidTarget { offset, target } => write!(f, "invalid jump target {target} at offset {offset}"),
+            VerifyError::InvalidConstant { offset, index } => write!(f, "constant {index} doesn't exist at offset {offset}"),
+            VerifyError::InvalidImport { offset, index } => write!(f, "import {index} doesn't exist at offset {offset}"),
+            VerifyError::InvalidRegister { offset, register, frame_size } => write!(f, "register @{register} is outside of the frame of size {frame_size} at offset {offset}"),
+            VerifyError::ArgumentCount { offset, expected, found } => write!(f, "the function expects {expected} arguments but {found} were given at offset {offset}"),
+            VerifyError::StackUnderflow { offset } => write!(f, "popped more values than the frame has at offset {offset}"),
+            VerifyError::InvalidWindow { offset, frame_size } => write!(f, "the call window doesn't end the frame of size {frame_size} at offset {offset}"),
+            VerifyError::FrameMismatch { offset, expected, found } => write!(f, "the frame has size {found} here but {expected} on another path at offset {offset}"),
+            VerifyError::InvalidReturn { offset, expected, found } => write!(f, "returns with a frame of size {found} instead of {expected} at offset {offset}"),
+            VerifyError::FallsOffEnd { offset } => write!(f, "execution runs past the end of the function at offset {offset}"),
+        }
+    }
+}
+
+
+impl std::error::Error for VerifyError {}
@@ -0,0 +1,397 @@
+use std::{process::Command, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
+
+use anatase::{VM, aot::{self, TranslateError}, program::{Program, Constant}};
+
+mod common;
+
+use common::program;
+
+
+///
+/// What `program` ends with when the `VM` runs it and when its
+/// translation does, the result line or the error
+///
+/// `None` if there's no C compiler to build the translation with
+///
+fn run(program: Program) -> Option<(String, String)> {
+    static BUILDS : AtomicUsize = AtomicUsize::new(0);
+
+    let source = aot::translate(&program).unwrap();
+
+    let program = Arc::new(program);
+    let mut vm = VM::<true>::new(program);
+    let interpreted = match vm.run() {
+        Ok(()) => format!("result is {}", vm.format(vm.stack.reg(0))),
+        Err(e) => format!("error: {e}"),
+    };
+
+
+    let dir = std::env::temp_dir().join(format!(
+        "anatase-aot-{}-{}",
+        std::process::id(), BUILDS.fetch_add(1, Ordering::Relaxed),
+    ));
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("program.c"), source).unwrap();
+    std::fs::write(dir.join("anatase.h"), aot::RUNTIME).unwrap();
+
+    let status = Command::new("cc")
+        .current_dir(&dir)
+        .args(["-std=c99", "-O2", "-o", "program", "program.c", "-lm"])
+        .status();
+
+    let Ok(status) = status
+    else {
+        eprintln!("skipping, there's no C compiler");
+        return None
+    };
+
+    assert!(status.success(), "the translation doesn't compile");
+
+
+    let output = Command::new(dir.join("program")).output().unwrap();
+    let translated = match output.status.success() {
+        true => String::from_utf8(output.stdout).unwrap().lines().last().unwrap().to_string(),
+        false => String::from_utf8(output.stderr).unwrap().lines().next().unwrap().to_string(),
+    };
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Some((interpreted, translated))
+}
+
+
+#[test]
+fn numeric_loop() {
+    let Some((interpreted, translated)) = run(common::numeric_loop(30.0))
+    else { return };
+
+    assert_eq!(interpreted, "result is float 832040.0");
+    assert_eq!(translated, interpreted);
+}
+
+
+#[test]
+fn recursion() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 2,                           // push 2
+        3, 1, 0, 0,                     // set @1 20
+        50, 0, 25, 0, 0, 0, 1, 1,       // call @0 fib @1
+        8, 3,                           // pop 3
+        0,                              // ret
+
+        // fib
+        7, 3,                           // push 3
+        3, 2, 1, 0,                     // set @2 2
+        14, 1, 2, 75, 0, 0, 0, 42, 0, 0, 0, // jlti @1 @2 $done $recurse
+
+        // $recurse (42)
+        162, 3, 1, 2, 0,                // subik @3 @1 1
+        50, 3, 25, 0, 0, 0, 1, 3,       // call @3 fib @3
+        162, 4, 1, 1, 0,                // subik @4 @1 2
+        50, 4, 25, 0, 0, 0, 1, 4,       // call @4 fib @4
+        100, 0, 3, 4,                   // addi @0 @3 @4
+        8, 4,                           // pop 4
+        0,                              // ret
+
+        // $done (75)
+        1, 0, 1,                        // cpy @0 @1
+        8, 4,                           // pop 4
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(20), Constant::Int(2), Constant::Int(1)];
+    let Some((interpreted, translated)) = run(program(bytecode, constants, &[(8, 0, "main"), (25, 1, "fib")]))
+    else { return };
+
+    assert_eq!(interpreted, "result is int 6765");
+    assert_eq!(translated, interpreted);
+}
+
+
+#[test]
+fn windows_and_tail_calls() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 3,                           // push 3
+        3, 2, 0, 0,                     // set @2 100000
+        3, 3, 1, 0,                     // set @3 0
+        52, 1, 31, 0, 0, 0, 2,          // callw @1 sum @2 @3
+        1, 0, 1,                        // cpy @0 @1
+        8, 4,                           // pop 4
+        0,                              // ret
+
+        // sum
+        7, 1,                           // push 1
+        3, 3, 1, 0,                     // set @3 0
+        18, 1, 3, 65, 0, 0, 0, 48, 0, 0, 0, // jeqi @1 @3 $done $next
+
+        // $next (48)
+        100, 3, 2, 1,                   // addi @3 @2 @1
+        162, 1, 1, 2, 0,                // subik @1 @1 1
+        53, 31, 0, 0, 0, 2, 1, 3,       // tailcall sum @1 @3
+
+        // $done (65)
+        1, 0, 2,                        // cpy @0 @2
+        8, 2,                           // pop 2
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(100_000), Constant::Int(0), Constant::Int(1)];
+    let Some((interpreted, translated)) = run(program(bytecode, constants, &[(8, 0, "main"), (31, 2, "sum")]))
+    else { return };
+
+    assert_eq!(interpreted, "result is int 5000050000");
+    assert_eq!(translated, interpreted);
+}
+
+
+#[test]
+fn tail_calls_between_functions() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 1,                           // push 1
+        3, 1, 0, 0,                     // set @1 1000001
+        50, 0, 25, 0, 0, 0, 1, 1,       // call @0 even @1
+        8, 2,                           // pop 2
+        0,                              // ret
+
+        // even
+        7, 1,                           // push 1
+        3, 2, 1, 0,                     // set @2 0
+        18, 1, 2, 54, 0, 0, 0, 42, 0, 0, 0, // jeqi @1 @2 $done $next
+
+        // $next (42)
+        162, 1, 1, 2, 0,                // subik @1 @1 1
+        53, 60, 0, 0, 0, 1, 1,          // tailcall odd @1
+
+        // $done (54)
+        1, 0, 2,                        // cpy @0 @2
+        8, 2,                           // pop 2
+        0,                              // ret
+
+        // odd
+        7, 1,                           // push 1
+        3, 2, 1, 0,                     // set @2 0
+        18, 1, 2, 89, 0, 0, 0, 77, 0, 0, 0, // jeqi @1 @2 $done $next
+
+        // $next (77)
+        162, 1, 1, 2, 0,                // subik @1 @1 1
+        53, 25, 0, 0, 0, 1, 1,          // tailcall even @1
+
+        // $done (89)
+        3, 0, 2, 0,                     // set @0 1
+        8, 2,                           // pop 2
+        0,                              // ret
+    ];
+
+    // deep enough to overflow the C stack if every
+    // tail call was a call that returned afterwards
+    let constants = vec![Constant::Int(1_000_001), Constant::Int(0), Constant::Int(1)];
+    let Some((interpreted, translated)) = run(program(bytecode, constants, &[(8, 0, "main"), (25, 1, "even"), (60, 1, "odd")]))
+    else { return };
+
+    assert_eq!(interpreted, "result is int 1");
+    assert_eq!(translated, interpreted);
+}
+
+
+#[test]
+fn floats_print_the_same() {
+    let floats = [0.1 + 0.2, 1e20, 1e16, 9999999999999998.0, 1e-7, 0.0001, -0.0, 123456.789, f64::MAX, f64::NAN, f64::NEG_INFINITY];
+
+    for float in floats {
+        let bytecode = vec![
+            3, 0, 0, 0, // set @0 #0
+            0,          // ret
+        ];
+
+        let Some((interpreted, translated)) = run(program(bytecode, vec![Constant::Float(float)], &[]))
+        else { return };
+
+        assert_eq!(translated, interpreted);
+    }
+}
+
+
+#[test]
+fn errors_are_reported_the_same() {
+    let division = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,    // call @0 main, ret
+
+        // main
+        7, 3,                       // push 3
+        3, 1, 0, 0,                 // set @1 10
+        3, 2, 1, 0,                 // set @2 0
+
+        // $loop (18)
+        162, 1, 1, 2, 0,            // subik @1 @1 1
+        14, 2, 1, 18, 0, 0, 0, 34, 0, 0, 0, // jlti @2 @1 $loop $end
+
+        // $end (34)
+        109, 0, 2, 1,               // divi @0 @2 @1
+        8, 4,                       // pop 4
+        0,                          // ret
+    ];
+
+    let constants = vec![Constant::Int(10), Constant::Int(0), Constant::Int(1)];
+    let Some((interpreted, translated)) = run(program(division, constants, &[(8, 0, "main")]))
+    else { return };
+
+    assert_eq!(interpreted, "error: division by zero at offset 34 in function 8");
+    assert_eq!(translated, interpreted);
+
+
+    let recursion = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,    // call @0 main, ret
+
+        // main
+        50, 0, 8, 0, 0, 0, 0,       // call @0 main
+        8, 1,                       // pop 1
+        0,                          // ret
+    ];
+
+    let Some((interpreted, translated)) = run(program(recursion, vec![], &[(8, 0, "main")]))
+    else { return };
+
+    assert_eq!(interpreted, "error: stack overflow at offset 8 in function 8");
+    assert_eq!(translated, interpreted);
+
+
+    let mismatch = vec![
+        7, 2,         // push 2
+        3, 1, 0, 0,   // set @1 1.5
+        100, 0, 1, 1, // addi @0 @1 @1
+        0,            // ret
+    ];
+
+    let Some((interpreted, translated)) = run(program(mismatch, vec![Constant::Float(1.5)], &[]))
+    else { return };
+
+    assert_eq!(interpreted, "error: type mismatch, expected int found float at offset 6 in function 0");
+    assert_eq!(translated, interpreted);
+}
+
+
+#[test]
+fn integers_overflow_the_same() {
+    // (opcode, lhs, rhs, whether the operands are cast to uints)
+    let cases = [
+        (100, i64::MAX, 1, false),                  // addi
+        (109, i64::MIN, -1, false),                 // divi
+        (112, i64::MIN, -1, false),                 // remi
+        (115, 1, 64, false),                        // lsi
+        (118, -8, -1, false),                       // rsi
+        (119, 1, 64, true),                         // rsu
+        (200, i64::MAX, 1, false),                  // addi_c
+        (202, i64::MIN, 1, false),                  // subi_c
+        (204, 3_000_000_000, 4_000_000_000, false), // muli_c
+        (204, i64::MIN, -1, false),                 // muli_c
+        (206, -1, 63, false),                       // lsi_c
+        (206, 1, 63, false),                        // lsi_c
+        (208, 1, 64, false),                        // rsi_c
+        (205, 1 << 32, 1 << 32, true),              // mulu_c
+        (207, 3, 62, true),                         // lsu_c
+        (216, 1, 65, false),                        // lsi_w
+        (218, -8, 65, false),                       // rsi_w
+        (220, i64::MIN, -1, false),                 // addi_s
+        (222, i64::MAX, -1, false),                 // subi_s
+        (224, i64::MIN, -2, false),                 // muli_s
+        (226, -3, 63, false),                       // lsi_s
+        (223, 1, 2, true),                          // subu_s
+        (227, 3, 63, true),                         // lsu_s
+    ];
+
+    for (opcode, lhs, rhs, uint) in cases {
+        let mut bytecode = vec![
+            7, 3,       // push 3
+            3, 1, 0, 0, // set @1 #0
+            3, 2, 1, 0, // set @2 #1
+        ];
+
+        if uint {
+            bytecode.extend_from_slice(&[
+                150, 1, 1, // cast_iu @1 @1
+                150, 2, 2, // cast_iu @2 @2
+            ]);
+        }
+
+        bytecode.extend_from_slice(&[opcode, 0, 1, 2, 0]);
+
+        let constants = vec![Constant::Int(lhs), Constant::Int(rhs)];
+        let Some((interpreted, translated)) = run(program(bytecode, constants, &[]))
+        else { return };
+
+        assert_eq!(translated, interpreted, "opcode {opcode}");
+    }
+}
+
+
+#[test]
+fn bitwise_and_logical() {
+    let cases = [
+        (vec![
+            180, 3, 1, 2,   // andi @3 @1 @2
+            184, 3, 3, 1,   // xori @3 @3 @1
+            186, 3, 3,      // noti @3 @3
+            191, 3, 3,      // negi @3 @3
+            182, 0, 3, 2,   // ori @0 @3 @2
+        ], vec![Constant::Int(12), Constant::Int(10)]),
+
+        (vec![
+            150, 1, 1,      // cast_iu @1 @1
+            150, 2, 2,      // cast_iu @2 @2
+            181, 3, 1, 2,   // andu @3 @1 @2
+            185, 3, 3, 1,   // xoru @3 @3 @1
+            187, 3, 3,      // notu @3 @3
+            183, 0, 3, 2,   // oru @0 @3 @2
+        ], vec![Constant::Int(12), Constant::Int(10)]),
+
+        (vec![
+            190, 3, 2,      // notb @3 @2
+            188, 3, 3, 1,   // andb @3 @3 @1
+            189, 0, 2, 3,   // orb @0 @2 @3
+        ], vec![Constant::Bool(true), Constant::Bool(false)]),
+
+        (vec![
+            192, 0, 1,      // negf @0 @1
+        ], vec![Constant::Float(1.5), Constant::Float(0.0)]),
+    ];
+
+    for (ops, constants) in cases {
+        let mut bytecode = vec![
+            7, 3,       // push 3
+            3, 1, 0, 0, // set @1 #0
+            3, 2, 1, 0, // set @2 #1
+        ];
+
+        bytecode.extend_from_slice(&ops);
+        bytecode.push(0);
+
+        let Some((interpreted, translated)) = run(program(bytecode, constants, &[]))
+        else { return };
+
+        assert_eq!(translated, interpreted);
+    }
+}
+
+
+#[test]
+fn strings_need_a_vm() {
+    let bytecode = vec![
+        3, 0, 0, 0, // set @0 "hi"
+        0,          // ret
+    ];
+
+    let program = program(bytecode, vec![Constant::Str("hi".into())], &[]);
+    assert_eq!(
+        aot::translate(&program),
+        Err(TranslateError::Unsupported { offset: 0, mnemonic: "set" }),
+    );
+}
@@ -0,0 +1,44 @@
+use anatase::{program::{Program, Constant}, symbols::FunctionTable};
+
+
+pub fn program(bytecode: Vec<u8>, constants: Vec<Constant>, entries: &[(u32, u8, &str)]) -> Program {
+    Program::new(constants, bytecode, FunctionTable::new(entries), vec![], None).unwrap()
+}
+
+
+///
+/// The loop of `fib` in `test.an`, `n` times
+///
+/// `@0` ends up as the `n`th fibonacci number
+///
+pub fn numeric_loop(n: f64) -> Program {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,    // call @0 main, ret
+
+        // main
+        7, 6,                       // push 6
+        3, 1, 0, 0,                 // set @1 n
+        3, 2, 1, 0,                 // set @2 0.0
+        3, 3, 2, 0,                 // set @3 1.0
+        3, 4, 1, 0,                 // set @4 0.0
+
+        // $loop-cond (26)
+        132, 5, 4, 1,               // ltf @5 @4 @1
+        9, 5, 40, 0, 0, 0, 60, 0, 0, 0, // jif @5 $loop-body $end
+
+        // $loop-body (40)
+        102, 5, 2, 3,               // addf @5 @2 @3
+        1, 2, 3,                    // cpy @2 @3
+        1, 3, 5,                    // cpy @3 @5
+        161, 4, 4, 2, 0,            // addfk @4 @4 1.0
+        11, 26, 0, 0, 0,            // jmp $loop-cond
+
+        // $end (60)
+        1, 0, 2,                    // cpy @0 @2
+        8, 7,                       // pop 7
+        0,                          // ret
+    ];
+
+    let constants = vec![Constant::Float(n), Constant::Float(0.0), Constant::Float(1.0)];
+    program(bytecode, constants, &[(8, 0, "main")])
+}
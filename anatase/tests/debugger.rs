@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use anatase::{VM, Data, errors::{VmError, Location}, program::{Program, Constant}, symbols::FunctionTable, debug_info::DebugInfo, debugger::{Breakpoint, BreakpointError, Stop}};
+
+
+fn str(bytes: &mut Vec<u8>, str: &str) {
+    bytes.extend_from_slice(&(str.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(str.as_bytes());
+}
+
+
+fn vm() -> VM<true> {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 1,                           // push 1
+        3, 1, 0, 0,                     // set @1 0
+        50, 0, 25, 0, 0, 0, 1, 1,       // call @0 double @1
+        8, 2,                           // pop 2
+        0,                              // ret
+
+        // double
+        102, 0, 1, 1,                   // addf @0 @1 @1
+        8, 1,                           // pop 1
+        0,                              // ret
+    ];
+
+    let mut debug_info = vec![];
+    str(&mut debug_info, "test.an");
+    str(&mut debug_info, "");
+    debug_info.extend_from_slice(&0u32.to_le_bytes());
+    debug_info.extend_from_slice(&25u32.to_le_bytes());
+    str(&mut debug_info, "entry");
+
+    let program = Program::new(
+        vec![Constant::Float(21.0)],
+        bytecode,
+        FunctionTable::new(&[(8, 0, "main"), (25, 1, "double")]),
+        vec![],
+        DebugInfo::from_bytes(&debug_info),
+    ).unwrap();
+
+    VM::<true>::new(Arc::new(program))
+}
+
+
+#[test]
+fn breakpoints() {
+    let mut vm = vm();
+
+    assert_eq!(vm.set_breakpoint(Breakpoint::Offset(26)), Err(BreakpointError::NotAnInstruction(26)));
+    assert_eq!(vm.set_breakpoint(Breakpoint::Block { function: "main", block: "entry" }), Err(BreakpointError::UnknownBlock("entry".to_string())));
+    assert_eq!(vm.set_breakpoint(Breakpoint::Block { function: "double", block: "entry" }), Ok(25));
+
+    assert_eq!(vm.resume(), Ok(Stop::Breakpoint(25)));
+    assert_eq!(vm.depth(), 2);
+    assert_eq!(vm.registers()[1].as_f64(), Some(21.0));
+
+    assert_eq!(vm.step(), Ok(Stop::Step));
+    assert_eq!(vm.offset(), 29);
+    assert_eq!(vm.registers()[0].as_f64(), Some(42.0));
+
+    assert_eq!(vm.step_out(), Ok(Stop::Step));
+    assert_eq!(vm.offset(), 22);
+    assert_eq!(vm.depth(), 1);
+
+    assert_eq!(vm.resume(), Ok(Stop::Finished));
+}
+
+
+#[test]
+fn step_over_call() {
+    let mut vm = vm();
+
+    for _ in 0..3 {
+        assert_eq!(vm.step(), Ok(Stop::Step));
+    }
+
+    assert_eq!(vm.offset(), 14);
+    assert_eq!(vm.step_over(), Ok(Stop::Step));
+    assert_eq!(vm.offset(), 22);
+    assert_eq!(vm.registers()[0].as_f64(), Some(42.0));
+}
+
+
+fn add(_: &mut VM<true>, args: &[Data]) -> Result<Data, VmError> {
+    Ok(Data::new_i64(args[0].as_i64().unwrap() + args[1].as_i64().unwrap()))
+}
+
+
+#[test]
+fn step_onto_native() {
+    let bytecode = vec![
+        7, 3,                   // push 3
+        3, 1, 0, 0,             // set @1 0
+        3, 2, 0, 0,             // set @2 0
+        51, 0, 0, 0, 2, 1, 2,   // callnative @0 add @1 @2
+        0,
+    ];
+
+    let program = Program::new(vec![Constant::Int(20)], bytecode, FunctionTable::default(), vec!["add".to_string()], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+
+    // nothing steps while a native is missing
+    assert_eq!(vm.step(), Err(VmError::UnresolvedImport(Location { offset: 0, function: 0 }, 0)));
+    assert_eq!(vm.offset(), 0);
+
+    vm.register_native("add", add);
+
+    for _ in 0..4 {
+        assert_eq!(vm.step(), Ok(Stop::Step));
+    }
+
+    assert_eq!(vm.registers()[0].as_i64(), Some(40));
+    assert_eq!(vm.step(), Ok(Stop::Finished));
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use anatase::{VM, Data, Limits, embed::CallError, errors::VmError, native::LinkError, program::{Program, Constant, LoadError}, symbols::FunctionTable};
+use archiver::Packed;
+
+
+fn program() -> Vec<u8> {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0, // call @0 main, ret
+
+        // main
+        8, 1,        // pop 1
+        0,           // ret
+
+        // double
+        102, 0, 1, 1, // addf @0 @1 @1
+        8, 1,         // pop 1
+        0,            // ret
+    ];
+
+    let functions = FunctionTable::new(&[(8, 0, "main"), (11, 1, "double")]);
+
+    Packed::new()
+        .with(archiver::Data(vec![]))
+        .with(archiver::Data(bytecode))
+        .with(archiver::Data(functions.to_bytes()))
+        .with(archiver::Data(vec![]))
+        .as_bytes()
+}
+
+
+#[test]
+fn call_by_name() {
+    let mut vm = VM::<false>::load(&program()).unwrap();
+
+    for i in 0..3 {
+        let result = vm.call("double", &[Data::new_f64(i as f64)]).unwrap();
+        assert_eq!(result.as_f64(), Some(i as f64 * 2.0));
+    }
+}
+
+
+#[test]
+fn unchecked_arguments() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        8, 1,                           // pop 1
+        0,                              // ret
+
+        // choose
+        9, 1, 21, 0, 0, 0, 28, 0, 0, 0, // jif @1 $yes $no
+
+        // $yes (21)
+        3, 0, 0, 0,                     // set @0 1
+        8, 1,                           // pop 1
+        0,                              // ret
+
+        // $no (28)
+        3, 0, 1, 0,                     // set @0 0
+        8, 1,                           // pop 1
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(1), Constant::Int(0)];
+    let functions = FunctionTable::new(&[(8, 0, "main"), (11, 1, "choose")]);
+    let program = Program::new(constants, bytecode, functions, vec![], None).unwrap();
+    let mut vm = VM::<false>::new(Arc::new(program)).unwrap();
+
+    let mut choose = |arg| vm.call("choose", &[arg]).unwrap().as_i64();
+    assert_eq!(choose(Data::new_bool(true)), Some(1));
+    assert_eq!(choose(Data::new_bool(false)), Some(0));
+
+    // nothing checks that the condition is a bool
+    assert_eq!(choose(Data::new_i64(1)), Some(1));
+    assert_eq!(choose(Data::new_uninit()), Some(0));
+}
+
+
+#[test]
+fn call_results_stay_alive() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        8, 1,                           // pop 1
+        0,                              // ret
+
+        // greet
+        7, 2,                           // push 2
+        3, 1, 0, 0,                     // set @1 "hello "
+        3, 2, 1, 0,                     // set @2 "world"
+        60, 0, 1, 2,                    // strcat @0 @1 @2
+        8, 3,                           // pop 3
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Str("hello ".into()), Constant::Str("world".into())];
+    let functions = FunctionTable::new(&[(8, 0, "main"), (11, 0, "greet")]);
+    let program = Program::new(constants, bytecode, functions, vec![], None).unwrap();
+    let mut vm = VM::<false>::new(Arc::new(program)).unwrap();
+
+    // nothing in the program refers to the first string anymore
+    let first = vm.call("greet", &[]).unwrap();
+    assert_eq!(vm.collect_garbage(), 0);
+    let second = vm.call("greet", &[]).unwrap();
+
+    assert_ne!(first.as_object(), second.as_object());
+    assert_eq!(vm.memory.string(first), Some("hello world"));
+
+    vm.release(first);
+    vm.release(second);
+    assert_eq!(vm.collect_garbage(), 2);
+}
+
+
+#[test]
+fn too_many_constants() {
+    // one more empty string than a program can have
+    let mut constants = vec![];
+    for _ in 0..=1 << 16 {
+        constants.push(2);
+        constants.extend_from_slice(&0u64.to_le_bytes());
+    }
+
+    let bytes = Packed::new()
+        .with(archiver::Data(constants))
+        .with(archiver::Data(vec![0]))
+        .with(archiver::Data(vec![]))
+        .with(archiver::Data(vec![]))
+        .as_bytes();
+
+    assert_eq!(VM::<true>::load(&bytes).unwrap_err(), LoadError::TooManyConstants((1 << 16) + 1));
+
+    let strings = vec![Constant::Str("".into()); (1 << 16) + 1];
+    let program = Program::new(strings, vec![0], FunctionTable::default(), vec![], None);
+    assert_eq!(program.unwrap_err(), LoadError::TooManyConstants((1 << 16) + 1));
+
+    // the string constants don't take up the room the program gets
+    let free = |constants| {
+        let program = Program::new(constants, vec![0], FunctionTable::default(), vec![], None).unwrap();
+        let vm = VM::<true>::new(Arc::new(program));
+        vm.memory.capacity() - vm.memory.live()
+    };
+
+    assert_eq!(free(vec![Constant::Str("".into()); 1 << 16]), free(vec![]));
+}
+
+
+#[test]
+fn call_errors() {
+    let mut vm = VM::<true>::load(&program()).unwrap();
+
+    assert_eq!(vm.call("triple", &[]).unwrap_err(), CallError::UnknownFunction("triple".to_string()));
+    assert_eq!(vm.call("double", &[]).unwrap_err(), CallError::ArgumentCount { expected: 1, found: 0 });
+    assert!(matches!(vm.call("double", &[Data::new_i64(1)]), Err(CallError::Runtime { .. })));
+
+    // the vm is still usable after a failed call
+    let result = vm.call("double", &[Data::new_f64(4.0)]).unwrap();
+    assert_eq!(result.as_f64(), Some(8.0));
+}
+
+
+fn reenter<const DEBUG: bool>(vm: &mut VM<DEBUG>, _: &[Data]) -> Result<Data, VmError> {
+    match vm.call("recurse", &[]) {
+        Ok(v) => Ok(v),
+        Err(CallError::Runtime { error, .. }) => Err(error),
+        Err(e) => panic!("{e}"),
+    }
+}
+
+
+#[test]
+fn reentrant_native() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0, // call @0 recurse, ret
+
+        // recurse
+        51, 0, 0, 0, 0, // callnative @0 reenter
+        8, 1,           // pop 1
+        0,              // ret
+    ];
+
+    let functions = FunctionTable::new(&[(8, 0, "recurse")]);
+    let program = Program::new(vec![], bytecode, functions, vec!["reenter".to_string()], None).unwrap();
+
+    let mut vm = VM::<true>::new(Arc::new(program));
+    assert_eq!(vm.call("recurse", &[]).unwrap_err(), CallError::Link(LinkError::MissingImport("reenter".to_string())));
+
+    vm.register_native("reenter", reenter);
+
+    // the call depth stops the first, the nesting of `call`s the second
+    for call_depth in [10, Limits::default().call_depth] {
+        vm.set_limits(Limits { call_depth, ..Limits::default() });
+
+        assert!(matches!(
+            vm.call("recurse", &[]),
+            Err(CallError::Runtime { error: VmError::StackOverflow(_), .. }),
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "jit")]
+
+use std::sync::Arc;
+
+use anatase::{VM, errors::{VmError, Location}, program::{Program, Constant}};
+
+mod common;
+
+use common::program;
+
+
+/// A `VM<false>` that compiles every function the first time it's called
+fn compiled(program: Arc<Program>) -> VM<false> {
+    let mut compiled = VM::<false>::new(program).unwrap();
+    compiled.set_jit_threshold(0);
+    compiled
+}
+
+
+#[test]
+fn numeric_loop() {
+    let program = Arc::new(common::numeric_loop(30.0));
+
+    let mut interpreted = VM::<true>::new(program.clone());
+    assert_eq!(interpreted.run(), Ok(()));
+
+    let mut compiled = compiled(program);
+    assert_eq!(compiled.run(), Ok(()));
+    assert_ne!(compiled.jit_compiled_count(), 0);
+
+    assert_eq!(compiled.stack.reg(0).as_f64(), Some(832040.0));
+    assert_eq!(compiled.stack.reg(0).as_f64(), interpreted.stack.reg(0).as_f64());
+}
+
+
+#[test]
+fn calls_leave_the_compiled_code() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 3,                           // push 3
+        3, 2, 0, 0,                     // set @2 100000
+        3, 3, 1, 0,                     // set @3 0
+        50, 1, 33, 0, 0, 0, 2, 2, 3,    // call @1 sum @2 @3
+        1, 0, 1,                        // cpy @0 @1
+        8, 4,                           // pop 4
+        0,                              // ret
+
+        // sum
+        7, 1,                           // push 1
+        3, 3, 1, 0,                     // set @3 0
+        18, 1, 3, 67, 0, 0, 0, 50, 0, 0, 0, // jeqi @1 @3 $done $next
+
+        // $next (50)
+        100, 3, 2, 1,                   // addi @3 @2 @1
+        162, 1, 1, 2, 0,                // subik @1 @1 1
+        53, 33, 0, 0, 0, 2, 1, 3,       // tailcall sum @1 @3
+
+        // $done (67)
+        1, 0, 2,                        // cpy @0 @2
+        8, 2,                           // pop 2
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(100_000), Constant::Int(0), Constant::Int(1)];
+    let program = Arc::new(program(bytecode, constants, &[(8, 0, "main"), (33, 2, "sum")]));
+
+    let mut compiled = compiled(program);
+    assert_eq!(compiled.run(), Ok(()));
+    assert_eq!(compiled.stack.reg(0).as_i64(), Some(5_000_050_000));
+}
+
+
+#[test]
+fn unsupported_instructions_fall_back() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,    // call @0 main, ret
+
+        // main
+        7, 3,                       // push 3
+        3, 1, 0, 0,                 // set @1 10
+        3, 2, 1, 0,                 // set @2 0
+
+        // $loop (18)
+        162, 1, 1, 2, 0,            // subik @1 @1 1
+        14, 2, 1, 18, 0, 0, 0, 34, 0, 0, 0, // jlti @2 @1 $loop $end
+
+        // $end (34)
+        109, 0, 2, 1,               // divi @0 @2 @1
+        8, 4,                       // pop 4
+        0,                          // ret
+    ];
+
+    let constants = vec![Constant::Int(10), Constant::Int(0), Constant::Int(1)];
+    let program = Arc::new(program(bytecode, constants, &[(8, 0, "main")]));
+
+    let mut interpreted = VM::<true>::new(program.clone());
+    let mut compiled = compiled(program);
+
+    let error = Err(VmError::DivisionByZero(Location { offset: 34, function: 8 }));
+    assert_eq!(interpreted.run(), error);
+    assert_eq!(compiled.run(), error);
+}
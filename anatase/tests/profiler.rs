@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use anatase::{VM, program::{Program, Constant}, profiler::{OpcodeProfile, FunctionProfile, Weight}, symbols::FunctionTable};
+
+
+#[test]
+fn opcode_counts() {
+    let bytecode = [
+        7, 3,         // push 3
+        3, 1, 0, 0,   // set @1 0
+        3, 2, 0, 0,   // set @2 0
+        100, 0, 1, 2, // addi @0 @1 @2
+        0,            // ret
+    ];
+
+    let program = Program::new(vec![Constant::Int(2)], bytecode.to_vec(), FunctionTable::default(), vec![], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+
+    let mut profile = OpcodeProfile::new(false);
+    vm.run_profiled(&mut profile).unwrap();
+
+    let entries : Vec<_> = profile.entries().iter().map(|x| (x.mnemonic, x.count)).collect();
+    assert_eq!(entries, [("set", 2), ("ret", 1), ("push", 1), ("addi", 1)]);
+    assert_eq!(profile.total(), 5);
+    assert!(profile.to_json().starts_with(r#"[{"opcode":3,"mnemonic":"set","count":2,"time_ns":null}"#));
+}
+
+
+#[test]
+fn folded_stacks() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 1,                           // push 1
+        3, 1, 0, 0,                     // set @1 0
+        50, 0, 25, 0, 0, 0, 1, 1,       // call @0 double @1
+        8, 2,                           // pop 2
+        0,                              // ret
+
+        // double
+        102, 0, 1, 1,                   // addf @0 @1 @1
+        8, 1,                           // pop 1
+        0,                              // ret
+    ];
+
+    let functions = FunctionTable::new(&[(8, 0, "main"), (25, 1, "double")]);
+    let program = Program::new(vec![Constant::Float(1.0)], bytecode, functions.clone(), vec![], None).unwrap();
+    let mut vm = VM::<false>::new(Arc::new(program)).unwrap();
+
+    let mut profile = FunctionProfile::new();
+    vm.run_function_profiled(&mut profile).unwrap();
+
+    assert_eq!(
+        profile.folded(&functions, Weight::Instructions),
+        "<bootstrap> 2\n<bootstrap>;main 5\n<bootstrap>;main;double 3\n",
+    );
+}
+
+
+#[test]
+fn tailcalls_replace_the_function() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 1,                           // push 1
+        3, 1, 0, 0,                     // set @1 0
+        50, 0, 25, 0, 0, 0, 1, 1,       // call @0 first @1
+        8, 2,                           // pop 2
+        0,                              // ret
+
+        // first
+        53, 32, 0, 0, 0, 1, 1,          // tailcall second @1
+
+        // second
+        102, 0, 1, 1,                   // addf @0 @1 @1
+        8, 1,                           // pop 1
+        0,                              // ret
+    ];
+
+    let functions = FunctionTable::new(&[(8, 0, "main"), (25, 1, "first"), (32, 1, "second")]);
+    let program = Program::new(vec![Constant::Float(1.0)], bytecode, functions.clone(), vec![], None).unwrap();
+    let mut vm = VM::<false>::new(Arc::new(program)).unwrap();
+
+    let mut profile = FunctionProfile::new();
+    vm.run_function_profiled(&mut profile).unwrap();
+
+    assert_eq!(
+        profile.folded(&functions, Weight::Instructions),
+        "<bootstrap> 2\n<bootstrap>;main 5\n<bootstrap>;main;first 1\n<bootstrap>;main;second 3\n",
+    );
+}
@@ -0,0 +1,721 @@
+use std::sync::Arc;
+
+use anatase::{VM, Data, Limits, fuel::Status, native::LinkError, errors::{VmError, Location}, garbage_collector::ObjectData, program::{Program, Constant}, symbols::FunctionTable};
+
+
+fn vm(bytecode: &[u8], constants: Vec<Constant>) -> VM<true> {
+    let program = Program::new(constants, bytecode.to_vec(), FunctionTable::default(), vec![], None).unwrap();
+    VM::<true>::new(Arc::new(program))
+}
+
+
+#[test]
+fn division_by_zero() {
+    let bytecode = [
+        7, 3,         // push 3
+        3, 1, 0, 0,   // set @1 0
+        3, 2, 1, 0,   // set @2 1
+        109, 0, 1, 2, // divi @0 @1 @2
+        0,            // ret
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Int(5), Constant::Int(0)]);
+
+    assert_eq!(
+        vm.run(),
+        Err(VmError::DivisionByZero(Location { offset: 10, function: 0 })),
+    );
+}
+
+
+#[test]
+fn invalid_opcode() {
+    let bytecode = [
+        7, 1,  // push 1
+        254,   // not an instruction
+        0,
+    ];
+
+    let mut vm = vm(&bytecode, vec![]);
+
+    assert_eq!(
+        vm.run(),
+        Err(VmError::InvalidOpcode(Location { offset: 2, function: 0 }, 254)),
+    );
+}
+
+
+#[test]
+fn type_mismatch() {
+    let bytecode = [
+        7, 3,         // push 3
+        3, 1, 0, 0,   // set @1 0
+        3, 2, 1, 0,   // set @2 1
+        100, 0, 1, 2, // addi @0 @1 @2
+        0,
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Int(5), Constant::Float(1.0)]);
+
+    assert!(matches!(vm.run(), Err(VmError::TypeMismatch { .. })));
+}
+
+
+#[test]
+fn garbage_collection() {
+    let mut vm = vm(&[0], vec![]);
+
+    for _ in 0..3 {
+        vm.alloc(ObjectData::String(String::new())).unwrap();
+    }
+
+    assert_eq!(vm.memory.live(), 3);
+    assert_eq!(vm.collect_garbage(), 3);
+    assert_eq!(vm.memory.live(), 0);
+
+    // filling the heap collects the unreachable objects
+    // instead of running out of memory
+    for _ in 0..vm.memory.capacity() * 2 {
+        vm.alloc(ObjectData::String(String::new())).unwrap();
+    }
+}
+
+
+#[test]
+fn string_concat() {
+    let bytecode = [
+        7, 3,        // push 3
+        3, 1, 0, 0,  // set @1 0
+        3, 2, 1, 0,  // set @2 1
+        60, 0, 1, 2, // strcat @0 @1 @2
+        0,
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Str("héllo".into()), Constant::Str(" world".into())]);
+    vm.run().unwrap();
+
+    assert_eq!(vm.memory.string(vm.stack.reg(0)), Some("héllo world"));
+}
+
+
+#[test]
+fn char_at_out_of_bounds() {
+    let bytecode = [
+        7, 3,        // push 3
+        3, 1, 0, 0,  // set @1 0
+        3, 2, 1, 0,  // set @2 1
+        65, 0, 1, 2, // charat @0 @1 @2
+        0,
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Str("héllo".into()), Constant::Int(5)]);
+
+    assert_eq!(
+        vm.run(),
+        Err(VmError::IndexOutOfBounds { location: Location { offset: 10, function: 0 }, index: 5, len: 5 }),
+    );
+}
+
+
+#[test]
+fn array_out_of_bounds() {
+    let bytecode = [
+        7, 3,        // push 3
+        3, 1, 0, 0,  // set @1 0
+        70, 2, 1, 1, // arrnew @2 @1 @1
+        74, 2, 1,    // arrpush @2 @1
+        71, 1, 2,    // arrlen @1 @2
+        72, 0, 2, 1, // arrget @0 @2 @1
+        0,
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Int(2)]);
+
+    assert_eq!(
+        vm.run(),
+        Err(VmError::IndexOutOfBounds { location: Location { offset: 16, function: 0 }, index: 3, len: 3 }),
+    );
+
+    let array = vm.memory.array(vm.stack.reg(2)).unwrap();
+    assert_eq!(array.len(), 3);
+}
+
+
+#[test]
+fn array_too_large() {
+    let bytecode = [
+        7, 2,        // push 2
+        3, 1, 0, 0,  // set @1 i64::MAX
+        70, 0, 1, 1, // arrnew @0 @1 @1
+        0,
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Int(i64::MAX)]);
+
+    assert_eq!(
+        vm.run(),
+        Err(VmError::OutOfMemory(Location { offset: 6, function: 0 })),
+    );
+}
+
+
+#[test]
+fn pop_empty_array() {
+    let bytecode = [
+        7, 3,        // push 3
+        3, 1, 0, 0,  // set @1 0
+        70, 2, 1, 1, // arrnew @2 @1 @1
+        75, 0, 2,    // arrpop @0 @2
+        0,
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Int(0)]);
+
+    assert_eq!(
+        vm.run(),
+        Err(VmError::EmptyArray(Location { offset: 10, function: 0 })),
+    );
+}
+
+
+fn add<const DEBUG: bool>(vm: &mut VM<DEBUG>, args: &[Data]) -> Result<Data, VmError> {
+    match (args[0].as_i64(), args[1].as_i64()) {
+        (Some(a), Some(b)) => Ok(Data::new_i64(a + b)),
+        _ => Err(VmError::Native { location: vm.location(), message: "expected two ints".to_string() }),
+    }
+}
+
+
+#[test]
+fn native_call() {
+    let bytecode = [
+        7, 3,                   // push 3
+        3, 1, 0, 0,             // set @1 0
+        3, 2, 0, 0,             // set @2 0
+        51, 0, 0, 0, 2, 1, 2,   // callnative @0 add @1 @2
+        0,
+    ];
+
+    let program = Program::new(vec![Constant::Int(20)], bytecode.to_vec(), FunctionTable::default(), vec!["add".to_string()], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+
+    assert_eq!(vm.link(), Err(LinkError::MissingImport("add".to_string())));
+
+    vm.register_native("add", add);
+    vm.link().unwrap();
+    vm.run().unwrap();
+
+    assert_eq!(vm.stack.reg(0).as_i64(), Some(40));
+}
+
+
+#[test]
+fn natives_link_before_running() {
+    let bytecode = [
+        7, 3,                   // push 3
+        3, 1, 0, 0,             // set @1 0
+        3, 2, 0, 0,             // set @2 0
+        51, 0, 0, 0, 2, 1, 2,   // callnative @0 add @1 @2
+        0,
+    ];
+
+    let program = Program::new(vec![Constant::Int(20)], bytecode.to_vec(), FunctionTable::default(), vec!["add".to_string()], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+
+    // nothing runs while a native is missing
+    assert_eq!(vm.run(), Err(VmError::UnresolvedImport(Location { offset: 0, function: 0 }, 0)));
+    assert_eq!(vm.location().offset, 0);
+
+    vm.register_native("add", add);
+    vm.run().unwrap();
+
+    assert_eq!(vm.stack.reg(0).as_i64(), Some(40));
+}
+
+
+fn two_strings<const DEBUG: bool>(vm: &mut VM<DEBUG>, _: &[Data]) -> Result<Data, VmError> {
+    let first = vm.alloc_string("first".to_string()).unwrap();
+    vm.alloc_string("second".to_string()).unwrap();
+    Ok(first)
+}
+
+
+#[test]
+fn native_allocations() {
+    let bytecode = [
+        7, 1,                   // push 1
+        51, 0, 0, 0, 0,         // callnative @0 two_strings
+        0,
+    ];
+
+    let program = Program::new(vec![], bytecode.to_vec(), FunctionTable::default(), vec!["two_strings".to_string()], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+    vm.register_native("two_strings", two_strings);
+
+    // leaves one free slot so the second string collects garbage
+    for _ in vm.memory.live()..vm.memory.capacity() - 1 {
+        vm.alloc(ObjectData::String(String::new())).unwrap();
+    }
+
+    vm.run().unwrap();
+    assert_eq!(vm.memory.string(vm.stack.reg(0)), Some("first"));
+    assert_eq!(vm.memory.live(), 2);
+}
+
+
+#[test]
+fn fuel() {
+    let bytecode = [
+        7, 3,         // push 3
+        3, 1, 0, 0,   // set @1 0
+        3, 2, 0, 0,   // set @2 0
+        100, 0, 1, 2, // addi @0 @1 @2
+        0,            // ret
+    ];
+
+    let mut program = vm(&bytecode, vec![Constant::Int(20)]);
+
+    assert_eq!(program.run_with_fuel(3), Ok(Status::OutOfFuel));
+    assert_eq!(program.run_with_fuel(0), Ok(Status::OutOfFuel));
+    assert_eq!(program.run_with_fuel(3), Ok(Status::Finished));
+    assert_eq!(program.stack.reg(0).as_i64(), Some(40));
+
+
+    // jmp $0
+    let mut looping = vm(&[11, 0, 0, 0, 0], vec![]);
+
+    let mut costs = [1; 256];
+    costs[11] = 10;
+
+    assert_eq!(looping.run_with_fuel(1000), Ok(Status::OutOfFuel));
+    assert_eq!(looping.run_with_fuel_costs(1000, &costs), Ok(Status::OutOfFuel));
+}
+
+
+#[test]
+fn stack_overflow() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,  // call @0 main, ret
+
+        // main
+        50, 0, 8, 0, 0, 0, 0,     // call @0 main
+        8, 1,                     // pop 1
+        0,                        // ret
+    ];
+
+    let functions = FunctionTable::new(&[(8, 0, "main")]);
+    let program = Arc::new(Program::new(vec![], bytecode, functions, vec![], None).unwrap());
+
+    let overflow = Err(VmError::StackOverflow(Location { offset: 8, function: 8 }));
+
+    let mut vm = VM::<false>::new(program.clone()).unwrap();
+    vm.set_limits(Limits { call_depth: 100, ..Limits::default() });
+    assert_eq!(vm.run(), overflow);
+    assert_eq!(vm.backtrace().frames.len(), 101);
+
+    let mut vm = VM::<true>::new(program);
+    vm.set_limits(Limits { stack_slots: 50, ..Limits::default() });
+    assert_eq!(vm.run(), overflow);
+    assert_eq!(vm.limits().stack_slots, 50);
+}
+
+
+#[test]
+fn invalid_window() {
+    let bytecode = [
+        7, 3,                   // push 3
+        52, 0, 9, 0, 0, 0, 1,   // callw @0 $9 1
+        0,                      // ret
+    ];
+
+    let mut vm = vm(&bytecode, vec![]);
+
+    assert_eq!(
+        vm.run(),
+        Err(VmError::InvalidWindow(Location { offset: 2, function: 0 })),
+    );
+}
+
+
+#[test]
+fn interrupt() {
+    fn send_sync<T: Send + Sync>(_: &T) {}
+
+    // jmp $0
+    let mut looping = vm(&[11, 0, 0, 0, 0], vec![]);
+    let handle = looping.interrupt_handle();
+    send_sync(&handle);
+
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        handle.interrupt();
+    });
+
+    assert_eq!(looping.run(), Err(VmError::Interrupted(Location { offset: 0, function: 0 })));
+    thread.join().unwrap();
+
+    // the interrupt is cleared once it's reported
+    assert_eq!(looping.run_with_fuel(100), Ok(Status::OutOfFuel));
+
+
+    // straight-line code never checks
+    let bytecode = [
+        7, 1,         // push 1
+        3, 1, 0, 0,   // set @1 0
+        8, 1,         // pop 1
+        0,            // ret
+    ];
+
+    let mut vm = vm(&bytecode, vec![Constant::Int(1)]);
+    vm.interrupt_handle().interrupt();
+    assert_eq!(vm.run(), Ok(()));
+}
+
+
+#[test]
+fn pre_decoded() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 5,                           // push 5
+        3, 1, 0, 0,                     // set @1 10
+        3, 2, 1, 0,                     // set @2 0
+        3, 3, 2, 0,                     // set @3 1
+        3, 4, 1, 0,                     // set @4 0
+
+        // $loop (26)
+        133, 5, 1, 4,                   // gti @5 @1 @4
+        9, 5, 40, 0, 0, 0, 58, 0, 0, 0, // jif @5 $body $end
+
+        // $body (40)
+        50, 2, 64, 0, 0, 0, 2, 2, 1,    // call @2 add @2 @1
+        103, 1, 1, 3,                   // subi @1 @1 @3
+        11, 26, 0, 0, 0,                // jmp $loop
+
+        // $end (58)
+        1, 0, 2,                        // cpy @0 @2
+        8, 6,                           // pop 6
+        0,                              // ret
+
+        // add
+        100, 0, 1, 2,                   // addi @0 @1 @2
+        8, 1,                           // pop 1
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(10), Constant::Int(0), Constant::Int(1)];
+    let functions = FunctionTable::new(&[(8, 0, "main"), (64, 2, "add")]);
+    let program = Arc::new(Program::new(constants, bytecode, functions, vec![], None).unwrap());
+
+    // `run` executes the pre-decoded instructions while
+    // `run_with_fuel` goes through the bytecode
+    let mut vm = VM::<false>::new(program.clone()).unwrap();
+    assert_eq!(vm.run(), Ok(()));
+    assert_eq!(vm.stack.reg(0).as_i64(), Some(55));
+
+    let mut vm = VM::<false>::new(program).unwrap();
+    assert_eq!(vm.run_with_fuel(u64::MAX), Ok(Status::Finished));
+    assert_eq!(vm.stack.reg(0).as_i64(), Some(55));
+
+
+    let bytecode = vec![
+        7, 3,         // push 3
+        3, 1, 0, 0,   // set @1 0
+        3, 2, 1, 0,   // set @2 1
+        109, 0, 1, 2, // divi @0 @1 @2
+        0,            // ret
+    ];
+
+    let program = Program::new(vec![Constant::Int(5), Constant::Int(0)], bytecode, FunctionTable::default(), vec![], None).unwrap();
+    let mut vm = VM::<false>::new(Arc::new(program)).unwrap();
+
+    assert_eq!(vm.run(), Err(VmError::DivisionByZero(Location { offset: 10, function: 0 })));
+}
+
+
+#[test]
+fn immediates() {
+    let bytecode = vec![
+        7, 3,                           // push 3
+        3, 1, 0, 0,                     // set @1 0
+        3, 2, 0, 0,                     // set @2 0
+        3, 3, 1, 0,                     // set @3 10
+
+        // $loop (14)
+        14, 1, 3, 25, 0, 0, 0, 39, 0, 0, 0, // jlti @1 @3 $body $end
+
+        // $body (25)
+        160, 1, 1, 2, 0,                // addik @1 @1 1
+        100, 2, 2, 1,                   // addi @2 @2 @1
+        11, 14, 0, 0, 0,                // jmp $loop
+
+        // $end (39)
+        1, 0, 2,                        // cpy @0 @2
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(0), Constant::Int(10), Constant::Int(1)];
+
+    let mut program = vm(&bytecode, constants.clone());
+    assert_eq!(program.run(), Ok(()));
+    assert_eq!(program.stack.reg(0).as_i64(), Some(55));
+
+    let program = Program::new(constants, bytecode, FunctionTable::default(), vec![], None).unwrap();
+    let mut verified = VM::<false>::new(Arc::new(program)).unwrap();
+    assert_eq!(verified.run(), Ok(()));
+    assert_eq!(verified.stack.reg(0).as_i64(), Some(55));
+}
+
+
+#[test]
+fn comparison_and_jump() {
+    // the pre-decoded `VM` merges the `ltik` with the `jnif`
+    // after it, which still has to leave its result in @3
+    let program = |result: u8| vec![
+        7, 3,                           // push 3
+        3, 1, 0, 0,                     // set @1 0
+        3, 2, 0, 0,                     // set @2 0
+
+        // $loop (10)
+        166, 3, 1, 1, 0,                // ltik @3 @1 10
+        10, 3, 39, 0, 0, 0, 25, 0, 0, 0, // jnif @3 $end $body
+
+        // $body (25)
+        160, 1, 1, 2, 0,                // addik @1 @1 1
+        100, 2, 2, 1,                   // addi @2 @2 @1
+        11, 10, 0, 0, 0,                // jmp $loop
+
+        // $end (39)
+        1, 0, result,                   // cpy @0 @result
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(0), Constant::Int(10), Constant::Int(1)];
+
+    assert_eq!(both(program(2), constants.clone()).map(|x| x.as_i64()), Ok(Some(55)));
+    assert_eq!(both(program(3), constants).map(|x| x.as_bool()), Ok(Some(false)));
+}
+
+
+#[test]
+fn call_window() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 3,                           // push 3
+        3, 2, 0, 0,                     // set @2 20
+        3, 3, 1, 0,                     // set @3 22
+        52, 1, 31, 0, 0, 0, 2,          // callw @1 add @2 @3
+        1, 0, 1,                        // cpy @0 @1
+        8, 4,                           // pop 4
+        0,                              // ret
+
+        // add
+        100, 0, 1, 2,                   // addi @0 @1 @2
+        8, 1,                           // pop 1
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(20), Constant::Int(22)];
+    let functions = FunctionTable::new(&[(8, 0, "main"), (31, 2, "add")]);
+    let program = Arc::new(Program::new(constants, bytecode, functions, vec![], None).unwrap());
+
+    let mut checked = VM::<true>::new(program.clone());
+    assert_eq!(checked.run(), Ok(()));
+    assert_eq!(checked.stack.reg(0).as_i64(), Some(42));
+
+    let mut verified = VM::<false>::new(program).unwrap();
+    assert_eq!(verified.run(), Ok(()));
+    assert_eq!(verified.stack.reg(0).as_i64(), Some(42));
+}
+
+
+#[test]
+fn tail_call() {
+    let bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 3,                           // push 3
+        3, 2, 0, 0,                     // set @2 100000
+        3, 3, 1, 0,                     // set @3 0
+        50, 1, 33, 0, 0, 0, 2, 2, 3,    // call @1 sum @2 @3
+        1, 0, 1,                        // cpy @0 @1
+        8, 4,                           // pop 4
+        0,                              // ret
+
+        // sum
+        7, 1,                           // push 1
+        3, 3, 1, 0,                     // set @3 0
+        18, 1, 3, 67, 0, 0, 0, 50, 0, 0, 0, // jeqi @1 @3 $done $next
+
+        // $next (50)
+        100, 3, 2, 1,                   // addi @3 @2 @1
+        162, 1, 1, 2, 0,                // subik @1 @1 1
+        53, 33, 0, 0, 0, 2, 1, 3,       // tailcall sum @1 @3
+
+        // $done (67)
+        1, 0, 2,                        // cpy @0 @2
+        8, 2,                           // pop 2
+        0,                              // ret
+    ];
+
+    let constants = vec![Constant::Int(100_000), Constant::Int(0), Constant::Int(1)];
+    let functions = FunctionTable::new(&[(8, 0, "main"), (33, 2, "sum")]);
+    let program = Arc::new(Program::new(constants, bytecode, functions, vec![], None).unwrap());
+
+    // every call to `sum` after the first reuses its frame
+    let limits = Limits { call_depth: 3, stack_slots: 64 };
+
+    let mut checked = VM::<true>::new(program.clone());
+    checked.set_limits(limits);
+    assert_eq!(checked.run(), Ok(()));
+    assert_eq!(checked.stack.reg(0).as_i64(), Some(5_000_050_000));
+
+    let mut verified = VM::<false>::new(program).unwrap();
+    verified.set_limits(limits);
+    assert_eq!(verified.run(), Ok(()));
+    assert_eq!(verified.stack.reg(0).as_i64(), Some(5_000_050_000));
+}
+
+
+// runs the program on the stepping and the pre-decoded `VM`, which have to agree
+fn both(bytecode: Vec<u8>, constants: Vec<Constant>) -> Result<Data, VmError> {
+    let mut stepping = vm(&bytecode, constants.clone());
+    let stepped = stepping.run().map(|()| stepping.stack.reg(0));
+
+    let program = Program::new(constants, bytecode, FunctionTable::default(), vec![], None).unwrap();
+    let mut verified = VM::<false>::new(Arc::new(program)).unwrap();
+    let decoded = verified.run().map(|()| verified.stack.reg(0));
+
+    assert_eq!(format!("{stepped:?}"), format!("{decoded:?}"));
+    stepped
+}
+
+
+// `opcode @0 @1 @2` with the operands cast to uints if `uint`
+fn arithmetic(opcode: u8, lhs: i64, rhs: i64, uint: bool) -> Result<Data, VmError> {
+    let mut bytecode = vec![
+        7, 3,         // push 3
+        3, 1, 0, 0,   // set @1 #0
+        3, 2, 1, 0,   // set @2 #1
+    ];
+
+    if uint {
+        bytecode.extend_from_slice(&[
+            150, 1, 1, // cast_iu @1 @1
+            150, 2, 2, // cast_iu @2 @2
+        ]);
+    }
+
+    bytecode.extend_from_slice(&[opcode, 0, 1, 2, 0]);
+
+    both(bytecode, vec![Constant::Int(lhs), Constant::Int(rhs)])
+}
+
+
+#[test]
+fn integer_overflow() {
+    let int = |opcode, lhs, rhs| arithmetic(opcode, lhs, rhs, false).map(|v| v.as_i64().unwrap());
+    let uint = |opcode, lhs, rhs| arithmetic(opcode, lhs, rhs, true).map(|v| v.as_u64().unwrap());
+    let overflow = |offset| VmError::Overflow(Location { offset, function: 0 });
+
+    // the plain opcodes wrap
+    assert_eq!(int(100, i64::MAX, 1), Ok(i64::MIN));        // addi
+    assert_eq!(int(106, i64::MAX, 2), Ok(-2));              // muli
+    assert_eq!(int(109, i64::MIN, -1), Ok(i64::MIN));       // divi
+    assert_eq!(int(112, i64::MIN, -1), Ok(0));              // remi
+    assert_eq!(uint(104, 0, 1), Ok(u64::MAX));              // subu
+
+    // and shift every bit out past 63
+    assert_eq!(int(115, 1, 64), Ok(0));                     // lsi
+    assert_eq!(int(115, 1, -1), Ok(0));                     // lsi
+    assert_eq!(int(118, -8, 100), Ok(-1));                  // rsi
+    assert_eq!(int(118, 8, 64), Ok(0));                     // rsi
+    assert_eq!(uint(116, 1, 64), Ok(0));                    // lsu
+    assert_eq!(uint(119, 1, 63), Ok(0));                    // rsu
+
+    // checked
+    assert_eq!(int(200, i64::MAX, 1), Err(overflow(10)));   // addi_c
+    assert_eq!(int(200, i64::MAX, -1), Ok(i64::MAX - 1));   // addi_c
+    assert_eq!(int(204, i64::MIN, -1), Err(overflow(10)));  // muli_c
+    assert_eq!(uint(203, 0, 1), Err(overflow(16)));         // subu_c
+    assert_eq!(int(206, 1, 62), Ok(1 << 62));               // lsi_c
+    assert_eq!(int(206, 1, 63), Err(overflow(10)));         // lsi_c
+    assert_eq!(int(206, -1, 63), Ok(i64::MIN));             // lsi_c
+    assert_eq!(int(208, 1, 64), Err(overflow(10)));         // rsi_c
+    assert_eq!(uint(207, -1, 1), Err(overflow(16)));        // lsu_c
+
+    // wrapping
+    assert_eq!(int(103, i64::MIN, 1), Ok(i64::MAX));        // subi, or subi_w
+    assert_eq!(int(216, 1, 65), Ok(2));                     // lsi_w
+    assert_eq!(uint(219, 4, 66), Ok(1));                    // rsu_w
+
+    // saturating
+    assert_eq!(int(220, i64::MAX, 1), Ok(i64::MAX));        // addi_s
+    assert_eq!(int(222, i64::MIN, 1), Ok(i64::MIN));        // subi_s
+    assert_eq!(int(224, i64::MIN, 2), Ok(i64::MIN));        // muli_s
+    assert_eq!(int(224, i64::MIN, -2), Ok(i64::MAX));       // muli_s
+    assert_eq!(uint(223, 0, 1), Ok(0));                     // subu_s
+    assert_eq!(uint(225, -1, 2), Ok(u64::MAX));             // mulu_s
+    assert_eq!(int(226, -3, 63), Ok(i64::MIN));             // lsi_s
+    assert_eq!(int(226, 0, 100), Ok(0));                    // lsi_s
+    assert_eq!(uint(227, 3, 63), Ok(u64::MAX));             // lsu_s
+    assert_eq!(int(228, -5, 64), Ok(-1));                   // rsi_s
+}
+
+
+#[test]
+fn bitwise_and_logical() {
+    let int = |opcode, lhs, rhs| arithmetic(opcode, lhs, rhs, false).map(|v| v.as_i64().unwrap());
+    let uint = |opcode, lhs, rhs| arithmetic(opcode, lhs, rhs, true).map(|v| v.as_u64().unwrap());
+
+    let bool = |opcode, lhs, rhs| both(
+        vec![
+            7, 3,               // push 3
+            3, 1, 0, 0,         // set @1 #0
+            3, 2, 1, 0,         // set @2 #1
+            opcode, 0, 1, 2,    // op @0 @1 @2
+            0,                  // ret
+        ],
+        vec![Constant::Bool(lhs), Constant::Bool(rhs)],
+    ).map(|v| v.as_bool().unwrap());
+
+    let unary = |opcode, val| both(
+        vec![
+            7, 2,               // push 2
+            3, 1, 0, 0,         // set @1 #0
+            opcode, 0, 1,       // op @0 @1
+            0,                  // ret
+        ],
+        vec![val],
+    );
+
+    assert_eq!(int(180, 12, 10), Ok(8));                    // andi
+    assert_eq!(int(182, 12, 10), Ok(14));                   // ori
+    assert_eq!(int(184, 12, 10), Ok(6));                    // xori
+    assert_eq!(int(184, -1, 5), Ok(!5));                    // xori
+    assert_eq!(uint(181, 12, 10), Ok(8));                   // andu
+    assert_eq!(uint(183, 12, 10), Ok(14));                  // oru
+    assert_eq!(uint(185, -1, 0), Ok(u64::MAX));             // xoru
+
+    assert_eq!(bool(188, true, false), Ok(false));          // andb
+    assert_eq!(bool(188, true, true), Ok(true));            // andb
+    assert_eq!(bool(189, false, true), Ok(true));           // orb
+    assert_eq!(bool(189, false, false), Ok(false));         // orb
+
+    assert_eq!(unary(186, Constant::Int(0)).map(|v| v.as_i64()), Ok(Some(-1)));              // noti
+    assert_eq!(unary(190, Constant::Bool(false)).map(|v| v.as_bool()), Ok(Some(true)));      // notb
+    assert_eq!(unary(191, Constant::Int(5)).map(|v| v.as_i64()), Ok(Some(-5)));              // negi
+    assert_eq!(unary(191, Constant::Int(i64::MIN)).map(|v| v.as_i64()), Ok(Some(i64::MIN))); // negi
+    assert_eq!(unary(192, Constant::Float(1.5)).map(|v| v.as_f64()), Ok(Some(-1.5)));        // negf
+
+    // only the checked `VM` looks at the tags
+    let mut not = vm(&[7, 2, 3, 1, 0, 0, 190, 0, 1, 0], vec![Constant::Int(1)]);
+    assert!(matches!(not.run(), Err(VmError::TypeMismatch { .. })));
+
+    let mut and = vm(&[7, 3, 3, 1, 0, 0, 3, 2, 0, 0, 188, 0, 1, 2, 0], vec![Constant::Int(1)]);
+    assert!(matches!(and.run(), Err(VmError::TypeMismatch { .. })));
+}
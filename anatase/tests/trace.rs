@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use anatase::{VM, program::{Program, Constant}, symbols::FunctionTable, trace::{self, Tracer}};
+
+
+#[test]
+fn trace_round_trip() {
+    let bytecode = [
+        7, 3,         // push 3
+        3, 1, 0, 0,   // set @1 0
+        100, 2, 1, 1, // addi @2 @1 @1
+        0,            // ret
+    ];
+
+    let program = Program::new(vec![Constant::Int(2)], bytecode.to_vec(), FunctionTable::default(), vec![], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+
+    let mut tracer = Tracer::new(vec![]);
+    vm.run_traced(&mut tracer).unwrap();
+
+    let records = trace::parse(&tracer.finish().unwrap()).unwrap();
+    let lines : Vec<_> = records.iter().map(|x| x.instruction.to_string()).collect();
+    assert_eq!(lines, ["push 3", "set @1 #0", "addi @2 @1 @1", "ret"]);
+
+    let addi = &records[2];
+    assert_eq!(addi.offset, 6);
+    assert_eq!(addi.registers.len(), 2);
+
+    let (register, before, after) = addi.registers[0];
+    assert_eq!(register, 2);
+    assert_eq!(before.as_i64(), None);
+    assert_eq!(after.as_i64(), Some(4));
+}
+
+
+#[test]
+fn trace_filter() {
+    let program = Program::new(vec![], vec![7, 1, 0], FunctionTable::default(), vec![], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+
+    let mut tracer = Tracer::new(vec![]).with_filter([8]);
+    vm.run_traced(&mut tracer).unwrap();
+
+    assert_eq!(trace::parse(&tracer.finish().unwrap()).map(|x| x.len()), Some(0));
+}
+
+
+#[test]
+fn trace_wide_call() {
+    // a `call` with every register, which is 262 bytes long
+    let mut bytecode = vec![
+        50, 0, 8, 0, 0, 0, 0, 0,        // call @0 main, ret
+
+        // main
+        7, 255,                         // push 255
+        50, 0, 21, 1, 0, 0, 255,        // call @0 wide @1 .. @255
+    ];
+
+    bytecode.extend(1..=255);
+    bytecode.extend_from_slice(&[
+        8, 255,                         // pop 255
+        8, 1,                           // pop 1
+        0,                              // ret
+
+        // wide (277)
+        8, 1,                           // pop 1
+        0,                              // ret
+    ]);
+
+    let functions = FunctionTable::new(&[(8, 0, "main"), (277, 255, "wide")]);
+    let program = Program::new(vec![], bytecode, functions, vec![], None).unwrap();
+    let mut vm = VM::<true>::new(Arc::new(program));
+
+    let mut tracer = Tracer::new(vec![]);
+    vm.run_traced(&mut tracer).unwrap();
+
+    let records = trace::parse(&tracer.finish().unwrap()).unwrap();
+    assert_eq!(records.len(), 9);
+
+    let call = &records[2];
+    assert_eq!(call.instruction.next() - call.offset, 262);
+    assert_eq!(call.registers.len(), 256);
+}
@@ -0,0 +1,142 @@
+use anatase::{symbols::FunctionTable, verifier::{verify, VerifyError}};
+
+
+// call @0 main, ret
+const BOOTSTRAP : [u8; 8] = [50, 0, 8, 0, 0, 0, 0, 0];
+
+
+#[test]
+fn valid_program() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[
+        7, 2,          // push 2
+        3, 1, 0, 0,    // set @1 0
+        1, 0, 1,       // cpy @0 @1
+        12, 1, 10, 0, 0, 0, // ijif @1 $10
+        8, 3,          // pop 3
+        0,             // ret
+    ]);
+
+    assert_eq!(verify(&bytecode, 1, 0, &FunctionTable::new(&[(8, 0, "main")])), Ok(()));
+}
+
+
+#[test]
+fn register_outside_of_frame() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[
+        7, 1,          // push 1
+        1, 0, 2,       // cpy @0 @2
+        0,
+    ]);
+
+    assert_eq!(
+        verify(&bytecode, 0, 0, &FunctionTable::new(&[(8, 0, "main")])),
+        Err(VerifyError::InvalidRegister { offset: 10, register: 2, frame_size: 2 }),
+    );
+}
+
+
+#[test]
+fn jump_into_an_instruction() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[
+        11, 9, 0, 0, 0, // jmp $9
+        0,
+    ]);
+
+    assert_eq!(
+        verify(&bytecode, 0, 0, &FunctionTable::new(&[(8, 0, "main")])),
+        Err(VerifyError::InvalidTarget { offset: 8, target: 9 }),
+    );
+}
+
+
+#[test]
+fn truncated_instruction() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[3, 0, 0]);
+
+    assert!(matches!(
+        verify(&bytecode, 1, 0, &FunctionTable::new(&[(8, 0, "main")])),
+        Err(VerifyError::Decode(_)),
+    ));
+}
+
+
+#[test]
+fn call_window_inside_the_frame() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[
+        7, 3,                   // push 3
+        52, 0, 17, 0, 0, 0, 1,  // callw @0 $17 1
+        8, 4,                   // pop 4
+        0,                      // ret
+
+        // id
+        8, 1,                   // pop 1
+        0,                      // ret
+    ]);
+
+    assert_eq!(
+        verify(&bytecode, 0, 0, &FunctionTable::new(&[(8, 0, "main"), (17, 1, "id")])),
+        Err(VerifyError::InvalidWindow { offset: 10, frame_size: 4 }),
+    );
+}
+
+
+#[test]
+fn call_window_past_the_last_register() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[
+        7, 255,                     // push 255
+        7, 45,                      // push 45
+        52, 200, 20, 0, 0, 0, 100,  // callw @200 $20 100
+        0,                          // ret
+
+        // wide
+        8, 1,                       // pop 1
+        0,                          // ret
+    ]);
+
+    assert_eq!(
+        verify(&bytecode, 0, 0, &FunctionTable::new(&[(8, 0, "main"), (20, 100, "wide")])),
+        Err(VerifyError::InvalidWindow { offset: 12, frame_size: 301 }),
+    );
+}
+
+
+#[test]
+fn paths_join_with_different_frames() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[
+        7, 1,               // push 1
+        12, 1, 18, 0, 0, 0, // ijif @1 $18
+        7, 1,               // push 1
+
+        // $18
+        8, 3,               // pop 3
+        0,                  // ret
+    ]);
+
+    assert_eq!(
+        verify(&bytecode, 0, 0, &FunctionTable::new(&[(8, 0, "main")])),
+        Err(VerifyError::FrameMismatch { offset: 18, expected: 3, found: 2 }),
+    );
+}
+
+
+#[test]
+fn return_with_the_wrong_frame() {
+    let mut bytecode = BOOTSTRAP.to_vec();
+    bytecode.extend_from_slice(&[
+        7, 1,          // push 1
+        8, 1,          // pop 1
+        0,             // ret
+    ]);
+
+    assert_eq!(
+        verify(&bytecode, 0, 0, &FunctionTable::new(&[(8, 0, "main")])),
+        Err(VerifyError::InvalidReturn { offset: 12, expected: 0, found: 1 }),
+    );
+}
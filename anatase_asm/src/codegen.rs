@@ -1,10 +1,31 @@
 use std::collections::HashMap;
 
-use crate::{SymbolMap, parser::Function, consts::{Ret}, Literal, OperatorKind};
+use archiver::Packed;
+
+use crate::{SymbolMap, SymbolIndex, SourceRange, parser::Function, consts::{Ret}, Literal, Operand, OperatorKind};
+
+
+#[derive(Debug)]
+pub struct Assembled {
+    pub constants: Vec<Literal>,
+    pub bytecode: Vec<u8>,
+    /// (name, offset, argc) of every function
+    pub functions: Vec<(SymbolIndex, u32, u8)>,
+    /// The names of the natives called with `callnative`
+    pub imports: Vec<SymbolIndex>,
+    /// The source range of the instruction at each offset
+    pub lines: Vec<(u32, SourceRange)>,
+    /// The offset and name of every block
+    pub blocks: Vec<(u32, SymbolIndex)>,
+}
+
 
-pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>, Vec<u8>) {
+pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> Assembled {
     let mut bytecode = Vec::new();
     let mut constants = Vec::new();
+    let mut lines = Vec::new();
+    let mut imports = Vec::new();
+    let mut blocks = Vec::new();
     
     let mut function_starts = HashMap::with_capacity(functions.len());
     let mut function_calls = Vec::new();
@@ -38,6 +59,7 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
         let temp = function_starts.insert(f.name, bytecode.len());
         assert!(temp.is_none());
 
+        lines.push((offset(&bytecode), f.declaration_range));
         bytecode.push(OperatorKind::Jmp(f.entry).as_bytecode());
         jumps.push((OperatorKind::Jmp(f.entry), bytecode.len()));
         bytecode.push(0);
@@ -47,10 +69,14 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
 
         for b in &f.body {
             block_starts.insert(b.id, bytecode.len());
+            blocks.push((offset(&bytecode), b.id.0));
             for o in &b.operators {
-                bytecode.push(o.kind.as_bytecode());
+                let kind = lower_immediate(&o.kind);
+
+                lines.push((offset(&bytecode), o.source_range));
+                bytecode.push(kind.as_bytecode());
                 println!("{o:?}");
-                match o.kind {
+                match kind {
                     crate::OperatorKind::Ret() => bytecode.push(Ret),
 
                     
@@ -62,18 +88,55 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
 
 
                     crate::OperatorKind::Set(dst, val) => {
-                        let index = constants.iter().enumerate().find(|x| x.1 == &val);
-                        let index = match index {
-                            Some(v) => v.0,
-                            None => {
-                                constants.push(val);
-                                constants.len()-1
-                            },
-                        };
+                        dst.to_bytes(&mut bytecode);
+                        constant(&mut constants, val).to_bytes(&mut bytecode);
+                    }
+
 
+                    | crate::OperatorKind::AddIK(dst, lhs, val)
+                    | crate::OperatorKind::AddFK(dst, lhs, val)
+                    | crate::OperatorKind::SubIK(dst, lhs, val)
+                    | crate::OperatorKind::SubFK(dst, lhs, val)
+                    | crate::OperatorKind::MulIK(dst, lhs, val)
+                    | crate::OperatorKind::MulFK(dst, lhs, val)
+                    | crate::OperatorKind::LtIK (dst, lhs, val)
+                    | crate::OperatorKind::LtFK (dst, lhs, val)
+                    | crate::OperatorKind::GtIK (dst, lhs, val)
+                    | crate::OperatorKind::GtFK (dst, lhs, val)
+                    | crate::OperatorKind::LeIK (dst, lhs, val)
+                    | crate::OperatorKind::LeFK (dst, lhs, val)
+                    | crate::OperatorKind::GeIK (dst, lhs, val)
+                    | crate::OperatorKind::GeFK (dst, lhs, val)
+                    | crate::OperatorKind::EqIK (dst, lhs, val)
+                    | crate::OperatorKind::EqFK (dst, lhs, val)
+                    | crate::OperatorKind::NeIK (dst, lhs, val)
+                    | crate::OperatorKind::NeFK (dst, lhs, val)
+                     => {
                         dst.to_bytes(&mut bytecode);
-                        u16::try_from(index).expect("too many constants").to_bytes(&mut bytecode);
+                        lhs.to_bytes(&mut bytecode);
+                        constant(&mut constants, val).to_bytes(&mut bytecode);
                     }
+
+
+                    | crate::OperatorKind::AddI(_, _, Operand::Literal(_))
+                    | crate::OperatorKind::AddF(_, _, Operand::Literal(_))
+                    | crate::OperatorKind::SubI(_, _, Operand::Literal(_))
+                    | crate::OperatorKind::SubF(_, _, Operand::Literal(_))
+                    | crate::OperatorKind::MulI(_, _, Operand::Literal(_))
+                    | crate::OperatorKind::MulF(_, _, Operand::Literal(_))
+                    | crate::OperatorKind::LtI (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::LtF (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::GtI (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::GtF (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::LeI (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::LeF (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::GeI (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::GeF (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::EqI (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::EqF (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::NeI (_, _, Operand::Literal(_))
+                    | crate::OperatorKind::NeF (_, _, Operand::Literal(_))
+                     => unreachable!("immediates are lowered to the constant forms"),
                     
 
                     | crate::OperatorKind::Cast_IU(v1, v2)
@@ -82,8 +145,17 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
                     | crate::OperatorKind::Cast_UF(v1, v2)
                     | crate::OperatorKind::Cast_FI(v1, v2)
                     | crate::OperatorKind::Cast_FU(v1, v2)
+                    | crate::OperatorKind::NotI(v1, v2)
+                    | crate::OperatorKind::NotU(v1, v2)
+                    | crate::OperatorKind::NotB(v1, v2)
+                    | crate::OperatorKind::NegI(v1, v2)
+                    | crate::OperatorKind::NegF(v1, v2)
                     | crate::OperatorKind::Cpy(v1, v2)
                     | crate::OperatorKind::Swap(v1, v2)
+                    | crate::OperatorKind::StrLen(v1, v2)
+                    | crate::OperatorKind::ArrLen(v1, v2)
+                    | crate::OperatorKind::ArrPush(v1, v2)
+                    | crate::OperatorKind::ArrPop(v1, v2)
                      => {
                         v1.to_bytes(&mut bytecode);
                         v2.to_bytes(&mut bytecode);
@@ -96,7 +168,29 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
                     | crate::OperatorKind::JNif(v, ..)
                      => {
                         v.to_bytes(&mut bytecode);
-                        jumps.push((o.kind.clone(), bytecode.len()));
+                        jumps.push((kind.clone(), bytecode.len()));
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                    },
+
+                    | crate::OperatorKind::JLtI(lhs, rhs, ..)
+                    | crate::OperatorKind::JLtF(lhs, rhs, ..)
+                    | crate::OperatorKind::JLeI(lhs, rhs, ..)
+                    | crate::OperatorKind::JLeF(lhs, rhs, ..)
+                    | crate::OperatorKind::JEqI(lhs, rhs, ..)
+                    | crate::OperatorKind::JEqF(lhs, rhs, ..)
+                    | crate::OperatorKind::JNeI(lhs, rhs, ..)
+                    | crate::OperatorKind::JNeF(lhs, rhs, ..)
+                     => {
+                        lhs.to_bytes(&mut bytecode);
+                        rhs.to_bytes(&mut bytecode);
+                        jumps.push((kind.clone(), bytecode.len()));
                         bytecode.push(0);
                         bytecode.push(0);
                         bytecode.push(0);
@@ -108,7 +202,7 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
                     },
 
                     crate::OperatorKind::Jmp(_) => {
-                        jumps.push((o.kind.clone(), bytecode.len()));
+                        jumps.push((kind.clone(), bytecode.len()));
                         bytecode.push(0);
                         bytecode.push(0);
                         bytecode.push(0);
@@ -120,7 +214,7 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
                     | crate::OperatorKind::IJif(reg, _)
                      => {
                         reg.to_bytes(&mut bytecode);
-                        jumps.push((o.kind.clone(), bytecode.len()));
+                        jumps.push((kind.clone(), bytecode.len()));
                         bytecode.push(0);
                         bytecode.push(0);
                         bytecode.push(0);
@@ -143,36 +237,76 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
                     },
 
 
-                    | crate::OperatorKind::LtI  (v1, v2, v3)
+                    crate::OperatorKind::TailCall(func, ref args) => {
+                        function_calls.push((func, bytecode.len()));
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+
+                        args.as_slice().to_bytes(&mut bytecode);
+                    },
+
+
+                    // the arguments are already in place after `dst`
+                    crate::OperatorKind::CallW(dst, func, ref args) => {
+                        dst.to_bytes(&mut bytecode);
+
+                        function_calls.push((func, bytecode.len()));
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+                        bytecode.push(0);
+
+                        bytecode.push(args.len() as u8);
+                    },
+
+
+                    crate::OperatorKind::CallNative(dst, func, ref args) => {
+                        let index = match imports.iter().position(|x| *x == func) {
+                            Some(v) => v,
+                            None => {
+                                imports.push(func);
+                                imports.len()-1
+                            },
+                        };
+
+                        dst.to_bytes(&mut bytecode);
+                        u16::try_from(index).expect("too many imports").to_bytes(&mut bytecode);
+                        args.as_slice().to_bytes(&mut bytecode);
+                    },
+
+
+                    | crate::OperatorKind::LtI  (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::LtU  (v1, v2, v3)
-                    | crate::OperatorKind::LtF  (v1, v2, v3)
-                    | crate::OperatorKind::GtI  (v1, v2, v3)
+                    | crate::OperatorKind::LtF  (v1, v2, Operand::Reg(v3))
+                    | crate::OperatorKind::GtI  (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::GtU  (v1, v2, v3)
-                    | crate::OperatorKind::GtF  (v1, v2, v3)
-                    | crate::OperatorKind::LeI  (v1, v2, v3)
+                    | crate::OperatorKind::GtF  (v1, v2, Operand::Reg(v3))
+                    | crate::OperatorKind::LeI  (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::LeU  (v1, v2, v3)
-                    | crate::OperatorKind::LeF  (v1, v2, v3)
-                    | crate::OperatorKind::GeI  (v1, v2, v3)
+                    | crate::OperatorKind::LeF  (v1, v2, Operand::Reg(v3))
+                    | crate::OperatorKind::GeI  (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::GeU  (v1, v2, v3)
-                    | crate::OperatorKind::GeF  (v1, v2, v3)
-                    | crate::OperatorKind::EqI  (v1, v2, v3)
+                    | crate::OperatorKind::GeF  (v1, v2, Operand::Reg(v3))
+                    | crate::OperatorKind::EqI  (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::EqU  (v1, v2, v3)
-                    | crate::OperatorKind::EqF  (v1, v2, v3)
-                    | crate::OperatorKind::NeI  (v1, v2, v3)
+                    | crate::OperatorKind::EqF  (v1, v2, Operand::Reg(v3))
+                    | crate::OperatorKind::NeI  (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::NeU  (v1, v2, v3)
-                    | crate::OperatorKind::NeF  (v1, v2, v3)
-                    | crate::OperatorKind::AddI (v1, v2, v3)
+                    | crate::OperatorKind::NeF  (v1, v2, Operand::Reg(v3))
+                    | crate::OperatorKind::AddI (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::AddU (v1, v2, v3)
-                    | crate::OperatorKind::AddF (v1, v2, v3)
-                    | crate::OperatorKind::SubI (v1, v2, v3)
+                    | crate::OperatorKind::AddF (v1, v2, Operand::Reg(v3))
+                    | crate::OperatorKind::SubI (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::SubU (v1, v2, v3)
-                    | crate::OperatorKind::SubF (v1, v2, v3)
+                    | crate::OperatorKind::SubF (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::DivI (v1, v2, v3)
                     | crate::OperatorKind::DivU (v1, v2, v3)
                     | crate::OperatorKind::DivF (v1, v2, v3)
-                    | crate::OperatorKind::MulI (v1, v2, v3)
+                    | crate::OperatorKind::MulI (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::MulU (v1, v2, v3)
-                    | crate::OperatorKind::MulF (v1, v2, v3)
+                    | crate::OperatorKind::MulF (v1, v2, Operand::Reg(v3))
                     | crate::OperatorKind::RemI (v1, v2, v3)
                     | crate::OperatorKind::RemU (v1, v2, v3)
                     | crate::OperatorKind::RemF (v1, v2, v3)
@@ -180,11 +314,58 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
                     | crate::OperatorKind::LsU  (v1, v2, v3)
                     | crate::OperatorKind::RsI  (v1, v2, v3)
                     | crate::OperatorKind::RsU  (v1, v2, v3)
+                    | crate::OperatorKind::AndI (v1, v2, v3)
+                    | crate::OperatorKind::AndU (v1, v2, v3)
+                    | crate::OperatorKind::OrI  (v1, v2, v3)
+                    | crate::OperatorKind::OrU  (v1, v2, v3)
+                    | crate::OperatorKind::XorI (v1, v2, v3)
+                    | crate::OperatorKind::XorU (v1, v2, v3)
+                    | crate::OperatorKind::AndB (v1, v2, v3)
+                    | crate::OperatorKind::OrB  (v1, v2, v3)
+                    | crate::OperatorKind::AddI_C(v1, v2, v3)
+                    | crate::OperatorKind::AddU_C(v1, v2, v3)
+                    | crate::OperatorKind::SubI_C(v1, v2, v3)
+                    | crate::OperatorKind::SubU_C(v1, v2, v3)
+                    | crate::OperatorKind::MulI_C(v1, v2, v3)
+                    | crate::OperatorKind::MulU_C(v1, v2, v3)
+                    | crate::OperatorKind::LsI_C (v1, v2, v3)
+                    | crate::OperatorKind::LsU_C (v1, v2, v3)
+                    | crate::OperatorKind::RsI_C (v1, v2, v3)
+                    | crate::OperatorKind::RsU_C (v1, v2, v3)
+                    | crate::OperatorKind::LsI_W (v1, v2, v3)
+                    | crate::OperatorKind::LsU_W (v1, v2, v3)
+                    | crate::OperatorKind::RsI_W (v1, v2, v3)
+                    | crate::OperatorKind::RsU_W (v1, v2, v3)
+                    | crate::OperatorKind::AddI_S(v1, v2, v3)
+                    | crate::OperatorKind::AddU_S(v1, v2, v3)
+                    | crate::OperatorKind::SubI_S(v1, v2, v3)
+                    | crate::OperatorKind::SubU_S(v1, v2, v3)
+                    | crate::OperatorKind::MulI_S(v1, v2, v3)
+                    | crate::OperatorKind::MulU_S(v1, v2, v3)
+                    | crate::OperatorKind::LsI_S (v1, v2, v3)
+                    | crate::OperatorKind::LsU_S (v1, v2, v3)
+                    | crate::OperatorKind::RsI_S (v1, v2, v3)
+                    | crate::OperatorKind::RsU_S (v1, v2, v3)
+                    | crate::OperatorKind::StrCat(v1, v2, v3)
+                    | crate::OperatorKind::StrEq (v1, v2, v3)
+                    | crate::OperatorKind::StrCmp(v1, v2, v3)
+                    | crate::OperatorKind::CharAt(v1, v2, v3)
+                    | crate::OperatorKind::ArrNew(v1, v2, v3)
+                    | crate::OperatorKind::ArrGet(v1, v2, v3)
+                    | crate::OperatorKind::ArrSet(v1, v2, v3)
                      => {
                         v1.to_bytes(&mut bytecode);
                         v2.to_bytes(&mut bytecode);
                         v3.to_bytes(&mut bytecode);
                     }
+
+
+                    crate::OperatorKind::SubStr(v1, v2, v3, v4) => {
+                        v1.to_bytes(&mut bytecode);
+                        v2.to_bytes(&mut bytecode);
+                        v3.to_bytes(&mut bytecode);
+                        v4.to_bytes(&mut bytecode);
+                    }
                 }
             }
 
@@ -194,7 +375,15 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
             println!("{}", i);
             match j.0 {
                 | crate::OperatorKind::Jif(_, yes, no)
-                | crate::OperatorKind::JNif(_, yes, no) => {
+                | crate::OperatorKind::JNif(_, yes, no)
+                | crate::OperatorKind::JLtI(_, _, yes, no)
+                | crate::OperatorKind::JLtF(_, _, yes, no)
+                | crate::OperatorKind::JLeI(_, _, yes, no)
+                | crate::OperatorKind::JLeF(_, _, yes, no)
+                | crate::OperatorKind::JEqI(_, _, yes, no)
+                | crate::OperatorKind::JEqF(_, _, yes, no)
+                | crate::OperatorKind::JNeI(_, _, yes, no)
+                | crate::OperatorKind::JNeF(_, _, yes, no) => {
                     let index = block_starts.get(&yes).unwrap();
                     let index = u32::try_from(*index).expect("index too big");
                     let index : [u8; 4] = index.to_le_bytes();
@@ -246,7 +435,178 @@ pub fn codegen(symbol_map: &SymbolMap, functions: &[Function]) -> (Vec<Literal>,
     }
 
     println!("{bytecode:?}");
-    (constants, bytecode)
+
+    let functions = functions.iter()
+        .map(|f| (f.name, offset_of(function_starts[&f.name]), f.argc))
+        .collect();
+
+    Assembled {
+        constants,
+        bytecode,
+        functions,
+        imports,
+        lines,
+        blocks,
+    }
+}
+
+
+/// Returns the index of `val` in the constant table, adding it if it isn't there
+fn constant(constants: &mut Vec<Literal>, val: Literal) -> u16 {
+    let index = match constants.iter().position(|x| x == &val) {
+        Some(v) => v,
+        None => {
+            constants.push(val);
+            constants.len()-1
+        },
+    };
+
+    u16::try_from(index).expect("too many constants")
+}
+
+
+///
+/// Turns an operator with a literal on its right-hand side, like
+/// `addi @1 @1 1`, into the form that reads it from the constant table
+///
+fn lower_immediate(kind: &OperatorKind) -> OperatorKind {
+    use OperatorKind::*;
+
+    let &(
+        | AddI(dst, lhs, Operand::Literal(val))
+        | AddF(dst, lhs, Operand::Literal(val))
+        | SubI(dst, lhs, Operand::Literal(val))
+        | SubF(dst, lhs, Operand::Literal(val))
+        | MulI(dst, lhs, Operand::Literal(val))
+        | MulF(dst, lhs, Operand::Literal(val))
+        | LtI (dst, lhs, Operand::Literal(val))
+        | LtF (dst, lhs, Operand::Literal(val))
+        | GtI (dst, lhs, Operand::Literal(val))
+        | GtF (dst, lhs, Operand::Literal(val))
+        | LeI (dst, lhs, Operand::Literal(val))
+        | LeF (dst, lhs, Operand::Literal(val))
+        | GeI (dst, lhs, Operand::Literal(val))
+        | GeF (dst, lhs, Operand::Literal(val))
+        | EqI (dst, lhs, Operand::Literal(val))
+        | EqF (dst, lhs, Operand::Literal(val))
+        | NeI (dst, lhs, Operand::Literal(val))
+        | NeF (dst, lhs, Operand::Literal(val))
+    ) = kind
+    else { return kind.clone() };
+
+    match kind {
+        AddI(..) => AddIK(dst, lhs, val),
+        AddF(..) => AddFK(dst, lhs, val),
+        SubI(..) => SubIK(dst, lhs, val),
+        SubF(..) => SubFK(dst, lhs, val),
+        MulI(..) => MulIK(dst, lhs, val),
+        MulF(..) => MulFK(dst, lhs, val),
+        LtI (..) => LtIK (dst, lhs, val),
+        LtF (..) => LtFK (dst, lhs, val),
+        GtI (..) => GtIK (dst, lhs, val),
+        GtF (..) => GtFK (dst, lhs, val),
+        LeI (..) => LeIK (dst, lhs, val),
+        LeF (..) => LeFK (dst, lhs, val),
+        GeI (..) => GeIK (dst, lhs, val),
+        GeF (..) => GeFK (dst, lhs, val),
+        EqI (..) => EqIK (dst, lhs, val),
+        EqF (..) => EqFK (dst, lhs, val),
+        NeI (..) => NeIK (dst, lhs, val),
+        NeF (..) => NeFK (dst, lhs, val),
+        _ => unreachable!(),
+    }
+}
+
+
+fn offset(bytecode: &[u8]) -> u32 {
+    offset_of(bytecode.len())
+}
+
+
+fn offset_of(index: usize) -> u32 {
+    u32::try_from(index).expect("index too big")
+}
+
+
+impl Assembled {
+    ///
+    /// Packs the program into the sections of an `.anb` file
+    ///
+    /// The sections are, in order, the constants, the bytecode, the
+    /// function table, the import table and, if `debug_info` is given
+    /// as the file name and its source code, the line and block tables
+    /// used for backtraces and the debugger
+    ///
+    pub fn pack(self, symbol_map: &SymbolMap, debug_info: Option<(&str, &str)>) -> Packed {
+        let mut constant_bytes = vec![];
+        for i in self.constants {
+            match i {
+                Literal::Integer(v) => {
+                    constant_bytes.push(0);
+                    v.to_bytes(&mut constant_bytes)
+                },
+
+                Literal::Float(v) => {
+                    constant_bytes.push(1);
+                    v.to_bytes(&mut constant_bytes)
+                },
+
+                Literal::String(v) => {
+                    constant_bytes.push(2);
+                    symbol_map.get(v).to_bytes(&mut constant_bytes);
+                },
+
+                Literal::Bool(v) => {
+                    let val = if v { 3 } else { 4 };
+                    constant_bytes.push(val);
+                },
+
+                Literal::Empty => (),
+            }
+        }
+
+
+        let mut function_bytes = vec![];
+        for (name, offset, argc) in self.functions {
+            offset.to_bytes(&mut function_bytes);
+            argc.to_bytes(&mut function_bytes);
+            symbol_map.get(name).to_bytes(&mut function_bytes);
+        }
+
+
+        let mut import_bytes = vec![];
+        for name in self.imports {
+            symbol_map.get(name).to_bytes(&mut import_bytes);
+        }
+
+
+        let packed = Packed::new()
+            .with(archiver::Data(constant_bytes))
+            .with(archiver::Data(self.bytecode))
+            .with(archiver::Data(function_bytes))
+            .with(archiver::Data(import_bytes));
+
+        let Some((file_name, source)) = debug_info
+        else { return packed };
+
+        let mut debug_bytes = vec![];
+        file_name.to_bytes(&mut debug_bytes);
+        source.to_bytes(&mut debug_bytes);
+
+        u32::try_from(self.lines.len()).expect("too many instructions").to_bytes(&mut debug_bytes);
+        for (offset, range) in self.lines {
+            offset.to_bytes(&mut debug_bytes);
+            offset_of(range.start).to_bytes(&mut debug_bytes);
+            offset_of(range.end).to_bytes(&mut debug_bytes);
+        }
+
+        for (offset, name) in self.blocks {
+            offset.to_bytes(&mut debug_bytes);
+            symbol_map.get(name).to_bytes(&mut debug_bytes);
+        }
+
+        packed.with(archiver::Data(debug_bytes))
+    }
 }
 
 
@@ -265,6 +625,16 @@ impl<T: ToBytecode> ToBytecode for &[T] {
 }
 
 
+impl ToBytecode for &str {
+    fn to_bytes(&self, vec: &mut Vec<u8>) {
+        let len : u64 = self.len().try_into().expect("string too big");
+
+        len.to_bytes(vec);
+        vec.extend_from_slice(self.as_bytes());
+    }
+}
+
+
 impl ToBytecode for u8 {
     fn to_bytes(&self, vec: &mut Vec<u8>) {
         vec.push(*self)
@@ -1,49 +1,12 @@
 
 use std::{fmt::Write, collections::HashMap};
 
+use anatase_common::snippet::highlight;
 use colored::{Color, Colorize};
 
 use super::{SymbolIndex, SourceRange};
 
 
-const LINE_COUNT : usize = 1;
-
-
-pub fn line_at_index(value: &str, index: usize) -> Option<(&str, usize)> {
-    let mut index_counter = 0;
-    for (i, line) in value.lines().enumerate() {
-        index_counter += line.chars().map(|x| x.len_utf8()).sum::<usize>();
-        index_counter += LINE_COUNT;
-
-        if index_counter > index {
-            return Some((line, i));
-        }
-    }
-    
-    Some(("", value.lines().count()))
-}
-
-pub fn start_of_line(value: &str, line_number: usize) -> usize {
-    let mut counter = 0;
-
-    for (i, line) in value.lines().enumerate() {
-        if i == line_number {
-            break
-        }
-
-        counter += line.chars().map(|x| x.len_utf8()).sum::<usize>();
-        counter += LINE_COUNT;
-    }
-
-    counter
-}
-
-const ORANGE: Color = Color::TrueColor {
-    r: 255,
-    g: 160,
-    b: 100,
-};
-
 // Error Creation
 
 #[derive(Debug, PartialEq)]
@@ -158,87 +121,9 @@ impl ErrorOption {
 
 
             ErrorOption::Highlight { range, note, colour, file } => {
-                let mut string = String::new();
-
                 let (file_name, source) = files.get(&file).unwrap();
 
-                let start_line = line_at_index(source, range.start).unwrap().1;
-                let end_line   = line_at_index(source, range.end).unwrap().1;
-                let line_size  = end_line.to_string().len();
-
-                
-                {
-                    let _ = writeln!(string, "{}{} {}:{}:{}", " ".repeat(line_size), "-->".color(ORANGE), file_name, start_line, range.start - start_of_line(source, start_line));
-                    let _ = write!(string, "{} {}", " ".repeat(line_size), "|".color(ORANGE));
-                }
-
-
-                // println!("{}", source.as_bytes().len());
-                // writeln!(string, "{}", &source[range.start..range.end]);
-                
-               for (line_number, line) in source.lines().enumerate().take(end_line + 1).skip(start_line) {
-                    let _ = writeln!(string);
-
-                    let _ = writeln!(string, "{:>w$} {} {}", line_number.to_string().color(ORANGE), "|".color(ORANGE), line, w = line_size);
-
-                    if line_number == start_line {
-                        let start_of_line = start_of_line(source, line_number);
-
-                        let _ = write!(string, "{:>w$} {} ",
-                            " ".repeat(line_number.to_string().len()),
-                            "|".color(ORANGE),
-
-                            w = line_size,
-                        );
-
-                        let _ = write!(string, "{}{}",
-                            " ".repeat({
-                                let mut count = 0;
-                                for (index, i) in line.chars().enumerate() {
-                                    if count >= range.start - start_of_line {
-                                        count = index;
-                                        break
-                                    }
-                                    count += i.len_utf8();
-                                }
-                                count
-                            }),
-                            "^".repeat({
-                                if end_line == line_number {
-                                    (range.end-range.start) + 1
-                                } else {
-                                    dbg!(line, range, start_of_line, line_number);
-                                    line.len() - (range.start - start_of_line) + 1
-                                }
-                            }).color(colour),
-                        );
-
-                        
-                    } else if line_number == end_line {
-                        let _ = write!(string, "{}",
-                            "^".repeat({
-                                let start_of_end = start_of_line(source, end_line);
-                                range.end - start_of_end
-                            }).color(colour),
-                        );
-
-                       
-                    } else {
-                        let _ = write!(string, "{}",
-                            "^".repeat(line.len()).color(colour),
-                        );
-                    }
-
-                }
-
-                
-                if let Some(note) = note {
-                    let _ = writeln!(string, " {note}");
-                } else {
-                    let _ = writeln!(string);
-                }
-        
-                string
+                highlight(file_name, source, range.start, range.end, note.as_deref(), colour)
             },
         }
     }
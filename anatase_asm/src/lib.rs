@@ -13,6 +13,7 @@ pub mod semantic_anal;
 pub mod codegen;
 mod errors;
 pub mod disassembler;
+pub mod peephole;
 
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Debug, Hash)]
@@ -108,6 +109,18 @@ impl Literal {
 }
 
 
+///
+/// The right-hand side of an arithmetic or comparison operator,
+/// either a register or an immediate that codegen turns into the
+/// constant form of the operator
+///
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+    Reg(u8),
+    Literal(Literal),
+}
+
+
 pub trait PrettyPrint {
     fn pretty_print(&self, symbol_map: &SymbolMap) -> String;
 }
@@ -125,6 +138,14 @@ macro_rules! operators {
             pub fn operator_token(str: &str) -> Option<OperatorToken> {
                 match str {
                     $(lower!(stringify!($name)) => Some(OperatorToken::$name),)+
+
+                    // the plain operators already wrap
+                    "addi_w" => Some(OperatorToken::AddI),
+                    "addu_w" => Some(OperatorToken::AddU),
+                    "subi_w" => Some(OperatorToken::SubI),
+                    "subu_w" => Some(OperatorToken::SubU),
+                    "muli_w" => Some(OperatorToken::MulI),
+                    "mulu_w" => Some(OperatorToken::MulU),
                     _ => None
                 }
             }
@@ -215,19 +236,47 @@ operators!(
     11 Jmp((label BlockId)),
     12 IJif((reg u8) (label BlockId)),
     13 IJNif((reg u8) (label BlockId)),
+
+    14 JLtI((reg u8) (reg u8) (label BlockId) (label BlockId)),
+    15 JLtF((reg u8) (reg u8) (label BlockId) (label BlockId)),
+    16 JLeI((reg u8) (reg u8) (label BlockId) (label BlockId)),
+    17 JLeF((reg u8) (reg u8) (label BlockId) (label BlockId)),
+    18 JEqI((reg u8) (reg u8) (label BlockId) (label BlockId)),
+    19 JEqF((reg u8) (reg u8) (label BlockId) (label BlockId)),
+    20 JNeI((reg u8) (reg u8) (label BlockId) (label BlockId)),
+    21 JNeF((reg u8) (reg u8) (label BlockId) (label BlockId)),
     
     50 Call((reg u8) (expect_identifier SymbolIndex) (reg_list Vec<u8>)),
+    51 CallNative((reg u8) (expect_identifier SymbolIndex) (reg_list Vec<u8>)),
+    52 CallW((reg u8) (expect_identifier SymbolIndex) (reg_list Vec<u8>)),
+    53 TailCall((expect_identifier SymbolIndex) (reg_list Vec<u8>)),
+
+
+    60 StrCat ((reg u8) (reg u8) (reg u8)),
+    61 StrLen ((reg u8) (reg u8)),
+    62 StrEq  ((reg u8) (reg u8) (reg u8)),
+    63 StrCmp ((reg u8) (reg u8) (reg u8)),
+    64 SubStr ((reg u8) (reg u8) (reg u8) (reg u8)),
+    65 CharAt ((reg u8) (reg u8) (reg u8)),
+
+
+    70 ArrNew  ((reg u8) (reg u8) (reg u8)),
+    71 ArrLen  ((reg u8) (reg u8)),
+    72 ArrGet  ((reg u8) (reg u8) (reg u8)),
+    73 ArrSet  ((reg u8) (reg u8) (reg u8)),
+    74 ArrPush ((reg u8) (reg u8)),
+    75 ArrPop  ((reg u8) (reg u8)),
     
 
-    100 AddI ((reg u8) (reg u8) (reg u8)),
+    100 AddI ((reg u8) (reg u8) (reg_or_literal Operand)),
     101 AddU ((reg u8) (reg u8) (reg u8)),
-    102 AddF ((reg u8) (reg u8) (reg u8)),
-    103 SubI ((reg u8) (reg u8) (reg u8)),
+    102 AddF ((reg u8) (reg u8) (reg_or_literal Operand)),
+    103 SubI ((reg u8) (reg u8) (reg_or_literal Operand)),
     104 SubU ((reg u8) (reg u8) (reg u8)),
-    105 SubF ((reg u8) (reg u8) (reg u8)),
-    106 MulI ((reg u8) (reg u8) (reg u8)),
+    105 SubF ((reg u8) (reg u8) (reg_or_literal Operand)),
+    106 MulI ((reg u8) (reg u8) (reg_or_literal Operand)),
     107 MulU ((reg u8) (reg u8) (reg u8)),
-    108 MulF ((reg u8) (reg u8) (reg u8)),
+    108 MulF ((reg u8) (reg u8) (reg_or_literal Operand)),
     109 DivI ((reg u8) (reg u8) (reg u8)),
     110 DivU ((reg u8) (reg u8) (reg u8)),
     111 DivF ((reg u8) (reg u8) (reg u8)),
@@ -239,24 +288,24 @@ operators!(
     118 RsI ((reg u8) (reg u8) (reg u8)),
     119 RsU ((reg u8) (reg u8) (reg u8)),
 
-    130 LtI  ((reg u8) (reg u8) (reg u8)),
+    130 LtI  ((reg u8) (reg u8) (reg_or_literal Operand)),
     131 LtU  ((reg u8) (reg u8) (reg u8)),
-    132 LtF  ((reg u8) (reg u8) (reg u8)),
-    133 GtI  ((reg u8) (reg u8) (reg u8)),
+    132 LtF  ((reg u8) (reg u8) (reg_or_literal Operand)),
+    133 GtI  ((reg u8) (reg u8) (reg_or_literal Operand)),
     134 GtU  ((reg u8) (reg u8) (reg u8)),
-    135 GtF  ((reg u8) (reg u8) (reg u8)),
-    136 LeI  ((reg u8) (reg u8) (reg u8)),
+    135 GtF  ((reg u8) (reg u8) (reg_or_literal Operand)),
+    136 LeI  ((reg u8) (reg u8) (reg_or_literal Operand)),
     137 LeU  ((reg u8) (reg u8) (reg u8)),
-    138 LeF  ((reg u8) (reg u8) (reg u8)),
-    139 GeI  ((reg u8) (reg u8) (reg u8)),
+    138 LeF  ((reg u8) (reg u8) (reg_or_literal Operand)),
+    139 GeI  ((reg u8) (reg u8) (reg_or_literal Operand)),
     140 GeU  ((reg u8) (reg u8) (reg u8)),
-    141 GeF  ((reg u8) (reg u8) (reg u8)),
-    142 EqI  ((reg u8) (reg u8) (reg u8)),
+    141 GeF  ((reg u8) (reg u8) (reg_or_literal Operand)),
+    142 EqI  ((reg u8) (reg u8) (reg_or_literal Operand)),
     143 EqU  ((reg u8) (reg u8) (reg u8)),
-    144 EqF  ((reg u8) (reg u8) (reg u8)),
-    145 NeI  ((reg u8) (reg u8) (reg u8)),
+    144 EqF  ((reg u8) (reg u8) (reg_or_literal Operand)),
+    145 NeI  ((reg u8) (reg u8) (reg_or_literal Operand)),
     146 NeU  ((reg u8) (reg u8) (reg u8)),
-    147 NeF  ((reg u8) (reg u8) (reg u8)),
+    147 NeF  ((reg u8) (reg u8) (reg_or_literal Operand)),
 
     150 Cast_IU ((reg u8) (reg u8)),
     151 Cast_IF ((reg u8) (reg u8)),
@@ -265,6 +314,69 @@ operators!(
     154 Cast_FI ((reg u8) (reg u8)),
     155 Cast_FU ((reg u8) (reg u8)),
 
+    160 AddIK ((reg u8) (reg u8) (literal Literal)),
+    161 AddFK ((reg u8) (reg u8) (literal Literal)),
+    162 SubIK ((reg u8) (reg u8) (literal Literal)),
+    163 SubFK ((reg u8) (reg u8) (literal Literal)),
+    164 MulIK ((reg u8) (reg u8) (literal Literal)),
+    165 MulFK ((reg u8) (reg u8) (literal Literal)),
+
+    166 LtIK ((reg u8) (reg u8) (literal Literal)),
+    167 LtFK ((reg u8) (reg u8) (literal Literal)),
+    168 GtIK ((reg u8) (reg u8) (literal Literal)),
+    169 GtFK ((reg u8) (reg u8) (literal Literal)),
+    170 LeIK ((reg u8) (reg u8) (literal Literal)),
+    171 LeFK ((reg u8) (reg u8) (literal Literal)),
+    172 GeIK ((reg u8) (reg u8) (literal Literal)),
+    173 GeFK ((reg u8) (reg u8) (literal Literal)),
+    174 EqIK ((reg u8) (reg u8) (literal Literal)),
+    175 EqFK ((reg u8) (reg u8) (literal Literal)),
+    176 NeIK ((reg u8) (reg u8) (literal Literal)),
+    177 NeFK ((reg u8) (reg u8) (literal Literal)),
+
+    180 AndI ((reg u8) (reg u8) (reg u8)),
+    181 AndU ((reg u8) (reg u8) (reg u8)),
+    182 OrI  ((reg u8) (reg u8) (reg u8)),
+    183 OrU  ((reg u8) (reg u8) (reg u8)),
+    184 XorI ((reg u8) (reg u8) (reg u8)),
+    185 XorU ((reg u8) (reg u8) (reg u8)),
+    186 NotI ((reg u8) (reg u8)),
+    187 NotU ((reg u8) (reg u8)),
+
+    188 AndB ((reg u8) (reg u8) (reg u8)),
+    189 OrB  ((reg u8) (reg u8) (reg u8)),
+    190 NotB ((reg u8) (reg u8)),
+
+    191 NegI ((reg u8) (reg u8)),
+    192 NegF ((reg u8) (reg u8)),
+
+    200 AddI_C  ((reg u8) (reg u8) (reg u8)),
+    201 AddU_C  ((reg u8) (reg u8) (reg u8)),
+    202 SubI_C  ((reg u8) (reg u8) (reg u8)),
+    203 SubU_C  ((reg u8) (reg u8) (reg u8)),
+    204 MulI_C  ((reg u8) (reg u8) (reg u8)),
+    205 MulU_C  ((reg u8) (reg u8) (reg u8)),
+    206 LsI_C   ((reg u8) (reg u8) (reg u8)),
+    207 LsU_C   ((reg u8) (reg u8) (reg u8)),
+    208 RsI_C   ((reg u8) (reg u8) (reg u8)),
+    209 RsU_C   ((reg u8) (reg u8) (reg u8)),
+
+    216 LsI_W   ((reg u8) (reg u8) (reg u8)),
+    217 LsU_W   ((reg u8) (reg u8) (reg u8)),
+    218 RsI_W   ((reg u8) (reg u8) (reg u8)),
+    219 RsU_W   ((reg u8) (reg u8) (reg u8)),
+
+    220 AddI_S  ((reg u8) (reg u8) (reg u8)),
+    221 AddU_S  ((reg u8) (reg u8) (reg u8)),
+    222 SubI_S  ((reg u8) (reg u8) (reg u8)),
+    223 SubU_S  ((reg u8) (reg u8) (reg u8)),
+    224 MulI_S  ((reg u8) (reg u8) (reg u8)),
+    225 MulU_S  ((reg u8) (reg u8) (reg u8)),
+    226 LsI_S   ((reg u8) (reg u8) (reg u8)),
+    227 LsU_S   ((reg u8) (reg u8) (reg u8)),
+    228 RsI_S   ((reg u8) (reg u8) (reg u8)),
+    229 RsU_S   ((reg u8) (reg u8) (reg u8)),
+
     
     255 Print ((reg u8)),
 );
@@ -5,9 +5,7 @@ use std::path::PathBuf;
 
 use anatase_asm::SymbolMap;
 use anatase_asm::PrettyPrint;
-use anatase_asm::codegen::ToBytecode;
 use anatase_asm::codegen::codegen;
-use archiver::Packed;
 
 fn main() {
     let mut symbol_map = SymbolMap::new();
@@ -47,45 +45,17 @@ fn main() {
     }
 
 
-    let codegen = codegen(&symbol_map, &instructions);
-
-
-    let mut constant_bytes = vec![];
-    for i in codegen.0 {
-        match i {
-            anatase_asm::Literal::Integer(v) => {
-                constant_bytes.push(0);
-                v.to_bytes(&mut constant_bytes)
-            },
-
-            anatase_asm::Literal::Float(v) => {
-                constant_bytes.push(1);
-                v.to_bytes(&mut constant_bytes)
-            },
-
-            anatase_asm::Literal::String(v) => {
-                let str = symbol_map.get(v);
-                let len : u64 = str.len().try_into().expect("string too big");
+    let mut instructions = instructions;
+    if !std::env::args().any(|x| x == "--no-peephole") {
+        anatase_asm::peephole::optimise(&mut instructions);
+    }
 
-                constant_bytes.push(2);
-                len.to_bytes(&mut constant_bytes);
-                constant_bytes.extend_from_slice(str.as_bytes());
-            },
 
-            anatase_asm::Literal::Bool(v) => {
-                let val = if v { 3 } else { 4 };
-                constant_bytes.push(val);
-            },
+    let codegen = codegen(&symbol_map, &instructions);
 
-            anatase_asm::Literal::Empty => (),
-        }
-    }
-    
 
-    let bytes = Packed::new()
-        .with(archiver::Data(constant_bytes))
-        .with(archiver::Data(codegen.1))
-        .as_bytes();
+    let debug_info = (!std::env::args().any(|x| x == "--strip")).then_some((symbol_map.get(file), data.as_str()));
+    let bytes = codegen.pack(&symbol_map, debug_info).as_bytes();
 
 
     let mut pathbuf = PathBuf::from(symbol_map.get(file));
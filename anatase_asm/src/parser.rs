@@ -1,4 +1,4 @@
-use crate::{lexer::{Token, TokenKind, Keyword}, SourceRange, SymbolIndex, Operator, errors::{CompilerError, ErrorBuilder, Error}, Literal, SymbolMap};
+use crate::{lexer::{Token, TokenKind, Keyword}, SourceRange, SymbolIndex, Operator, Operand, errors::{CompilerError, ErrorBuilder, Error}, Literal, SymbolMap};
 
 
 #[derive(Debug)]
@@ -229,6 +229,17 @@ impl Parser<'_> {
     }
 
 
+    pub fn reg_or_literal(&mut self) -> Result<Operand, Error> {
+        match self.current_kind() {
+            TokenKind::At => Ok(Operand::Reg(self.reg()?)),
+            TokenKind::Literal(v) => Ok(Operand::Literal(v)),
+            _ => Err(CompilerError::new(self.file, "expected a register or a literal")
+                .highlight(self.current_token().source_range)
+                .build())
+        }
+    }
+
+
     pub fn reg_list(&mut self) -> Result<Vec<u8>, Error> {
         let mut vec = vec![];
         loop {
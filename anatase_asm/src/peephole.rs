@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{parser::{Function, BlockId}, Operator, OperatorKind, Operand};
+
+
+///
+/// Fuses common pairs of operators in every function
+/// - `set @t <literal>` followed by an operator with `@t` as its
+///   right-hand side becomes the immediate form of that operator,
+///   `addf @4 @4 1.0` instead of going through `@t`
+/// - a comparison followed by a `jif` or a `jnif` on its result
+///   becomes a compare-and-branch like `jltf @4 @1 $body $end`
+/// - a `call` that's only followed by returning its result
+///   becomes a `tailcall`, the function won't show up in backtraces
+///
+/// The first two drop a write to a register so they only
+/// happen if nothing reads that register afterwards
+///
+pub fn optimise(functions: &mut [Function]) {
+    for function in functions {
+        let live_out = live_out(function);
+
+        for block in &mut function.body {
+            tail_call(&mut block.operators);
+            fuse(&mut block.operators, &live_out[&block.id]);
+        }
+    }
+}
+
+
+fn fuse(operators: &mut Vec<Operator>, live_out: &HashSet<u8>) {
+    let mut index = 0;
+    while index + 1 < operators.len() {
+        let first = &operators[index];
+        let second = &operators[index + 1];
+
+        let rest = &operators[index + 2..];
+        let is_live = |reg| is_live(reg, rest, live_out);
+
+        let kind = immediate(&first.kind, &second.kind, is_live)
+            .or_else(|| branch(&first.kind, &second.kind, is_live));
+
+        let Some(kind) = kind
+        else {
+            index += 1;
+            continue
+        };
+
+        let source_range = first.source_range.with(second.source_range);
+        operators.splice(index..index + 2, [Operator { kind, source_range }]);
+    }
+}
+
+
+/// `call @d f ...`, `cpy @0 @d`, `pop n` and `ret` at the end of a block
+fn tail_call(operators: &mut Vec<Operator>) {
+    use OperatorKind::*;
+
+    let len = match operators.as_slice() {
+        [
+            ..,
+            Operator { kind: Call(dst, ..), .. },
+            Operator { kind: Cpy(0, src), .. },
+            Operator { kind: Pop(_), .. },
+            Operator { kind: Ret(), .. },
+        ] if dst == src => 4,
+
+        [
+            ..,
+            Operator { kind: Call(0, ..), .. },
+            Operator { kind: Pop(_), .. },
+            Operator { kind: Ret(), .. },
+        ] => 3,
+
+        _ => return,
+    };
+
+    let start = operators.len() - len;
+    let source_range = operators[start].source_range.with(operators[operators.len() - 1].source_range);
+
+    let Call(_, func, ref args) = operators[start].kind
+    else { unreachable!() };
+
+    // the frame is reset to the size of the callee
+    // so there's nothing left to pop
+    let kind = TailCall(func, args.clone());
+
+    operators.truncate(start);
+    operators.push(Operator { kind, source_range });
+}
+
+
+/// `set @t <literal>` and an operator that reads `@t` as its right-hand side
+fn immediate(set: &OperatorKind, operator: &OperatorKind, is_live: impl Fn(u8) -> bool) -> Option<OperatorKind> {
+    use OperatorKind::*;
+
+    let &Set(temp, literal) = set
+    else { return None };
+
+    let mut kind = operator.clone();
+    let (
+        | AddI(dst, lhs, rhs) | AddF(dst, lhs, rhs)
+        | SubI(dst, lhs, rhs) | SubF(dst, lhs, rhs)
+        | MulI(dst, lhs, rhs) | MulF(dst, lhs, rhs)
+        | LtI (dst, lhs, rhs) | LtF (dst, lhs, rhs)
+        | GtI (dst, lhs, rhs) | GtF (dst, lhs, rhs)
+        | LeI (dst, lhs, rhs) | LeF (dst, lhs, rhs)
+        | GeI (dst, lhs, rhs) | GeF (dst, lhs, rhs)
+        | EqI (dst, lhs, rhs) | EqF (dst, lhs, rhs)
+        | NeI (dst, lhs, rhs) | NeF (dst, lhs, rhs)
+    ) = &mut kind
+    else { return None };
+
+    if *rhs != Operand::Reg(temp) || *lhs == temp {
+        return None
+    }
+
+    // the constant forms don't convert so a mismatch
+    // has to stay a type error at runtime
+    let is_float = matches!(operator, AddF(..) | SubF(..) | MulF(..) | LtF(..) | GtF(..) | LeF(..) | GeF(..) | EqF(..) | NeF(..));
+    match literal {
+        crate::Literal::Integer(_) if !is_float => (),
+        crate::Literal::Float(_) if is_float => (),
+        _ => return None,
+    }
+
+    if *dst != temp && is_live(temp) {
+        return None
+    }
+
+    *rhs = Operand::Literal(literal);
+    Some(kind)
+}
+
+
+/// A comparison and a `jif` or a `jnif` on its result
+fn branch(comparison: &OperatorKind, jump: &OperatorKind, is_live: impl Fn(u8) -> bool) -> Option<OperatorKind> {
+    use OperatorKind::*;
+
+    let (cond, yes, no) = match *jump {
+        Jif(cond, yes, no) => (cond, yes, no),
+        JNif(cond, yes, no) => (cond, no, yes),
+        _ => return None,
+    };
+
+    // `a > b` is `b < a` so only `<`, `<=`, `==` and `!=` need a fused form
+    let (dst, kind) = match *comparison {
+        LtI(dst, a, Operand::Reg(b)) => (dst, JLtI(a, b, yes, no)),
+        LtF(dst, a, Operand::Reg(b)) => (dst, JLtF(a, b, yes, no)),
+        GtI(dst, a, Operand::Reg(b)) => (dst, JLtI(b, a, yes, no)),
+        GtF(dst, a, Operand::Reg(b)) => (dst, JLtF(b, a, yes, no)),
+        LeI(dst, a, Operand::Reg(b)) => (dst, JLeI(a, b, yes, no)),
+        LeF(dst, a, Operand::Reg(b)) => (dst, JLeF(a, b, yes, no)),
+        GeI(dst, a, Operand::Reg(b)) => (dst, JLeI(b, a, yes, no)),
+        GeF(dst, a, Operand::Reg(b)) => (dst, JLeF(b, a, yes, no)),
+        EqI(dst, a, Operand::Reg(b)) => (dst, JEqI(a, b, yes, no)),
+        EqF(dst, a, Operand::Reg(b)) => (dst, JEqF(a, b, yes, no)),
+        NeI(dst, a, Operand::Reg(b)) => (dst, JNeI(a, b, yes, no)),
+        NeF(dst, a, Operand::Reg(b)) => (dst, JNeF(a, b, yes, no)),
+        _ => return None,
+    };
+
+    if dst != cond || is_live(cond) {
+        return None
+    }
+
+    Some(kind)
+}
+
+
+/// Whether `reg` is read by `operators` before it's written, or is live after them
+fn is_live(reg: u8, operators: &[Operator], live_out: &HashSet<u8>) -> bool {
+    for operator in operators {
+        let (reads, writes) = registers(&operator.kind);
+        if reads.contains(&reg) {
+            return true
+        }
+
+        if writes.contains(&reg) {
+            return false
+        }
+    }
+
+    live_out.contains(&reg)
+}
+
+
+///
+/// Returns the registers that are live at the end of each block
+///
+/// This is the usual backwards dataflow over the blocks of the
+/// function, repeated until nothing changes
+///
+fn live_out(function: &Function) -> HashMap<BlockId, HashSet<u8>> {
+    let successors : Vec<_> = function.body.iter().enumerate()
+        .map(|(index, block)| successors(&block.operators, function.body.get(index + 1).map(|x| x.id)))
+        .collect();
+
+    let out = |live_in: &HashMap<BlockId, HashSet<u8>>, index: usize| {
+        successors[index].iter()
+            .flat_map(|x| live_in.get(x).into_iter().flatten().copied())
+            .collect::<HashSet<u8>>()
+    };
+
+    let mut live_in : HashMap<BlockId, HashSet<u8>> = HashMap::new();
+    loop {
+        let mut changed = false;
+
+        for (index, block) in function.body.iter().enumerate().rev() {
+            let mut live = out(&live_in, index);
+            for operator in block.operators.iter().rev() {
+                let (reads, writes) = registers(&operator.kind);
+                for reg in writes { live.remove(&reg); }
+                live.extend(reads);
+            }
+
+            if live_in.get(&block.id) != Some(&live) {
+                live_in.insert(block.id, live);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break
+        }
+    }
+
+    function.body.iter().enumerate()
+        .map(|(index, block)| (block.id, out(&live_in, index)))
+        .collect()
+}
+
+
+/// The blocks a block can continue to, `next` is the block after it
+fn successors(operators: &[Operator], next: Option<BlockId>) -> Vec<BlockId> {
+    use OperatorKind::*;
+
+    let mut successors = vec![];
+    for operator in operators {
+        match operator.kind {
+            | Jif(_, yes, no)
+            | JNif(_, yes, no)
+            | JLtI(_, _, yes, no)
+            | JLtF(_, _, yes, no)
+            | JLeI(_, _, yes, no)
+            | JLeF(_, _, yes, no)
+            | JEqI(_, _, yes, no)
+            | JEqF(_, _, yes, no)
+            | JNeI(_, _, yes, no)
+            | JNeF(_, _, yes, no) => successors.extend([yes, no]),
+
+            | Jmp(v)
+            | IJif(_, v)
+            | IJNif(_, v) => successors.push(v),
+
+            _ => (),
+        }
+    }
+
+    let falls_through = !matches!(
+        operators.last().map(|x| &x.kind),
+        Some(Ret() | TailCall(..) | Jmp(_) | Jif(..) | JNif(..) | JLtI(..) | JLtF(..) | JLeI(..) | JLeF(..) | JEqI(..) | JEqF(..) | JNeI(..) | JNeF(..)),
+    );
+
+    if falls_through {
+        successors.extend(next);
+    }
+
+    successors
+}
+
+
+/// The registers an operator reads and the ones it writes
+fn registers(kind: &OperatorKind) -> (Vec<u8>, Vec<u8>) {
+    use OperatorKind::*;
+
+    let operand = |x: &Operand| match *x {
+        Operand::Reg(v) => Some(v),
+        Operand::Literal(_) => None,
+    };
+
+    match kind {
+        Ret() => (vec![0], vec![]),
+
+        | Push(_)
+        | Pop(_)
+        | Jmp(_) => (vec![], vec![]),
+
+        | Print(v)
+        | Jif(v, ..)
+        | JNif(v, ..)
+        | IJif(v, _)
+        | IJNif(v, _) => (vec![*v], vec![]),
+
+        Set(dst, _) => (vec![], vec![*dst]),
+
+        Swap(a, b) => (vec![*a, *b], vec![*a, *b]),
+
+        | Cpy(dst, src)
+        | StrLen(dst, src)
+        | ArrLen(dst, src)
+        | ArrPop(dst, src)
+        | Cast_IU(dst, src)
+        | Cast_IF(dst, src)
+        | Cast_UI(dst, src)
+        | Cast_UF(dst, src)
+        | Cast_FI(dst, src)
+        | Cast_FU(dst, src)
+        | NotI(dst, src)
+        | NotU(dst, src)
+        | NotB(dst, src)
+        | NegI(dst, src)
+        | NegF(dst, src)
+        | AddIK(dst, src, _)
+        | AddFK(dst, src, _)
+        | SubIK(dst, src, _)
+        | SubFK(dst, src, _)
+        | MulIK(dst, src, _)
+        | MulFK(dst, src, _)
+        | LtIK (dst, src, _)
+        | LtFK (dst, src, _)
+        | GtIK (dst, src, _)
+        | GtFK (dst, src, _)
+        | LeIK (dst, src, _)
+        | LeFK (dst, src, _)
+        | GeIK (dst, src, _)
+        | GeFK (dst, src, _)
+        | EqIK (dst, src, _)
+        | EqFK (dst, src, _)
+        | NeIK (dst, src, _)
+        | NeFK (dst, src, _) => (vec![*src], vec![*dst]),
+
+        | ArrPush(a, b)
+        | JLtI(a, b, ..)
+        | JLtF(a, b, ..)
+        | JLeI(a, b, ..)
+        | JLeF(a, b, ..)
+        | JEqI(a, b, ..)
+        | JEqF(a, b, ..)
+        | JNeI(a, b, ..)
+        | JNeF(a, b, ..) => (vec![*a, *b], vec![]),
+
+        ArrSet(arr, index, val) => (vec![*arr, *index, *val], vec![]),
+
+        | Call(dst, _, args)
+        | CallNative(dst, _, args) => (args.clone(), vec![*dst]),
+
+        TailCall(_, args) => (args.clone(), vec![]),
+
+        // the callee is free to change its arguments
+        CallW(dst, _, args) => (args.clone(), std::iter::once(*dst).chain(args.iter().copied()).collect()),
+
+        | AddI(dst, lhs, rhs)
+        | AddF(dst, lhs, rhs)
+        | SubI(dst, lhs, rhs)
+        | SubF(dst, lhs, rhs)
+        | MulI(dst, lhs, rhs)
+        | MulF(dst, lhs, rhs)
+        | LtI (dst, lhs, rhs)
+        | LtF (dst, lhs, rhs)
+        | GtI (dst, lhs, rhs)
+        | GtF (dst, lhs, rhs)
+        | LeI (dst, lhs, rhs)
+        | LeF (dst, lhs, rhs)
+        | GeI (dst, lhs, rhs)
+        | GeF (dst, lhs, rhs)
+        | EqI (dst, lhs, rhs)
+        | EqF (dst, lhs, rhs)
+        | NeI (dst, lhs, rhs)
+        | NeF (dst, lhs, rhs) => (std::iter::once(*lhs).chain(operand(rhs)).collect(), vec![*dst]),
+
+        | AddU(dst, lhs, rhs)
+        | SubU(dst, lhs, rhs)
+        | MulU(dst, lhs, rhs)
+        | DivI(dst, lhs, rhs)
+        | DivU(dst, lhs, rhs)
+        | DivF(dst, lhs, rhs)
+        | RemI(dst, lhs, rhs)
+        | RemU(dst, lhs, rhs)
+        | RemF(dst, lhs, rhs)
+        | LsI (dst, lhs, rhs)
+        | LsU (dst, lhs, rhs)
+        | RsI (dst, lhs, rhs)
+        | RsU (dst, lhs, rhs)
+        | AndI(dst, lhs, rhs)
+        | AndU(dst, lhs, rhs)
+        | OrI (dst, lhs, rhs)
+        | OrU (dst, lhs, rhs)
+        | XorI(dst, lhs, rhs)
+        | XorU(dst, lhs, rhs)
+        | AndB(dst, lhs, rhs)
+        | OrB (dst, lhs, rhs)
+        | AddI_C(dst, lhs, rhs)
+        | AddU_C(dst, lhs, rhs)
+        | SubI_C(dst, lhs, rhs)
+        | SubU_C(dst, lhs, rhs)
+        | MulI_C(dst, lhs, rhs)
+        | MulU_C(dst, lhs, rhs)
+        | LsI_C (dst, lhs, rhs)
+        | LsU_C (dst, lhs, rhs)
+        | RsI_C (dst, lhs, rhs)
+        | RsU_C (dst, lhs, rhs)
+        | LsI_W (dst, lhs, rhs)
+        | LsU_W (dst, lhs, rhs)
+        | RsI_W (dst, lhs, rhs)
+        | RsU_W (dst, lhs, rhs)
+        | AddI_S(dst, lhs, rhs)
+        | AddU_S(dst, lhs, rhs)
+        | SubI_S(dst, lhs, rhs)
+        | SubU_S(dst, lhs, rhs)
+        | MulI_S(dst, lhs, rhs)
+        | MulU_S(dst, lhs, rhs)
+        | LsI_S (dst, lhs, rhs)
+        | LsU_S (dst, lhs, rhs)
+        | RsI_S (dst, lhs, rhs)
+        | RsU_S (dst, lhs, rhs)
+        | LtU (dst, lhs, rhs)
+        | GtU (dst, lhs, rhs)
+        | LeU (dst, lhs, rhs)
+        | GeU (dst, lhs, rhs)
+        | EqU (dst, lhs, rhs)
+        | NeU (dst, lhs, rhs)
+        | StrCat(dst, lhs, rhs)
+        | StrEq (dst, lhs, rhs)
+        | StrCmp(dst, lhs, rhs)
+        | CharAt(dst, lhs, rhs)
+        | ArrNew(dst, lhs, rhs)
+        | ArrGet(dst, lhs, rhs) => (vec![*lhs, *rhs], vec![*dst]),
+
+        SubStr(dst, val, from, to) => (vec![*val, *from, *to], vec![*dst]),
+    }
+}
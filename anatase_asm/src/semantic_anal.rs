@@ -1,6 +1,6 @@
 use std::collections::HashSet;
 
-use crate::{errors::{Error, CompilerError, ErrorBuilder}, parser::Function, SymbolMap, SymbolIndex, Operator, OperatorKind};
+use crate::{errors::{Error, CompilerError, ErrorBuilder}, parser::Function, SymbolMap, SymbolIndex, Operator, OperatorKind, Operand, Literal};
 
 pub fn analyze(file: SymbolIndex, symbol_table: &mut SymbolMap, functions: &[Function]) -> Result<(), Error> {
     let main_ident = symbol_table.push("main".to_string());
@@ -34,7 +34,10 @@ pub fn analyze(file: SymbolIndex, symbol_table: &mut SymbolMap, functions: &[Fun
 
         for block in &f.body {
             for o in &block.operators {
-                if let OperatorKind::Call(_, name, ref args) = o.kind {
+                if let
+                    | OperatorKind::Call(_, name, ref args)
+                    | OperatorKind::CallW(_, name, ref args)
+                    | OperatorKind::TailCall(name, ref args) = o.kind {
                     let function = functions.iter().find(|x| x.name == name);
                     let function = match function {
                         Some(v) => v,
@@ -50,9 +53,82 @@ pub fn analyze(file: SymbolIndex, symbol_table: &mut SymbolMap, functions: &[Fun
                             .build())
                     }
                 }
+
+                if let OperatorKind::CallW(dst, _, ref args) = o.kind {
+                    let window = (dst as usize + 1..).take(args.len());
+                    if !args.iter().map(|x| *x as usize).eq(window) {
+                        return Err(CompilerError::new(file, "arguments aren't in the call window")
+                            .highlight(o.source_range)
+                                .note(format!("the arguments of a 'callw' have to be the registers right after @{dst}, in order"))
+                            .build())
+                    }
+                }
+
+                if let Some((literal, is_float)) = immediate(&o.kind) {
+                    let matches = match literal {
+                        Literal::Integer(_) => !is_float,
+                        Literal::Float(_) => is_float,
+                        _ => false,
+                    };
+
+                    if !matches {
+                        return Err(CompilerError::new(file, "immediate has the wrong type")
+                            .highlight(o.source_range)
+                                .note(format!("the operator expects {}", if is_float { "a float" } else { "an integer" }))
+                            .build())
+                    }
+                }
             }
         }
     }
 
     Ok(())
 }
+
+
+/// The literal operand of an operator and whether it has to be a float
+fn immediate(kind: &OperatorKind) -> Option<(Literal, bool)> {
+    use OperatorKind::*;
+
+    match *kind {
+        | AddI(_, _, Operand::Literal(v))
+        | SubI(_, _, Operand::Literal(v))
+        | MulI(_, _, Operand::Literal(v))
+        | LtI (_, _, Operand::Literal(v))
+        | GtI (_, _, Operand::Literal(v))
+        | LeI (_, _, Operand::Literal(v))
+        | GeI (_, _, Operand::Literal(v))
+        | EqI (_, _, Operand::Literal(v))
+        | NeI (_, _, Operand::Literal(v))
+        | AddIK(_, _, v)
+        | SubIK(_, _, v)
+        | MulIK(_, _, v)
+        | LtIK (_, _, v)
+        | GtIK (_, _, v)
+        | LeIK (_, _, v)
+        | GeIK (_, _, v)
+        | EqIK (_, _, v)
+        | NeIK (_, _, v) => Some((v, false)),
+
+        | AddF(_, _, Operand::Literal(v))
+        | SubF(_, _, Operand::Literal(v))
+        | MulF(_, _, Operand::Literal(v))
+        | LtF (_, _, Operand::Literal(v))
+        | GtF (_, _, Operand::Literal(v))
+        | LeF (_, _, Operand::Literal(v))
+        | GeF (_, _, Operand::Literal(v))
+        | EqF (_, _, Operand::Literal(v))
+        | NeF (_, _, Operand::Literal(v))
+        | AddFK(_, _, v)
+        | SubFK(_, _, v)
+        | MulFK(_, _, v)
+        | LtFK (_, _, v)
+        | GtFK (_, _, v)
+        | LeFK (_, _, v)
+        | GeFK (_, _, v)
+        | EqFK (_, _, v)
+        | NeFK (_, _, v) => Some((v, true)),
+
+        _ => None,
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use colored::{Color, Colorize};
+
+
+const LINE_COUNT : usize = 1;
+
+
+pub fn line_at_index(value: &str, index: usize) -> Option<(&str, usize)> {
+    let mut index_counter = 0;
+    for (i, line) in value.lines().enumerate() {
+        index_counter += line.chars().map(|x| x.len_utf8()).sum::<usize>();
+        index_counter += LINE_COUNT;
+
+        if index_counter > index {
+            return Some((line, i));
+        }
+    }
+
+    Some(("", value.lines().count()))
+}
+
+pub fn start_of_line(value: &str, line_number: usize) -> usize {
+    let mut counter = 0;
+
+    for (i, line) in value.lines().enumerate() {
+        if i == line_number {
+            break
+        }
+
+        counter += line.chars().map(|x| x.len_utf8()).sum::<usize>();
+        counter += LINE_COUNT;
+    }
+
+    counter
+}
+
+pub const ORANGE: Color = Color::TrueColor {
+    r: 255,
+    g: 160,
+    b: 100,
+};
+
+
+///
+/// Renders the lines of `source` covered by `start..=end` with the
+/// range underlined in `colour`, followed by an optional `note`
+///
+/// This is the snippet style used by both the assembler's compile
+/// errors and the runtime's backtraces
+///
+pub fn highlight(file_name: &str, source: &str, start: usize, end: usize, note: Option<&str>, colour: Color) -> String {
+    let mut string = String::new();
+
+    let start_line = line_at_index(source, start).unwrap().1;
+    let end_line   = line_at_index(source, end).unwrap().1;
+    let line_size  = end_line.to_string().len();
+
+
+    {
+        let _ = writeln!(string, "{}{} {}:{}:{}", " ".repeat(line_size), "-->".color(ORANGE), file_name, start_line, start - start_of_line(source, start_line));
+        let _ = write!(string, "{} {}", " ".repeat(line_size), "|".color(ORANGE));
+    }
+
+
+    for (line_number, line) in source.lines().enumerate().take(end_line + 1).skip(start_line) {
+        let _ = writeln!(string);
+
+        let _ = writeln!(string, "{:>w$} {} {}", line_number.to_string().color(ORANGE), "|".color(ORANGE), line, w = line_size);
+
+        if line_number == start_line {
+            let start_of_line = start_of_line(source, line_number);
+
+            let _ = write!(string, "{:>w$} {} ",
+                " ".repeat(line_number.to_string().len()),
+                "|".color(ORANGE),
+
+                w = line_size,
+            );
+
+            let _ = write!(string, "{}{}",
+                " ".repeat({
+                    let mut count = 0;
+                    for (index, i) in line.chars().enumerate() {
+                        if count >= start - start_of_line {
+                            count = index;
+                            break
+                        }
+                        count += i.len_utf8();
+                    }
+                    count
+                }),
+                "^".repeat({
+                    if end_line == line_number {
+                        (end-start) + 1
+                    } else {
+                        line.len() - (start - start_of_line) + 1
+                    }
+                }).color(colour),
+            );
+
+
+        } else if line_number == end_line {
+            let _ = write!(string, "{}",
+                "^".repeat({
+                    let start_of_end = start_of_line(source, end_line);
+                    end - start_of_end
+                }).color(colour),
+            );
+
+
+        } else {
+            let _ = write!(string, "{}",
+                "^".repeat(line.len()).color(colour),
+            );
+        }
+
+    }
+
+
+    if let Some(note) = note {
+        let _ = writeln!(string, " {note}");
+    } else {
+        let _ = writeln!(string);
+    }
+
+    string
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_range() {
+        colored::control::set_override(false);
+
+        let source = "fn main ~ 0 $entry\n    $entry\n        ret\n";
+        let start = source.find("ret").unwrap();
+
+        let result = highlight("test.an", source, start, start + 2, Some("here"), Color::BrightRed);
+
+        assert_eq!(result, " --> test.an:2:8\n  |\n2 |         ret\n  |         ^^^ here\n");
+    }
+}
@@ -79,10 +79,10 @@ impl Packed {
 
 
     pub fn from_bytes(data: &[u8]) -> Option<Packed> {
-        let data = &data[MAGIC_TEXT.as_bytes().len()..];
+        let data = data.strip_prefix(MAGIC_TEXT.as_bytes())?;
         let mut dec = ZlibDecoder::new(data);
         let mut data : Vec<u8> = Vec::new();
-        dec.read_to_end(&mut data).unwrap();
+        dec.read_to_end(&mut data).ok()?;
 
         let mut iterator = data.iter();
 